@@ -0,0 +1,11 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use verifier::az_snp_vtpm::fuzz;
+
+fuzz_target!(|data: &[u8]| fuzz::evidence(data));
@@ -0,0 +1,150 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use super::{AzSnpVtpm, CertError};
+use anyhow::{anyhow, Result};
+use log::warn;
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// What to do when a device presents an AK other than the pinned one
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AkChangePolicy {
+    /// Accept the evidence, but set `ak_changed` in the claim
+    Flag,
+    /// Reject the evidence
+    Reject,
+}
+
+/// The expected AK of each enrolled device. Devices are identified by the
+/// chip_id of their SNP report, AKs by the sha256 fingerprint of the AKpub.
+pub(crate) struct AkPins {
+    policy: AkChangePolicy,
+    pins: RwLock<HashMap<Vec<u8>, [u8; 32]>>,
+}
+
+impl AkPins {
+    pub(crate) fn new(policy: AkChangePolicy) -> Self {
+        Self {
+            policy,
+            pins: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub(crate) fn policy(&self) -> AkChangePolicy {
+        self.policy
+    }
+
+    /// Returns whether the AK of an enrolled device has changed, or `None`
+    /// if the device is not enrolled.
+    pub(crate) fn check(
+        &self,
+        chip_id: &[u8],
+        ak_fingerprint: &[u8; 32],
+    ) -> Result<Option<bool>, CertError> {
+        let pins = self
+            .pins
+            .read()
+            .map_err(|_| anyhow!("AK pins are poisoned"))?;
+        let Some(pinned) = pins.get(chip_id) else {
+            return Ok(None);
+        };
+
+        let changed = pinned != ak_fingerprint;
+        if changed {
+            warn!(
+                "AK of device {} changed from {} to {}",
+                hex::encode(chip_id),
+                hex::encode(pinned),
+                hex::encode(ak_fingerprint)
+            );
+            if self.policy == AkChangePolicy::Reject {
+                return Err(CertError::AkChanged);
+            }
+        }
+        Ok(Some(changed))
+    }
+}
+
+impl AzSnpVtpm {
+    /// Detect changes of the AK of enrolled devices, see `pin_ak`.
+    pub fn with_ak_pinning(mut self, policy: AkChangePolicy) -> Self {
+        self.ak_pins = Some(AkPins::new(policy));
+        self
+    }
+
+    /// Enroll the AK fingerprint (sha256 of the AKpub DER) expected from the
+    /// device with the given chip_id, replacing any previous enrollment.
+    pub fn pin_ak(&self, chip_id: &[u8], ak_fingerprint: [u8; 32]) -> Result<()> {
+        let Some(ak_pins) = &self.ak_pins else {
+            return Err(anyhow!("AK pinning is not enabled"));
+        };
+        ak_pins
+            .pins
+            .write()
+            .map_err(|_| anyhow!("AK pins are poisoned"))?
+            .insert(chip_id.to_vec(), ak_fingerprint);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::super::{ak_pub_der, AttestationReport};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use az_snp_vtpm::hcl::HclReport;
+    use serde_json::json;
+
+    fn fixture_identity() -> (Vec<u8>, [u8; 32]) {
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let fingerprint = openssl::sha::sha256(&ak_pub_der(&hcl_report).unwrap());
+        let snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        (snp_report.chip_id.to_vec(), fingerprint)
+    }
+
+    fn verify(verifier: &AzSnpVtpm) -> Result<serde_json::Value> {
+        verifier.verify_evidence(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_ak_pinning() {
+        let (chip_id, fingerprint) = fixture_identity();
+        let verifier = fixture_verifier().with_ak_pinning(AkChangePolicy::Flag);
+
+        // not enrolled yet
+        let claim = verify(&verifier).unwrap();
+        assert!(claim.get("ak_changed").is_none());
+
+        verifier.pin_ak(&chip_id, fingerprint).unwrap();
+        let claim = verify(&verifier).unwrap();
+        assert_eq!(claim["ak_changed"], json!(false));
+    }
+
+    #[test]
+    fn test_ak_change() {
+        let (chip_id, mut fingerprint) = fixture_identity();
+        fingerprint[0] ^= 1;
+
+        let verifier = fixture_verifier().with_ak_pinning(AkChangePolicy::Flag);
+        verifier.pin_ak(&chip_id, fingerprint).unwrap();
+        let claim = verify(&verifier).unwrap();
+        assert_eq!(claim["ak_changed"], json!(true));
+
+        let verifier = fixture_verifier().with_ak_pinning(AkChangePolicy::Reject);
+        verifier.pin_ak(&chip_id, fingerprint).unwrap();
+        let err = verify(&verifier).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::AkChanged)
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Verification of a batch of evidence in parallel on the rayon thread pool.
+
+use super::AzSnpVtpm;
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::Result;
+use rayon::prelude::*;
+
+impl AzSnpVtpm {
+    /// Verify each `(evidence, expected report_data, expected init_data
+    /// hash)` of `items` like `evaluate_blocking`, in parallel. The results
+    /// are in the order of `items`, and a failing item doesn't affect the
+    /// others.
+    pub fn verify_batch(
+        &self,
+        items: &[(Vec<u8>, ReportData, InitDataHash)],
+    ) -> Vec<Result<TeeEvidenceParsedClaim>> {
+        items
+            .par_iter()
+            .map(|(evidence, report_data, init_data_hash)| {
+                self.evaluate_blocking(evidence, report_data, init_data_hash)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+
+    #[test]
+    fn test_verify_batch() {
+        let good = || {
+            (
+                evidence_fixture(REPORT),
+                ReportData::Value(REPORT_DATA),
+                InitDataHash::NotProvided,
+            )
+        };
+        let items = vec![
+            good(),
+            (
+                evidence_fixture(REPORT),
+                ReportData::Value(b"other"),
+                InitDataHash::NotProvided,
+            ),
+            good(),
+            (
+                b"not evidence".to_vec(),
+                ReportData::Value(REPORT_DATA),
+                InitDataHash::NotProvided,
+            ),
+            good(),
+        ];
+
+        let results = fixture_verifier().verify_batch(&items);
+        let passed: Vec<bool> = results.iter().map(Result::is_ok).collect();
+        assert_eq!(passed, [true, false, true, false, true]);
+        assert_eq!(
+            results[0].as_ref().unwrap()["report_data"],
+            hex::encode(REPORT_DATA)
+        );
+        assert!(fixture_verifier().verify_batch(&[]).is_empty());
+    }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Builder of a configured `AzSnpVtpm`.
+//!
+//! The builder applies the options in the order they depend on each other,
+//! e.g. the cert chains of the generations before the VCEK CRL, which must
+//! be signed by one of them, and validates them once in `build`.
+
+use super::{AzSnpVtpm, CertError, CrlSource, SnpGeneration, PCR_COUNT};
+use anyhow::anyhow;
+use std::collections::HashMap;
+
+#[derive(Default)]
+pub struct AzSnpVtpmBuilder {
+    vendor_certs: Vec<(SnpGeneration, Vec<u8>)>,
+    vmpl: Option<u32>,
+    expected_pcrs: HashMap<usize, [u8; 32]>,
+    init_data_pcr: Option<usize>,
+    crl: Option<(CrlSource, bool)>,
+}
+
+impl AzSnpVtpmBuilder {
+    /// Verify the VCEKs of `generation` against the PEM bundle `pem` of its
+    /// ASK, ARK and ASVK, see `AzSnpVtpm::with_vendor_certs`
+    pub fn generation(mut self, generation: SnpGeneration, pem: &[u8]) -> Self {
+        self.vendor_certs.push((generation, pem.to_vec()));
+        self
+    }
+
+    /// See `AzSnpVtpm::with_vmpl`
+    pub fn expected_vmpl(mut self, vmpl: u32) -> Self {
+        self.vmpl = Some(vmpl);
+        self
+    }
+
+    /// See `AzSnpVtpm::with_expected_pcrs`
+    pub fn pcr_policy(mut self, expected_pcrs: HashMap<usize, [u8; 32]>) -> Self {
+        self.expected_pcrs = expected_pcrs;
+        self
+    }
+
+    /// See `AzSnpVtpm::with_init_data_pcr`
+    pub fn init_data_pcr(mut self, init_data_pcr: usize) -> Self {
+        self.init_data_pcr = Some(init_data_pcr);
+        self
+    }
+
+    /// See `AzSnpVtpm::with_vcek_crl`
+    pub fn crl(mut self, source: CrlSource, soft_fail: bool) -> Self {
+        self.crl = Some((source, soft_fail));
+        self
+    }
+
+    pub fn build(self) -> Result<AzSnpVtpm, CertError> {
+        if let Some(index) = self.init_data_pcr.filter(|index| *index >= PCR_COUNT) {
+            return Err(anyhow!("init_data PCR{index} is out of range").into());
+        }
+        if let Some(index) = self.expected_pcrs.keys().find(|index| **index >= PCR_COUNT) {
+            return Err(anyhow!("Expected PCR{index} is out of range").into());
+        }
+
+        let mut verifier = AzSnpVtpm::new()?.with_expected_pcrs(self.expected_pcrs);
+        for (generation, pem) in &self.vendor_certs {
+            verifier = verifier.with_vendor_certs(*generation, pem)?;
+        }
+        if let Some(vmpl) = self.vmpl {
+            verifier = verifier.with_vmpl(vmpl);
+        }
+        if let Some(init_data_pcr) = self.init_data_pcr {
+            verifier = verifier.with_init_data_pcr(init_data_pcr);
+        }
+        if let Some((source, soft_fail)) = self.crl {
+            verifier = verifier.with_vcek_crl(source, soft_fail);
+        }
+        Ok(verifier)
+    }
+}
+
+impl AzSnpVtpm {
+    pub fn builder() -> AzSnpVtpmBuilder {
+        AzSnpVtpmBuilder::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_clock, unrelated_chain, QUOTE, REPORT, REPORT_DATA,
+    };
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use az_snp_vtpm::vtpm::Quote;
+
+    #[test]
+    fn test_builder() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let pcr7 = *quote.pcrs_sha256().nth(7).unwrap();
+        let crl = include_bytes!("../../test_data/az-snp-vtpm/crl/clean.crl");
+
+        let verifier = AzSnpVtpm::builder()
+            .generation(SnpGeneration::Genoa, &unrelated_chain("Genoa"))
+            .expected_vmpl(1)
+            .pcr_policy(HashMap::from([(7, pcr7)]))
+            .init_data_pcr(9)
+            .crl(CrlSource::Bytes(crl.to_vec()), true)
+            .build()
+            .unwrap();
+        assert!(verifier.vendor_certs.contains_key(&SnpGeneration::Genoa));
+        assert_eq!(verifier.vmpl, 1);
+        assert_eq!(verifier.expected_pcrs[&7], pcr7);
+        assert_eq!(verifier.init_data_pcr, 9);
+        assert!(verifier.vcek_crl.is_some());
+
+        let claim = AzSnpVtpm::builder()
+            .pcr_policy(HashMap::from([(7, pcr7)]))
+            .init_data_pcr(9)
+            .build()
+            .unwrap()
+            .with_clock(fixture_clock())
+            .evaluate_blocking(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        assert_eq!(claim["init_data"], claim["tpm"]["pcr09"]);
+
+        let err = AzSnpVtpm::builder()
+            .expected_vmpl(1)
+            .build()
+            .unwrap()
+            .with_clock(fixture_clock())
+            .evaluate_blocking(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::VmplIncorrect { .. })
+        ));
+
+        AzSnpVtpm::builder()
+            .init_data_pcr(24)
+            .build()
+            .err()
+            .unwrap();
+        AzSnpVtpm::builder()
+            .pcr_policy(HashMap::from([(24, pcr7)]))
+            .build()
+            .err()
+            .unwrap();
+        AzSnpVtpm::builder()
+            .generation(SnpGeneration::Turin, b"")
+            .build()
+            .err()
+            .unwrap();
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Policies managed centrally and distributed as signed bundles.
+//!
+//! A signed bundle is a JSON envelope `{"bundle": ..., "signature": ...}`
+//! of the base64 encoded JSON `PolicyBundle` and its base64 encoded
+//! signature, ECDSA P-256 with SHA-256 (DER) or Ed25519, by the policy
+//! signing key. Applying a bundle replaces the TCB floor, microcode and ABI
+//! policies and the reference values of the verifier. A bundle is only
+//! enforced until it expires, after which evidence is rejected until a
+//! current bundle is applied.
+
+use super::{
+    AbiVersion, AzSnpVtpm, CertError, MicrocodePolicy, ReferenceValues, RequiredClaim, Tcb,
+};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize)]
+struct SignedPolicyBundle {
+    bundle: String,
+    signature: String,
+}
+
+/// Policy distributed in a signed bundle
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyBundle {
+    /// Version of the bundle, a bundle older than the applied one is
+    /// rejected
+    pub version: u64,
+    /// Expiry of the bundle, in seconds since the epoch
+    pub expires_at: u64,
+    #[serde(default)]
+    pub min_tcb: Option<Tcb>,
+    #[serde(default)]
+    pub microcode_policy: Option<MicrocodePolicy>,
+    #[serde(default)]
+    pub min_abi: Option<AbiVersion>,
+    /// Acceptable launch measurements, hex encoded
+    #[serde(default)]
+    pub measurements: Vec<String>,
+    /// Acceptable values of the PCRs by index, hex encoded
+    #[serde(default)]
+    pub pcrs: BTreeMap<usize, Vec<String>>,
+}
+
+impl PolicyBundle {
+    fn expiry(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.expires_at)
+    }
+
+    fn reference_values(&self) -> Result<ReferenceValues> {
+        let mut reference_values = ReferenceValues::new();
+        for measurement in &self.measurements {
+            reference_values = reference_values.allow(
+                RequiredClaim::Measurement,
+                hex::decode(measurement).context("Malformed measurement in policy bundle")?,
+            );
+        }
+        for (index, values) in &self.pcrs {
+            for value in values {
+                reference_values = reference_values.allow(
+                    RequiredClaim::Pcr(*index),
+                    hex::decode(value).context("Malformed PCR value in policy bundle")?,
+                );
+            }
+        }
+        Ok(reference_values)
+    }
+}
+
+/// Version and expiry of the applied bundle
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct AppliedBundle {
+    pub(crate) version: u64,
+    expiry: SystemTime,
+}
+
+impl AzSnpVtpm {
+    /// Accept policy bundles signed with the public key `pem`, either P-256
+    /// or Ed25519.
+    pub fn with_policy_signing_key(mut self, pem: &[u8]) -> Result<Self> {
+        let key = PKey::public_key_from_pem(pem).context("Failed to parse policy signing key")?;
+        match key.id() {
+            Id::EC if key.ec_key()?.group().curve_name() == Some(Nid::X9_62_PRIME256V1) => {}
+            Id::ED25519 => {}
+            _ => bail!("Policy signing key must be a P-256 or Ed25519 key"),
+        }
+        self.policy_signing_key = Some(key);
+        Ok(self)
+    }
+
+    /// Verify the signed policy bundle `signed` and apply it, replacing the
+    /// TCB floor, microcode and ABI policies and the reference values.
+    /// Tampered and expired bundles and bundles older than the applied one
+    /// are rejected.
+    pub fn with_policy_bundle(mut self, signed: &[u8]) -> Result<Self> {
+        let Some(key) = &self.policy_signing_key else {
+            bail!("No policy signing key configured");
+        };
+        let signed: SignedPolicyBundle =
+            serde_json::from_slice(signed).context("Failed to deserialize signed policy bundle")?;
+        let payload = STANDARD
+            .decode(&signed.bundle)
+            .context("Malformed policy bundle")?;
+        let signature = STANDARD
+            .decode(&signed.signature)
+            .context("Malformed policy bundle signature")?;
+        if !verify_bundle_signature(key, &payload, &signature)? {
+            return Err(CertError::PolicyBundleSignature.into());
+        }
+
+        let bundle: PolicyBundle =
+            serde_json::from_slice(&payload).context("Failed to deserialize policy bundle")?;
+        if self.now() >= bundle.expiry() {
+            return Err(CertError::PolicyBundleExpired(bundle.version).into());
+        }
+        if let Some(applied) = &self.policy_bundle {
+            if bundle.version < applied.version {
+                return Err(CertError::PolicyBundleRollback {
+                    applied: applied.version,
+                    version: bundle.version,
+                }
+                .into());
+            }
+        }
+
+        self.reference_values = bundle.reference_values()?;
+        self.min_tcb = bundle.min_tcb;
+        self.microcode_policy = bundle.microcode_policy.clone();
+        self.min_abi = bundle.min_abi;
+        self.policy_bundle = Some(AppliedBundle {
+            version: bundle.version,
+            expiry: bundle.expiry(),
+        });
+        Ok(self)
+    }
+
+    /// Reject evidence once the applied policy bundle has expired
+    pub(crate) fn verify_policy_bundle(&self) -> Result<(), CertError> {
+        match &self.policy_bundle {
+            Some(applied) if self.now() >= applied.expiry => {
+                Err(CertError::PolicyBundleExpired(applied.version))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn verify_bundle_signature(key: &PKey<Public>, payload: &[u8], signature: &[u8]) -> Result<bool> {
+    let mut verifier = match key.id() {
+        Id::ED25519 => Verifier::new_without_digest(key)?,
+        _ => Verifier::new(MessageDigest::sha256(), key)?,
+    };
+    // a malformed DER signature is an error for some backends, a mismatch
+    // for others
+    Ok(verifier.verify_oneshot(signature, payload).unwrap_or(false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::super::FixedClock;
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+    use std::sync::Arc;
+
+    const NOW: u64 = 1_800_000_000;
+
+    fn signing_key() -> (PKey<Private>, Vec<u8>) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let pem = key.public_key_to_pem().unwrap();
+        (key, pem)
+    }
+
+    fn sign(key: &PKey<Private>, payload: &[u8]) -> Vec<u8> {
+        let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+        let signature = signer.sign_oneshot_to_vec(payload).unwrap();
+        serde_json::to_vec(&SignedPolicyBundle {
+            bundle: STANDARD.encode(payload),
+            signature: STANDARD.encode(signature),
+        })
+        .unwrap()
+    }
+
+    fn verifier(pem: &[u8]) -> (AzSnpVtpm, Arc<FixedClock>) {
+        let clock = Arc::new(FixedClock::new(UNIX_EPOCH + Duration::from_secs(NOW)));
+        let verifier = AzSnpVtpm::new()
+            .unwrap()
+            .with_clock(clock.clone())
+            .with_policy_signing_key(pem)
+            .unwrap();
+        (verifier, clock)
+    }
+
+    fn verify(verifier: &AzSnpVtpm) -> Result<crate::TeeEvidenceParsedClaim> {
+        verifier.verify_evidence(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            None,
+        )
+    }
+
+    fn fixture_measurement() -> String {
+        let claim = verify(&fixture_verifier()).unwrap();
+        claim["measurement"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_valid_policy_bundle() {
+        let (key, pem) = signing_key();
+        let (verifier, _) = verifier(&pem);
+        let bundle = PolicyBundle {
+            version: 2,
+            expires_at: NOW + 60,
+            min_abi: Some(AbiVersion {
+                major: 0,
+                minor: 31,
+            }),
+            measurements: vec![fixture_measurement()],
+            ..Default::default()
+        };
+        let verifier = verifier
+            .with_policy_bundle(&sign(&key, &serde_json::to_vec(&bundle).unwrap()))
+            .unwrap();
+        verify(&verifier).unwrap();
+
+        // a newer bundle with another measurement replaces the first one
+        let bundle = PolicyBundle {
+            version: 3,
+            measurements: vec!["00".repeat(48)],
+            ..bundle
+        };
+        let verifier = verifier
+            .with_policy_bundle(&sign(&key, &serde_json::to_vec(&bundle).unwrap()))
+            .unwrap();
+        let err = verify(&verifier).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::ReferenceValueMismatch(
+                RequiredClaim::Measurement
+            ))
+        ));
+
+        // rollback to an older bundle
+        let bundle = PolicyBundle {
+            version: 1,
+            ..bundle
+        };
+        let err = verifier
+            .with_policy_bundle(&sign(&key, &serde_json::to_vec(&bundle).unwrap()))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::PolicyBundleRollback {
+                applied: 3,
+                version: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn test_tampered_policy_bundle() {
+        let (key, pem) = signing_key();
+        let bundle = PolicyBundle {
+            version: 1,
+            expires_at: NOW + 60,
+            measurements: vec![fixture_measurement()],
+            ..Default::default()
+        };
+        let signed = sign(&key, &serde_json::to_vec(&bundle).unwrap());
+
+        let mut tampered: SignedPolicyBundle = serde_json::from_slice(&signed).unwrap();
+        let relaxed = PolicyBundle {
+            measurements: vec![],
+            ..bundle
+        };
+        tampered.bundle = STANDARD.encode(serde_json::to_vec(&relaxed).unwrap());
+        let err = verifier(&pem)
+            .0
+            .with_policy_bundle(&serde_json::to_vec(&tampered).unwrap())
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::PolicyBundleSignature)
+        ));
+
+        // signed by another key
+        let (_, other_pem) = signing_key();
+        verifier(&other_pem)
+            .0
+            .with_policy_bundle(&signed)
+            .err()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_expired_policy_bundle() {
+        let (key, pem) = signing_key();
+        let bundle = PolicyBundle {
+            version: 1,
+            expires_at: NOW,
+            ..Default::default()
+        };
+        let err = verifier(&pem)
+            .0
+            .with_policy_bundle(&sign(&key, &serde_json::to_vec(&bundle).unwrap()))
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::PolicyBundleExpired(1))
+        ));
+
+        // a bundle expiring after it has been applied
+        let bundle = PolicyBundle {
+            expires_at: NOW + 60,
+            ..bundle
+        };
+        let (verifier, clock) = verifier(&pem);
+        let verifier = verifier
+            .with_policy_bundle(&sign(&key, &serde_json::to_vec(&bundle).unwrap()))
+            .unwrap();
+        verify(&verifier).unwrap();
+        clock.advance(Duration::from_secs(60));
+        let err = verify(&verifier).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::PolicyBundleExpired(1))
+        ));
+    }
+}
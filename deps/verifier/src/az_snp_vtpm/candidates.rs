@@ -0,0 +1,79 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Verification against any of several candidate report_data values, e.g.
+//! the current and the previous nonce during a key rotation.
+//!
+//! The candidate is picked by the quote nonce, then the evidence is verified
+//! against it like against a single expected report_data.
+
+use super::{claim_map, AzSnpVtpm, CertError, Evidence, ReportDataBinding};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{bail, Result};
+
+impl AzSnpVtpm {
+    /// Verify `evidence` like `evaluate`, expecting any of `candidates` as
+    /// report_data. The index of the matching candidate is added to the claim
+    /// as `report_data_candidate`. Only the quote nonce binding of the
+    /// report_data is supported.
+    pub fn evaluate_with_report_data_candidates(
+        &self,
+        evidence: &[u8],
+        candidates: &[&[u8]],
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        if self.report_data_binding != ReportDataBinding::QuoteNonce {
+            bail!("report_data candidates require the quote nonce binding");
+        }
+        let parsed = Evidence::from_slice(evidence)?;
+        let nonce = parsed.quote.nonce()?;
+        let index = candidates
+            .iter()
+            .position(|candidate| *candidate == nonce.as_slice())
+            .ok_or(CertError::NonceMismatch)?;
+
+        let mut claim = self.verify_evidence(
+            evidence,
+            &ReportData::Value(candidates[index]),
+            expected_init_data_hash,
+            None,
+        )?;
+        claim_map(&mut claim)?.insert("report_data_candidate".into(), index.into());
+        Ok(claim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+
+    fn evaluate(candidates: &[&[u8]]) -> Result<TeeEvidenceParsedClaim> {
+        fixture_verifier().evaluate_with_report_data_candidates(
+            &evidence_fixture(REPORT),
+            candidates,
+            &InitDataHash::NotProvided,
+        )
+    }
+
+    #[test]
+    fn test_report_data_candidates() {
+        let claim = evaluate(&[b"previous challenge", REPORT_DATA]).unwrap();
+        assert_eq!(claim["report_data_candidate"], 1);
+        assert_eq!(claim["report_data"], hex::encode(REPORT_DATA));
+
+        let claim = evaluate(&[REPORT_DATA]).unwrap();
+        assert_eq!(claim["report_data_candidate"], 0);
+
+        let no_match: [&[&[u8]]; 2] = [&[b"previous challenge", b"egnellahc"], &[]];
+        for candidates in no_match {
+            let err = evaluate(candidates).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::NonceMismatch)
+            ));
+        }
+    }
+}
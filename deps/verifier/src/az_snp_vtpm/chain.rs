@@ -0,0 +1,124 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Hash-chained quotes for tamper-evident continuous attestation.
+//!
+//! The agent keeps a running digest of its quotes, starting from
+//! `CHAIN_GENESIS`. The nonce of each quote is
+//! `sha256(running digest || report_data)`, and after each quote the
+//! running digest becomes `sha256(running digest || sha256(quote message))`.
+//! A verifier which keeps the running digest of the quotes it has verified
+//! rejects a quote following a dropped or reordered one.
+
+use super::{claim_map, verify_nonce, vtpm_error, AzSnpVtpm, Evidence};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{bail, Result};
+use az_snp_vtpm::vtpm::Quote;
+use openssl::sha::{sha256, Sha256};
+use serde_json::Value;
+
+/// Running digest before the first quote of a chain
+pub const CHAIN_GENESIS: [u8; 32] = [0; 32];
+
+/// The nonce of the quote following `prior_digest` for `report_data`
+pub fn chained_nonce(prior_digest: &[u8; 32], report_data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prior_digest);
+    hasher.update(report_data);
+    hasher.finish()
+}
+
+/// The running digest after `quote`
+pub fn chain_digest(prior_digest: &[u8; 32], quote: &Quote) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(prior_digest);
+    hasher.update(&sha256(&quote.message()));
+    hasher.finish()
+}
+
+/// Verify that the nonce of `quote` follows `prior_digest` for
+/// `report_data`, returning the updated running digest
+pub fn verify_chain_link(
+    quote: &Quote,
+    prior_digest: &[u8; 32],
+    report_data: &[u8],
+) -> Result<[u8; 32]> {
+    verify_nonce(quote, &chained_nonce(prior_digest, report_data)).map_err(vtpm_error)?;
+    Ok(chain_digest(prior_digest, quote))
+}
+
+impl AzSnpVtpm {
+    /// Verify `evidence` like `evaluate`, expecting its quote to follow
+    /// `prior_digest` in a hash chain for `expected_report_data`. The
+    /// updated running digest is added to the claim as `quote_chain_digest`.
+    pub fn evaluate_chained(
+        &self,
+        evidence: &[u8],
+        prior_digest: &[u8; 32],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let Some(expected_report_data) = expected_report_data.value()? else {
+            bail!("unexpected empty report data");
+        };
+
+        let evidence = Evidence::from_slice(evidence)?;
+        let digest = chain_digest(prior_digest, &evidence.quote);
+        let nonce = chained_nonce(prior_digest, expected_report_data);
+
+        let mut claim = self.verify_parsed_evidence(
+            evidence,
+            &ReportData::Value(&nonce),
+            expected_init_data_hash,
+            None,
+            None,
+        )?;
+        claim_map(&mut claim)?.insert(
+            "quote_chain_digest".into(),
+            Value::String(hex::encode(digest)),
+        );
+        Ok(claim)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_verifier, quote_with_nonce, REPORT, REPORT_DATA,
+    };
+    use super::super::CertError;
+    use super::*;
+
+    #[test]
+    fn test_verify_chain() {
+        let first = quote_with_nonce(&chained_nonce(&CHAIN_GENESIS, b"heartbeat 1"));
+        let digest = verify_chain_link(&first, &CHAIN_GENESIS, b"heartbeat 1").unwrap();
+        assert_eq!(digest, chain_digest(&CHAIN_GENESIS, &first));
+
+        let second = quote_with_nonce(&chained_nonce(&digest, b"heartbeat 2"));
+        let digest = verify_chain_link(&second, &digest, b"heartbeat 2").unwrap();
+        assert_ne!(digest, chain_digest(&CHAIN_GENESIS, &first));
+
+        // the first quote has been dropped
+        let err = verify_chain_link(&second, &CHAIN_GENESIS, b"heartbeat 2").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::NonceMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_evaluate_chained() {
+        // the nonce of the fixture quote is not chained
+        fixture_verifier()
+            .evaluate_chained(
+                &evidence_fixture(REPORT),
+                &CHAIN_GENESIS,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Quote nonces derived from the secret of an attested secure channel.
+//!
+//! The nonce is HKDF-SHA256 (RFC 5869) of the channel secret: extract with
+//! `salt`, then expand with `info` to `len` bytes. Both sides of the channel
+//! derive it independently, binding the attestation to the channel without
+//! a separate challenge.
+
+use super::{verify_nonce, vtpm_error, AzSnpVtpm};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{bail, Result};
+use az_snp_vtpm::vtpm::Quote;
+use openssl::md::Md;
+use openssl::pkey::Id;
+use openssl::pkey_ctx::PkeyCtx;
+
+/// Largest nonce a vTPM quote carries (TPM2B_DATA of a sha512 digest)
+const MAX_NONCE_LEN: usize = 64;
+
+/// Inputs of the HKDF derivation of a quote nonce
+pub struct HkdfNonce<'a> {
+    /// Input keying material, the channel secret
+    pub secret: &'a [u8],
+    pub salt: &'a [u8],
+    pub info: &'a [u8],
+    /// Length of the nonce carried by the quote
+    pub len: usize,
+}
+
+impl HkdfNonce<'_> {
+    /// Derive the expected quote nonce
+    pub fn derive(&self) -> Result<Vec<u8>> {
+        if self.len == 0 || self.len > MAX_NONCE_LEN {
+            bail!("Quote nonce length must be between 1 and {MAX_NONCE_LEN}");
+        }
+
+        let mut ctx = PkeyCtx::new_id(Id::HKDF)?;
+        ctx.derive_init()?;
+        ctx.set_hkdf_md(Md::sha256())?;
+        ctx.set_hkdf_key(self.secret)?;
+        ctx.set_hkdf_salt(self.salt)?;
+        ctx.add_hkdf_info(self.info)?;
+        let mut nonce = vec![0u8; self.len];
+        ctx.derive(Some(&mut nonce))?;
+        Ok(nonce)
+    }
+}
+
+/// Verify that the nonce of `quote` is the one derived from `hkdf`
+pub fn verify_hkdf_nonce(quote: &Quote, hkdf: &HkdfNonce) -> Result<()> {
+    verify_nonce(quote, &hkdf.derive()?).map_err(vtpm_error)?;
+    Ok(())
+}
+
+impl AzSnpVtpm {
+    /// Verify `evidence` like `evaluate`, expecting the quote nonce derived
+    /// from `hkdf` as report_data.
+    pub fn evaluate_with_hkdf_nonce(
+        &self,
+        evidence: &[u8],
+        hkdf: &HkdfNonce,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let nonce = hkdf.derive()?;
+        self.verify_evidence(
+            evidence,
+            &ReportData::Value(&nonce),
+            expected_init_data_hash,
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::quote_with_nonce;
+    use super::super::CertError;
+    use super::*;
+
+    #[test]
+    fn test_hkdf_nonce() {
+        // RFC 5869, A.1
+        let hkdf = HkdfNonce {
+            secret: &[0x0b; 22],
+            salt: &hex::decode("000102030405060708090a0b0c").unwrap(),
+            info: &hex::decode("f0f1f2f3f4f5f6f7f8f9").unwrap(),
+            len: 42,
+        };
+        assert_eq!(
+            hex::encode(hkdf.derive().unwrap()),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+
+        HkdfNonce { len: 0, ..hkdf }.derive().unwrap_err();
+    }
+
+    #[test]
+    fn test_verify_hkdf_nonce() {
+        let hkdf = HkdfNonce {
+            secret: b"channel secret",
+            salt: b"salt",
+            info: b"az-snp-vtpm quote nonce",
+            len: 32,
+        };
+        let quote = quote_with_nonce(&hkdf.derive().unwrap());
+        verify_hkdf_nonce(&quote, &hkdf).unwrap();
+
+        let other = HkdfNonce {
+            info: b"another channel",
+            ..hkdf
+        };
+        let err = verify_hkdf_nonce(&quote, &other).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::NonceMismatch)
+        ));
+    }
+}
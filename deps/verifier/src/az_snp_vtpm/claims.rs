@@ -0,0 +1,157 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Typed form of the claim of `AzSnpVtpm`, for in-process callers. It is
+//! serialized to the same JSON layout as the `TeeEvidenceParsedClaim`.
+
+use super::{AzSnpVtpm, Tcb};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AzSnpVtpmClaims {
+    /// SHA-256 PCRs of the quote, by `pcrNN` name
+    pub tpm: BTreeMap<String, String>,
+    /// Value of the init_data PCR
+    pub init_data: String,
+    pub report_data: String,
+    pub reported_tcb: Tcb,
+    pub committed_tcb: Tcb,
+    pub current_tcb: Tcb,
+    pub launch_tcb: Tcb,
+    pub vcek_tcb: Tcb,
+    pub microcode_svn: u8,
+    pub chip_id: String,
+    pub ak_fingerprint: String,
+    pub ak_pub: AkPub,
+    /// The other claims of the SNP report and of the optional checks
+    #[serde(flatten)]
+    pub snp: Map<String, Value>,
+}
+
+/// The attested AKpub, for binding application keys or channels to it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AkPub {
+    /// Base64 of the DER encoded SubjectPublicKeyInfo
+    pub der: String,
+    /// Hex of the SHA-256 thumbprint of `der`
+    pub sha256: String,
+}
+
+impl TryFrom<TeeEvidenceParsedClaim> for AzSnpVtpmClaims {
+    type Error = anyhow::Error;
+
+    fn try_from(claim: TeeEvidenceParsedClaim) -> Result<Self> {
+        serde_json::from_value(claim).context("Malformed Azure vTPM SEV-SNP claim")
+    }
+}
+
+impl TryFrom<AzSnpVtpmClaims> for TeeEvidenceParsedClaim {
+    type Error = anyhow::Error;
+
+    fn try_from(claims: AzSnpVtpmClaims) -> Result<Self> {
+        serde_json::to_value(claims).context("Failed to serialize Azure vTPM SEV-SNP claims")
+    }
+}
+
+impl AzSnpVtpm {
+    /// Same as `evaluate`, returning the typed claims
+    pub fn evaluate_claims(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<AzSnpVtpmClaims> {
+        self.verify_evidence(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            None,
+        )?
+        .try_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::super::{ak_pub_der, HclReport};
+    use super::*;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::json;
+
+    fn claims() -> AzSnpVtpmClaims {
+        fixture_verifier()
+            .evaluate_claims(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_typed_claims() {
+        let claims = claims();
+        let tcb = Tcb {
+            bootloader: 3,
+            tee: 0,
+            snp: 8,
+            microcode: 115,
+        };
+        assert_eq!(claims.reported_tcb, tcb);
+        assert_eq!(claims.committed_tcb, tcb);
+        assert_eq!(claims.launch_tcb, tcb);
+        assert_eq!(
+            claims.current_tcb,
+            Tcb {
+                microcode: 210,
+                ..tcb
+            }
+        );
+        assert_eq!(claims.microcode_svn, 115);
+        assert_eq!(claims.tpm.len(), 24);
+        assert_eq!(claims.init_data, claims.tpm["pcr08"]);
+        assert_eq!(claims.report_data, hex::encode(REPORT_DATA));
+        assert!(claims.snp.contains_key("measurement"));
+    }
+
+    #[test]
+    fn test_ak_pub_claim() {
+        let claims = claims();
+        let ak_pub = ak_pub_der(&HclReport::new(REPORT.to_vec()).unwrap()).unwrap();
+        assert_eq!(STANDARD.decode(&claims.ak_pub.der).unwrap(), ak_pub);
+        assert_eq!(
+            claims.ak_pub.sha256,
+            hex::encode(openssl::sha::sha256(&ak_pub))
+        );
+        assert_eq!(claims.ak_pub.sha256, claims.ak_fingerprint);
+    }
+
+    #[test]
+    fn test_claims_round_trip() {
+        let claim = fixture_verifier()
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        let typed = AzSnpVtpmClaims::try_from(claim.clone()).unwrap();
+        assert_eq!(typed, claims());
+
+        let json = TeeEvidenceParsedClaim::try_from(typed.clone()).unwrap();
+        assert_eq!(json, claim);
+        assert_eq!(json["tpm"]["pcr08"], json!(typed.init_data));
+        let parsed: AzSnpVtpmClaims = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, typed);
+
+        AzSnpVtpmClaims::try_from(json!({"tpm": {}})).unwrap_err();
+    }
+}
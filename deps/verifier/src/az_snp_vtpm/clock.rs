@@ -0,0 +1,77 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time of the time-based checks
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system clock, used by default
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The time the evidence is verified at, e.g. a fixed time in the past to
+/// accept historical evidence whose VCEK has expired since
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerificationTime {
+    /// The system time at each verification
+    #[default]
+    SystemNow,
+    /// This very time, whenever the evidence is verified
+    Fixed(SystemTime),
+}
+
+impl Clock for VerificationTime {
+    fn now(&self) -> SystemTime {
+        match self {
+            Self::SystemNow => SystemTime::now(),
+            Self::Fixed(time) => *time,
+        }
+    }
+}
+
+/// A clock which only moves when told to, for deterministic tests
+#[derive(Debug)]
+pub struct FixedClock {
+    now: Mutex<SystemTime>,
+}
+
+impl FixedClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(now),
+        }
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) = now;
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += duration;
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Whether something which happened at `since` is younger than `ttl` at
+/// `now`. A `since` in the future, e.g. after the clock has been set back,
+/// is not fresh.
+pub(crate) fn is_fresh(now: SystemTime, since: SystemTime, ttl: Duration) -> bool {
+    now.duration_since(since).is_ok_and(|age| age < ttl)
+}
@@ -0,0 +1,88 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Unwrapping of HCL reports carried in a CMS SignedData envelope.
+//!
+//! The envelope only provides transport integrity, it is verified against a
+//! configured transport certificate and is unrelated to the SNP attestation
+//! of the inner report.
+
+use anyhow::{Context, Result};
+use openssl::cms::{CMSOptions, CmsContentInfo};
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::X509;
+
+/// Verify the CMS SignedData `envelope` (DER) against `transport_cert` and
+/// return the encapsulated HCL report.
+pub(crate) fn unwrap_signed_report(envelope: &[u8], transport_cert: &X509) -> Result<Vec<u8>> {
+    let mut cms = CmsContentInfo::from_der(envelope).context("Malformed CMS envelope")?;
+
+    let mut store = X509StoreBuilder::new()?;
+    store.add_cert(transport_cert.clone())?;
+    let store = store.build();
+
+    let mut report = Vec::new();
+    cms.verify(
+        None,
+        Some(&store),
+        None,
+        Some(&mut report),
+        CMSOptions::BINARY,
+    )
+    .context("CMS envelope is not signed by the transport certificate")?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+
+    const ENVELOPE: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/hcl-report.cms");
+    const BAD_ENVELOPE: &[u8] =
+        include_bytes!("../../test_data/az-snp-vtpm/hcl-report-bad-transport.cms");
+    const TRANSPORT_CERT: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/transport.pem");
+
+    #[test]
+    fn test_unwrap_signed_report() {
+        let cert = X509::from_pem(TRANSPORT_CERT).unwrap();
+        let report = unwrap_signed_report(ENVELOPE, &cert).unwrap();
+        assert_eq!(report, REPORT);
+    }
+
+    #[test]
+    fn test_unwrap_signed_report_failure() {
+        let cert = X509::from_pem(TRANSPORT_CERT).unwrap();
+        assert_eq!(
+            unwrap_signed_report(BAD_ENVELOPE, &cert)
+                .unwrap_err()
+                .to_string(),
+            "CMS envelope is not signed by the transport certificate"
+        );
+    }
+
+    #[test]
+    fn test_verify_cms_wrapped_evidence() {
+        let cert = X509::from_pem(TRANSPORT_CERT).unwrap();
+        let verifier = fixture_verifier().with_cms_transport_cert(cert);
+        verifier
+            .verify_evidence(
+                &evidence_fixture(ENVELOPE),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        verifier
+            .verify_evidence(
+                &evidence_fixture(BAD_ENVELOPE),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Revocation check of the VCEK against a CRL.
+//!
+//! The CRL is given as bytes, read from a file or fetched from a URL, e.g.
+//! the KDS, must be signed by the ARK or ASK and is cached until its
+//! nextUpdate. Bytes and files are reloaded on demand, a URL is only fetched
+//! by `refresh_vcek_crl`, which `evaluate` calls before verifying evidence.
+//! A revoked VCEK is always rejected, an unavailable or outdated CRL only if
+//! the check doesn't soft-fail.
+
+use super::{asn1_time, AzSnpVtpm, CertError, SnpGeneration};
+use anyhow::{anyhow, bail, Context, Result};
+use log::{debug, warn};
+use openssl::asn1::Asn1TimeRef;
+use openssl::x509::{CrlStatus, X509Crl, X509Ref, X509};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+const KDS_VCEK: &str = "https://kdsintf.amd.com/vcek/v1";
+
+/// Where the VCEK CRL is read from, in DER or PEM
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CrlSource {
+    Bytes(Vec<u8>),
+    File(PathBuf),
+    Url(String),
+}
+
+impl CrlSource {
+    /// The CRL of the VCEKs of `generation` on the AMD KDS
+    pub fn kds(generation: SnpGeneration) -> Self {
+        Self::Url(format!("{KDS_VCEK}/{generation}/crl"))
+    }
+}
+
+pub(crate) struct VcekCrl {
+    pub(crate) source: CrlSource,
+    pub(crate) soft_fail: bool,
+    issuers: Vec<X509>,
+    crl: Mutex<Option<X509Crl>>,
+}
+
+impl VcekCrl {
+    pub(crate) fn new(source: CrlSource, soft_fail: bool, issuers: Vec<X509>) -> Self {
+        Self {
+            source,
+            soft_fail,
+            issuers,
+            crl: Mutex::new(None),
+        }
+    }
+
+    /// Fetch the CRL, unless the cached one is current
+    async fn refresh(&self, now: SystemTime) -> Result<()> {
+        if self
+            .lock_crl()?
+            .as_ref()
+            .is_some_and(|crl| is_current(crl, now).unwrap_or(false))
+        {
+            return Ok(());
+        }
+        let crl = match &self.source {
+            CrlSource::Url(url) => reqwest::get(url)
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?
+                .to_vec(),
+            _ => self.read_local()?,
+        };
+        let crl = self.parse(&crl, now)?;
+        *self.lock_crl()? = Some(crl);
+        Ok(())
+    }
+
+    /// Reject `vcek` if the CRL lists its serial
+    pub(crate) fn check(&self, vcek: &X509Ref, now: SystemTime) -> Result<(), CertError> {
+        let serial = vcek
+            .serial_number()
+            .to_bn()
+            .context("Failed to read VCEK serial")?;
+        let revoked = match self.is_revoked(vcek, now) {
+            Ok(revoked) => revoked,
+            Err(e) if self.soft_fail => {
+                warn!("Skipping the VCEK revocation check: {e:#}");
+                return Ok(());
+            }
+            Err(e) => return Err(CertError::CrlUnavailable(format!("{e:#}"))),
+        };
+        if revoked {
+            let serial = serial
+                .to_hex_str()
+                .context("Failed to encode VCEK serial")?;
+            return Err(CertError::VcekRevoked(serial.to_string()));
+        }
+        debug!("VCEK revocation check completed successfully");
+        Ok(())
+    }
+
+    fn is_revoked(&self, vcek: &X509Ref, now: SystemTime) -> Result<bool> {
+        let mut cached = self.lock_crl()?;
+        if !cached
+            .as_ref()
+            .is_some_and(|crl| is_current(crl, now).unwrap_or(false))
+        {
+            *cached = Some(self.parse(&self.read_local()?, now)?);
+        }
+        let crl = cached.as_ref().ok_or_else(|| anyhow!("No CRL"))?;
+        Ok(matches!(
+            crl.get_by_serial(vcek.serial_number()),
+            CrlStatus::Revoked(_)
+        ))
+    }
+
+    /// The CRL of a source which isn't fetched
+    fn read_local(&self) -> Result<Vec<u8>> {
+        match &self.source {
+            CrlSource::Bytes(crl) => Ok(crl.clone()),
+            CrlSource::File(path) => read_crl(path),
+            CrlSource::Url(_) => bail!("No current CRL, it must be refreshed"),
+        }
+    }
+
+    /// Parse a CRL, requiring it to be signed by an issuer and current
+    fn parse(&self, crl: &[u8], now: SystemTime) -> Result<X509Crl> {
+        let crl = X509Crl::from_der(crl)
+            .or_else(|_| X509Crl::from_pem(crl))
+            .context("Failed to parse VCEK CRL")?;
+        let signed = self.issuers.iter().any(|issuer| {
+            issuer
+                .public_key()
+                .and_then(|key| crl.verify(&key))
+                .unwrap_or(false)
+        });
+        if !signed {
+            bail!("VCEK CRL is not signed by the ARK or ASK");
+        }
+        if !is_current(&crl, now)? {
+            bail!("VCEK CRL is outdated");
+        }
+        Ok(crl)
+    }
+
+    fn lock_crl(&self) -> Result<MutexGuard<'_, Option<X509Crl>>> {
+        self.crl
+            .lock()
+            .map_err(|_| anyhow!("VCEK CRL cache is poisoned"))
+    }
+}
+
+fn read_crl(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("Failed to read VCEK CRL {}", path.display()))
+}
+
+/// Whether `now` is between the lastUpdate and nextUpdate of `crl`. A CRL
+/// without nextUpdate is never current.
+fn is_current(crl: &X509Crl, now: SystemTime) -> Result<bool> {
+    let now = asn1_time(now)?;
+    let Some(next_update) = crl.next_update() else {
+        return Ok(false);
+    };
+    Ok(compare(&now, crl.last_update())? != Ordering::Less
+        && compare(&now, next_update)? == Ordering::Less)
+}
+
+fn compare(now: &Asn1TimeRef, time: &Asn1TimeRef) -> Result<Ordering> {
+    now.compare(time).context("Failed to compare CRL times")
+}
+
+impl AzSnpVtpm {
+    /// Reject VCEKs listed in the CRL read from `source`, which must be
+    /// signed by the ARK or ASK of a configured generation. With
+    /// `soft_fail`, evidence is accepted when the CRL is unavailable or
+    /// outdated, instead of being rejected.
+    pub fn with_vcek_crl(mut self, source: CrlSource, soft_fail: bool) -> Self {
+        let issuers = self
+            .vendor_certs
+            .values()
+            .flat_map(|certs| [certs.ark.clone(), certs.ask.clone()])
+            .collect();
+        self.vcek_crl = Some(VcekCrl::new(source, soft_fail, issuers));
+        self
+    }
+
+    /// Fetch the VCEK CRL again if the cached one has reached its
+    /// nextUpdate. Needed before verifying evidence with the synchronous
+    /// APIs when the CRL is read from a URL.
+    pub async fn refresh_vcek_crl(&self) -> Result<()> {
+        match &self.vcek_crl {
+            Some(crl) => crl.refresh(self.now()).await,
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::super::FixedClock;
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Issuer of the test CRLs, whose nextUpdate is 2029-07-12
+    const ISSUER: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/crl/issuer.pem");
+
+    fn crl_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("test_data/az-snp-vtpm/crl")
+            .join(name)
+    }
+
+    fn verify(crl: &str, soft_fail: bool, now: u64) -> Result<crate::TeeEvidenceParsedClaim> {
+        verify_source(CrlSource::File(crl_path(crl)), soft_fail, now)
+    }
+
+    fn verify_source(
+        source: CrlSource,
+        soft_fail: bool,
+        now: u64,
+    ) -> Result<crate::TeeEvidenceParsedClaim> {
+        let clock = FixedClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(now));
+        let mut verifier = AzSnpVtpm::new().unwrap().with_clock(Arc::new(clock));
+        verifier.vcek_crl = Some(VcekCrl::new(
+            source,
+            soft_fail,
+            vec![X509::from_pem(ISSUER).unwrap()],
+        ));
+        verifier.verify_evidence(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_vcek_crl() {
+        verify("clean.crl", false, 1_800_000_000).unwrap();
+
+        // the serial of the fixture VCEK is 0
+        for soft_fail in [false, true] {
+            let err = verify("revoked.crl", soft_fail, 1_800_000_000).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::VcekRevoked(serial)) if serial == "0"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_vcek_crl_bytes() {
+        let revoked = std::fs::read(crl_path("revoked.crl")).unwrap();
+        let err = verify_source(CrlSource::Bytes(revoked), false, 1_800_000_000).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::VcekRevoked(_))
+        ));
+
+        let clean = std::fs::read(crl_path("clean.crl")).unwrap();
+        verify_source(CrlSource::Bytes(clean), false, 1_800_000_000).unwrap();
+
+        assert_eq!(
+            CrlSource::kds(SnpGeneration::Milan),
+            CrlSource::Url("https://kdsintf.amd.com/vcek/v1/Milan/crl".to_string())
+        );
+    }
+
+    #[test]
+    fn test_vcek_crl_unavailable() {
+        // after the nextUpdate of the CRL, or without a CRL
+        for (crl, now) in [("clean.crl", 1_900_000_000), ("missing.crl", 1_800_000_000)] {
+            let err = verify(crl, false, now).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::CrlUnavailable(_))
+            ));
+            verify(crl, true, now).unwrap();
+        }
+
+        // a CRL not signed by the AMD certificates
+        let err = fixture_verifier()
+            .with_vcek_crl(CrlSource::File(crl_path("clean.crl")), false)
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::CrlUnavailable(_))
+        ));
+    }
+}
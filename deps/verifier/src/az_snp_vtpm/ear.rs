@@ -0,0 +1,97 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Conversion of the Azure SNP vTPM claims into the claim names of an IETF
+//! Entity Attestation Token (RFC 9711), as carried by the Veraison EAR
+//! (https://github.com/veraison/ear): the report_data is the `eat_nonce`,
+//! the launch measurement and the PCRs are listed in `measurements`.
+
+use super::AzSnpVtpmClaims;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+pub const EAT_PROFILE: &str = "tag:confidential-containers.io,2025:az-snp-vtpm";
+/// Name of the SNP launch measurement in `measurements`
+pub const LAUNCH_MEASUREMENT: &str = "snp-launch";
+
+/// A measured component and its digest
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EarMeasurement {
+    /// `snp-launch` or the `pcrNN` name of a PCR
+    pub name: String,
+    /// Named Information Hash Algorithm of the digest
+    pub alg: String,
+    /// Hex encoded digest
+    pub digest: String,
+}
+
+/// EAT claims of an Azure SNP vTPM attestation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EarClaims {
+    pub eat_profile: String,
+    /// Hex encoded report_data
+    pub eat_nonce: String,
+    pub measurements: Vec<EarMeasurement>,
+}
+
+impl AzSnpVtpmClaims {
+    /// The claims under their EAT names
+    pub fn to_ear_claims(&self) -> Result<EarClaims> {
+        let measurement = self
+            .snp
+            .get("measurement")
+            .and_then(Value::as_str)
+            .context("Claim has no measurement")?;
+
+        let mut measurements = vec![EarMeasurement {
+            name: LAUNCH_MEASUREMENT.to_string(),
+            alg: "sha-384".to_string(),
+            digest: measurement.to_string(),
+        }];
+        measurements.extend(self.tpm.iter().map(|(name, digest)| EarMeasurement {
+            name: name.clone(),
+            alg: "sha-256".to_string(),
+            digest: digest.clone(),
+        }));
+        Ok(EarClaims {
+            eat_profile: EAT_PROFILE.to_string(),
+            eat_nonce: self.report_data.clone(),
+            measurements,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+
+    #[test]
+    fn test_to_ear_claims() {
+        let claims = fixture_verifier()
+            .evaluate_claims(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        let ear = claims.to_ear_claims().unwrap();
+
+        assert_eq!(ear.eat_profile, EAT_PROFILE);
+        assert_eq!(ear.eat_nonce, hex::encode(REPORT_DATA));
+        assert_eq!(ear.measurements.len(), 25);
+        let launch = &ear.measurements[0];
+        assert_eq!(launch.name, LAUNCH_MEASUREMENT);
+        assert_eq!(launch.digest.len(), 96);
+        assert_eq!(ear.measurements[9].name, "pcr08");
+        assert_eq!(ear.measurements[9].digest, claims.init_data);
+
+        let json = serde_json::to_value(&ear).unwrap();
+        assert_eq!(json["measurements"][0]["alg"], "sha-384");
+        assert_eq!(serde_json::from_value::<EarClaims>(json).unwrap(), ear);
+    }
+}
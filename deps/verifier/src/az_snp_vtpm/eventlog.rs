@@ -0,0 +1,293 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Replay of the TCG2 event log of the vTPM.
+//!
+//! The log is in the crypto agile format of the TCG PC Client Platform
+//! Firmware Profile (10.2): a SHA-1 format Spec ID event listing the digest
+//! sizes, followed by TCG_PCR_EVENT2 events carrying a digest per bank. The
+//! SHA-256 digests are replayed into zeroed PCRs, which must then equal the
+//! quoted PCRs. PCRs which no event extends are not compared.
+
+use super::{CertError, PCR_COUNT};
+use anyhow::{anyhow, bail, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read};
+
+const TPM_ALG_SHA256: u16 = 0x000b;
+const EV_NO_ACTION: u32 = 0x0000_0003;
+const SPEC_ID_SIGNATURE: &[u8; 16] = b"Spec ID Event03\0";
+const STARTUP_LOCALITY_SIGNATURE: &[u8; 16] = b"StartupLocality\0";
+/// Size of the SHA-1 digest of the Spec ID event
+const SHA1_DIGEST_SIZE: usize = 20;
+/// Upper bound of the size of an event, against malformed logs
+const MAX_EVENT_SIZE: u32 = 1 << 24;
+
+/// An event of the log extended into a PCR
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MeasuredComponent {
+    pub pcr: u32,
+    pub event_type: String,
+    /// Hex encoded SHA-256 digest extended into the PCR
+    pub digest: String,
+}
+
+struct Event {
+    pcr_index: u32,
+    event_type: u32,
+    digests: Vec<(u16, Vec<u8>)>,
+    data: Vec<u8>,
+}
+
+fn event_type_name(event_type: u32) -> String {
+    let name = match event_type {
+        0x0000_0001 => "EV_POST_CODE",
+        0x0000_0003 => "EV_NO_ACTION",
+        0x0000_0004 => "EV_SEPARATOR",
+        0x0000_0005 => "EV_ACTION",
+        0x0000_0006 => "EV_EVENT_TAG",
+        0x0000_0007 => "EV_S_CRTM_CONTENTS",
+        0x0000_0008 => "EV_S_CRTM_VERSION",
+        0x0000_000d => "EV_IPL",
+        0x8000_0001 => "EV_EFI_VARIABLE_DRIVER_CONFIG",
+        0x8000_0002 => "EV_EFI_VARIABLE_BOOT",
+        0x8000_0003 => "EV_EFI_BOOT_SERVICES_APPLICATION",
+        0x8000_0004 => "EV_EFI_BOOT_SERVICES_DRIVER",
+        0x8000_0006 => "EV_EFI_GPT_EVENT",
+        0x8000_0007 => "EV_EFI_ACTION",
+        0x8000_0008 => "EV_EFI_PLATFORM_FIRMWARE_BLOB",
+        0x8000_0009 => "EV_EFI_HANDOFF_TABLES",
+        0x8000_00e0 => "EV_EFI_VARIABLE_AUTHORITY",
+        _ => return format!("0x{event_type:08x}"),
+    };
+    name.to_string()
+}
+
+fn read_event_data(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let size = cursor.read_u32::<LittleEndian>()?;
+    if size > MAX_EVENT_SIZE {
+        bail!("Event of {size} bytes is too large");
+    }
+    let mut data = vec![0u8; size as usize];
+    cursor.read_exact(&mut data)?;
+    Ok(data)
+}
+
+/// The digest sizes by algorithm listed in the Spec ID event
+fn parse_spec_id(cursor: &mut Cursor<&[u8]>) -> Result<HashMap<u16, usize>> {
+    let _pcr_index = cursor.read_u32::<LittleEndian>()?;
+    let event_type = cursor.read_u32::<LittleEndian>()?;
+    let mut digest = [0u8; SHA1_DIGEST_SIZE];
+    cursor.read_exact(&mut digest)?;
+    let data = read_event_data(cursor)?;
+    if event_type != EV_NO_ACTION || !data.starts_with(SPEC_ID_SIGNATURE) {
+        bail!("Event log doesn't start with a Spec ID event");
+    }
+
+    let mut spec_id = Cursor::new(&data[SPEC_ID_SIGNATURE.len()..]);
+    // platformClass, specVersionMinor, specVersionMajor, specErrata, uintnSize
+    let mut header = [0u8; 8];
+    spec_id.read_exact(&mut header)?;
+    let count = spec_id.read_u32::<LittleEndian>()?;
+    let mut sizes = HashMap::new();
+    for _ in 0..count {
+        let alg = spec_id.read_u16::<LittleEndian>()?;
+        let size = spec_id.read_u16::<LittleEndian>()?;
+        sizes.insert(alg, size as usize);
+    }
+    Ok(sizes)
+}
+
+fn parse_event(cursor: &mut Cursor<&[u8]>, sizes: &HashMap<u16, usize>) -> Result<Event> {
+    let pcr_index = cursor.read_u32::<LittleEndian>()?;
+    let event_type = cursor.read_u32::<LittleEndian>()?;
+    let count = cursor.read_u32::<LittleEndian>()?;
+    let mut digests = vec![];
+    for _ in 0..count {
+        let alg = cursor.read_u16::<LittleEndian>()?;
+        let size = *sizes
+            .get(&alg)
+            .with_context(|| format!("Digest algorithm 0x{alg:04x} isn't in the Spec ID event"))?;
+        let mut digest = vec![0u8; size];
+        cursor.read_exact(&mut digest)?;
+        digests.push((alg, digest));
+    }
+    let data = read_event_data(cursor)?;
+    Ok(Event {
+        pcr_index,
+        event_type,
+        digests,
+        data,
+    })
+}
+
+fn parse_event_log(log: &[u8]) -> Result<Vec<Event>> {
+    let mut cursor = Cursor::new(log);
+    let sizes = parse_spec_id(&mut cursor).context("Malformed Spec ID event")?;
+    let mut events = vec![];
+    while (cursor.position() as usize) < log.len() {
+        let event = parse_event(&mut cursor, &sizes)
+            .with_context(|| format!("Malformed event {}", events.len() + 1))?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+/// Replay the SHA-256 digests of the TCG2 event `log` and check that the
+/// PCRs it extends equal the quoted `pcrs`, returning the measured
+/// components in the order of the log.
+pub(crate) fn replay_event_log(
+    log: &[u8],
+    pcrs: &BTreeMap<usize, &[u8; 32]>,
+) -> Result<Vec<MeasuredComponent>, CertError> {
+    let mut replayed = [[0u8; 32]; PCR_COUNT];
+    let mut extended = [false; PCR_COUNT];
+    let mut components = vec![];
+    for event in parse_event_log(log)? {
+        let index = event.pcr_index as usize;
+        if index >= PCR_COUNT {
+            return Err(anyhow!("Event extends the invalid PCR{index}").into());
+        }
+        if event.event_type == EV_NO_ACTION {
+            // the locality the TPM has been started from initializes PCR0
+            if index == 0 && event.data.starts_with(STARTUP_LOCALITY_SIGNATURE) {
+                let locality = event.data.get(STARTUP_LOCALITY_SIGNATURE.len());
+                replayed[0][31] = *locality.context("Malformed StartupLocality event")?;
+            }
+            continue;
+        }
+        let Some((_, digest)) = event.digests.iter().find(|(alg, _)| *alg == TPM_ALG_SHA256) else {
+            return Err(anyhow!("Event of PCR{index} has no SHA-256 digest").into());
+        };
+        replayed[index] = openssl::sha::sha256(&[&replayed[index][..], digest].concat());
+        extended[index] = true;
+        components.push(MeasuredComponent {
+            pcr: event.pcr_index,
+            event_type: event_type_name(event.event_type),
+            digest: hex::encode(digest),
+        });
+    }
+
+    for (index, pcr) in replayed.iter().enumerate() {
+        if !extended[index] {
+            continue;
+        }
+        if pcrs.get(&index).is_none_or(|quoted| *quoted != pcr) {
+            return Err(CertError::EventLogMismatch(index));
+        }
+    }
+    Ok(components)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A log with the Spec ID event, the StartupLocality and an event per
+    /// `(pcr, event type, SHA-256 digest)`, each also with a SHA-384 digest
+    pub(crate) fn event_log(events: &[(u32, u32, [u8; 32])]) -> Vec<u8> {
+        let mut spec_id = SPEC_ID_SIGNATURE.to_vec();
+        spec_id.extend([0, 0, 0, 0, 0, 2, 0, 2]);
+        spec_id.extend(2u32.to_le_bytes());
+        spec_id.extend([0x0b, 0x00, 32, 0x00, 0x0c, 0x00, 48, 0x00]);
+        spec_id.push(0);
+
+        let mut log = vec![];
+        log.extend(0u32.to_le_bytes());
+        log.extend(EV_NO_ACTION.to_le_bytes());
+        log.extend([0; SHA1_DIGEST_SIZE]);
+        log.extend((spec_id.len() as u32).to_le_bytes());
+        log.extend(spec_id);
+
+        let mut locality = STARTUP_LOCALITY_SIGNATURE.to_vec();
+        locality.push(3);
+        let mut push = |pcr: u32, event_type: u32, digest: [u8; 32], data: &[u8]| {
+            log.extend(pcr.to_le_bytes());
+            log.extend(event_type.to_le_bytes());
+            log.extend(2u32.to_le_bytes());
+            log.extend(TPM_ALG_SHA256.to_le_bytes());
+            log.extend(digest);
+            log.extend(0x000cu16.to_le_bytes());
+            log.extend([0xff; 48]);
+            log.extend((data.len() as u32).to_le_bytes());
+            log.extend(data);
+        };
+        push(0, EV_NO_ACTION, [0; 32], &locality);
+        for (pcr, event_type, digest) in events {
+            push(*pcr, *event_type, *digest, b"event");
+        }
+        log
+    }
+
+    const EVENTS: [(u32, u32, [u8; 32]); 3] = [
+        (0, 0x8000_0008, [1; 32]),
+        (0, 0x0000_0004, [2; 32]),
+        (7, 0x8000_00e0, [3; 32]),
+    ];
+
+    /// The quoted PCRs matching `EVENTS`
+    fn quoted_pcrs() -> Vec<[u8; 32]> {
+        let extend =
+            |pcr: [u8; 32], digest: [u8; 32]| openssl::sha::sha256(&[pcr, digest].concat());
+        let mut pcrs = vec![[0xaa; 32]; PCR_COUNT];
+        let mut pcr0 = [0; 32];
+        pcr0[31] = 3;
+        pcrs[0] = extend(extend(pcr0, [1; 32]), [2; 32]);
+        pcrs[7] = extend([0; 32], [3; 32]);
+        pcrs
+    }
+
+    #[test]
+    fn test_replay_event_log() {
+        let pcrs = quoted_pcrs();
+        let pcrs: BTreeMap<usize, &[u8; 32]> = pcrs.iter().enumerate().collect();
+        let components = replay_event_log(&event_log(&EVENTS), &pcrs).unwrap();
+        assert_eq!(
+            components,
+            vec![
+                MeasuredComponent {
+                    pcr: 0,
+                    event_type: "EV_EFI_PLATFORM_FIRMWARE_BLOB".into(),
+                    digest: "01".repeat(32),
+                },
+                MeasuredComponent {
+                    pcr: 0,
+                    event_type: "EV_SEPARATOR".into(),
+                    digest: "02".repeat(32),
+                },
+                MeasuredComponent {
+                    pcr: 7,
+                    event_type: "EV_EFI_VARIABLE_AUTHORITY".into(),
+                    digest: "03".repeat(32),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replay_tampered_event_log() {
+        let pcrs = quoted_pcrs();
+        let pcrs: BTreeMap<usize, &[u8; 32]> = pcrs.iter().enumerate().collect();
+
+        let mut tampered = EVENTS;
+        tampered[2].2[0] ^= 1;
+        assert!(matches!(
+            replay_event_log(&event_log(&tampered), &pcrs),
+            Err(CertError::EventLogMismatch(7))
+        ));
+
+        // a dropped event
+        assert!(matches!(
+            replay_event_log(&event_log(&EVENTS[1..]), &pcrs),
+            Err(CertError::EventLogMismatch(0))
+        ));
+
+        let log = event_log(&EVENTS);
+        replay_event_log(&log[..log.len() - 1], &pcrs).unwrap_err();
+        replay_event_log(&log[4..], &pcrs).unwrap_err();
+    }
+}
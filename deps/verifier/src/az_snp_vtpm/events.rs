@@ -0,0 +1,217 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Change-oriented stream of verification events, e.g. for an event bus.
+//!
+//! Each verification emits an event to the user supplied `EventSink`, with
+//! the verdict, the identity of the device and the claim fields which
+//! changed since the last accepted evidence of the same device. The
+//! transport of the events is up to the sink.
+
+use super::identity::device_identity;
+use super::AzSnpVtpm;
+use crate::TeeEvidenceParsedClaim;
+use anyhow::Result;
+use log::warn;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+/// Claims which differ on every verification, and thus are not changes
+const VOLATILE_CLAIMS: &[&str] = &["report_data", "quote_chain_digest"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventVerdict {
+    Accept,
+    Reject,
+}
+
+/// Previous and current value of a changed claim field
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub previous: Value,
+    pub current: Value,
+}
+
+/// Event emitted for each verification
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VerificationEvent {
+    pub verdict: EventVerdict,
+    /// Device identity of an accepted evidence
+    pub device_identity: Option<String>,
+    /// Whether this is the first accepted evidence of the device
+    pub first_seen: bool,
+    /// Claim fields changed since the last accepted evidence of the device
+    pub changed: BTreeMap<String, FieldChange>,
+    /// Reason of the rejection of an evidence
+    pub error: Option<String>,
+}
+
+/// Receives the verification events, e.g. to publish them on a bus
+pub trait EventSink: Send + Sync {
+    fn emit(&self, event: VerificationEvent);
+}
+
+/// Emits events to a sink, tracking the last claim seen for each device
+pub(crate) struct EventEmitter {
+    sink: Box<dyn EventSink>,
+    last_seen: Mutex<HashMap<String, Map<String, Value>>>,
+}
+
+impl EventEmitter {
+    pub(crate) fn new(sink: Box<dyn EventSink>) -> Self {
+        Self {
+            sink,
+            last_seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Emit the event of the verification which returned `result`
+    pub(crate) fn record(&self, result: &Result<TeeEvidenceParsedClaim>) {
+        let event = match result {
+            Ok(claim) => self.accept_event(claim),
+            Err(e) => VerificationEvent {
+                verdict: EventVerdict::Reject,
+                device_identity: None,
+                first_seen: false,
+                changed: BTreeMap::new(),
+                error: Some(format!("{e:#}")),
+            },
+        };
+        self.sink.emit(event);
+    }
+
+    fn accept_event(&self, claim: &TeeEvidenceParsedClaim) -> VerificationEvent {
+        let mut event = VerificationEvent {
+            verdict: EventVerdict::Accept,
+            device_identity: None,
+            first_seen: false,
+            changed: BTreeMap::new(),
+            error: None,
+        };
+        let identity = match device_identity(claim) {
+            Ok(identity) => identity,
+            Err(e) => {
+                warn!("Failed to derive the device identity of the event: {e:#}");
+                return event;
+            }
+        };
+        let mut current = claim.as_object().cloned().unwrap_or_default();
+        current.retain(|name, _| !VOLATILE_CLAIMS.contains(&name.as_str()));
+
+        let mut last_seen = self.last_seen.lock().unwrap_or_else(|e| e.into_inner());
+        match last_seen.get(&identity) {
+            Some(previous) => event.changed = changes(previous, &current),
+            None => event.first_seen = true,
+        }
+        last_seen.insert(identity.clone(), current);
+        event.device_identity = Some(identity);
+        event
+    }
+}
+
+fn changes(
+    previous: &Map<String, Value>,
+    current: &Map<String, Value>,
+) -> BTreeMap<String, FieldChange> {
+    let names: BTreeSet<&String> = previous.keys().chain(current.keys()).collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let previous = previous.get(name).cloned().unwrap_or(Value::Null);
+            let current = current.get(name).cloned().unwrap_or(Value::Null);
+            (previous != current).then(|| (name.clone(), FieldChange { previous, current }))
+        })
+        .collect()
+}
+
+impl AzSnpVtpm {
+    /// Emit an event to `sink` for each verification of an evidence
+    pub fn with_event_sink(mut self, sink: impl EventSink + 'static) -> Self {
+        self.events = Some(EventEmitter::new(Box::new(sink)));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct RecordingSink {
+        events: Arc<Mutex<Vec<VerificationEvent>>>,
+    }
+
+    impl EventSink for RecordingSink {
+        fn emit(&self, event: VerificationEvent) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let sink = RecordingSink::default();
+        let verifier = fixture_verifier().with_event_sink(sink.clone());
+        let verify = |report_data| {
+            verifier.verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(report_data),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+
+        verify(REPORT_DATA).unwrap();
+        verify(REPORT_DATA).unwrap();
+        verify(b"stale").unwrap_err();
+
+        let events = sink.events.lock().unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].verdict, EventVerdict::Accept);
+        assert!(events[0].first_seen);
+        assert!(events[0].device_identity.is_some());
+        assert_eq!(events[1].verdict, EventVerdict::Accept);
+        assert!(!events[1].first_seen);
+        assert_eq!(events[1].device_identity, events[0].device_identity);
+        assert!(events[1].changed.is_empty());
+        assert_eq!(events[2].verdict, EventVerdict::Reject);
+        assert!(events[2].error.is_some());
+    }
+
+    #[test]
+    fn test_changed_fields() {
+        let sink = RecordingSink::default();
+        let emitter = EventEmitter::new(Box::new(sink.clone()));
+        let claim = json!({
+            "chip_id": "01".repeat(64),
+            "reported_tcb_microcode": "115",
+            "report_data": "00",
+        });
+        emitter.record(&Ok(claim.clone()));
+
+        let mut updated = claim;
+        updated["reported_tcb_microcode"] = json!("116");
+        updated["report_data"] = json!("01");
+        emitter.record(&Ok(updated));
+
+        let events = sink.events.lock().unwrap();
+        assert_eq!(
+            events[1].changed,
+            BTreeMap::from([(
+                "reported_tcb_microcode".to_string(),
+                FieldChange {
+                    previous: json!("115"),
+                    current: json!("116"),
+                }
+            )])
+        );
+    }
+}
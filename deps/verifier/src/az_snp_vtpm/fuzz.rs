@@ -0,0 +1,39 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Entry points of the fuzz targets in `fuzz/`, fed with arbitrary bytes.
+//! Errors are discarded, a target only fails on a panic.
+
+use super::{ak_pub_der, validate_hcl_report, AzSnpVtpm, Evidence};
+use crate::{InitDataHash, ReportData};
+use az_snp_vtpm::hcl::HclReport;
+use az_snp_vtpm::report::AttestationReport;
+
+/// Parse `data` as an evidence, then inspect and verify it
+pub fn evidence(data: &[u8]) {
+    let _ = Evidence::from_slice(data);
+    let _ = AzSnpVtpm::inspect(data);
+    if let Ok(verifier) = AzSnpVtpm::new() {
+        let _ = verifier.evaluate_blocking(
+            data,
+            &ReportData::Value(b"challenge"),
+            &InitDataHash::NotProvided,
+        );
+    }
+}
+
+/// Parse `data` as an HCL report the way the verifier does, validating its
+/// layout before handing it to `HclReport::new`
+pub fn hcl_report(data: &[u8]) {
+    if validate_hcl_report(data).is_err() {
+        return;
+    }
+    let Ok(hcl_report) = HclReport::new(data.to_vec()) else {
+        return;
+    };
+    let _ = ak_pub_der(&hcl_report);
+    let _ = hcl_report.var_data_sha256();
+    let _: Result<AttestationReport, _> = hcl_report.try_into();
+}
@@ -0,0 +1,206 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! SEV-SNP CPU generations and the AMD cert chains of their VCEKs.
+//!
+//! The generation of a VCEK is read from its issuer, the ASK, whose common
+//! name is `SEV-<product name>`, and the VCEK is verified against the chain
+//! of that generation. Only the Milan chain is embedded in the verifier,
+//! the chains of the other generations are configured with
+//! `AzSnpVtpm::with_vendor_certs`.
+
+use super::{AzSnpVtpm, CertError};
+use crate::snp::VendorCertificates;
+use anyhow::{bail, Context, Result};
+use openssl::nid::Nid;
+use openssl::x509::{X509NameRef, X509Ref};
+use serde::Serialize;
+use std::fmt;
+
+/// A generation of SEV-SNP capable AMD EPYC CPUs
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum SnpGeneration {
+    /// Zen 3
+    Milan,
+    /// Zen 4
+    Genoa,
+    /// Zen 5
+    Turin,
+}
+
+impl fmt::Display for SnpGeneration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Milan => write!(f, "Milan"),
+            Self::Genoa => write!(f, "Genoa"),
+            Self::Turin => write!(f, "Turin"),
+        }
+    }
+}
+
+impl SnpGeneration {
+    /// The generation of the CPU a VCEK has been issued for
+    pub fn from_vcek(vcek: &X509Ref) -> Result<Self> {
+        Self::from_ask_name(vcek.issuer_name()).context("Unknown VCEK issuer")
+    }
+
+    /// The generation of the CPUs whose VCEKs an ASK issues
+    pub fn from_ask(ask: &X509Ref) -> Result<Self> {
+        Self::from_ask_name(ask.subject_name()).context("Unknown ASK")
+    }
+
+    fn from_ask_name(name: &X509NameRef) -> Result<Self> {
+        let name = name
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .context("No common name")?
+            .data()
+            .as_utf8()
+            .context("Malformed common name")?;
+        Ok(match name.to_string().as_str() {
+            "SEV-Milan" => Self::Milan,
+            "SEV-Genoa" => Self::Genoa,
+            "SEV-Turin" => Self::Turin,
+            other => bail!("Unknown common name {other}"),
+        })
+    }
+}
+
+impl AzSnpVtpm {
+    /// Verify the VCEKs of `generation` against the PEM bundle `pem` of its
+    /// ASK, ARK and ASVK, in this order, replacing the configured chain.
+    /// The ASK must be the one of `generation`.
+    pub fn with_vendor_certs(mut self, generation: SnpGeneration, pem: &[u8]) -> Result<Self> {
+        let certs = VendorCertificates::from_pem(pem)
+            .with_context(|| format!("Malformed {generation} cert chain"))?;
+        let ask_generation = SnpGeneration::from_ask(&certs.ask)?;
+        if ask_generation != generation {
+            bail!("The {generation} cert chain has the ASK of {ask_generation}");
+        }
+        self.vendor_certs.insert(generation, certs);
+        Ok(self)
+    }
+
+    /// Verify the VCEKs issued by the ASK of `certs` against this chain
+    /// rather than the embedded one, e.g. a chain provisioned out-of-band
+    /// in an air-gapped deployment.
+    pub fn with_vendor_cert_chain(mut self, certs: VendorCertificates) -> Result<Self> {
+        let generation = SnpGeneration::from_ask(&certs.ask)?;
+        self.vendor_certs.insert(generation, certs);
+        Ok(self)
+    }
+
+    /// The cert chain of the generation of `vcek`
+    pub(crate) fn vendor_certs_for(
+        &self,
+        vcek: &X509Ref,
+    ) -> Result<&VendorCertificates, CertError> {
+        let generation = SnpGeneration::from_vcek(vcek)?;
+        self.vendor_certs
+            .get(&generation)
+            .ok_or(CertError::MissingVendorCerts(generation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_verifier, unrelated_chain, vcek_of, REPORT, REPORT_DATA, VCEK,
+    };
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use openssl::x509::X509;
+
+    const MILAN_CERTS: &[u8] = include_bytes!("../snp/milan_ask_ark_asvk.pem");
+
+    #[test]
+    fn test_generation_from_vcek() {
+        let vcek = X509::from_pem(VCEK.as_bytes()).unwrap();
+        assert_eq!(
+            SnpGeneration::from_vcek(&vcek).unwrap(),
+            SnpGeneration::Milan
+        );
+        assert_eq!(
+            SnpGeneration::from_vcek(&vcek_of("Genoa")).unwrap(),
+            SnpGeneration::Genoa
+        );
+        assert_eq!(
+            SnpGeneration::from_vcek(&vcek_of("Turin")).unwrap(),
+            SnpGeneration::Turin
+        );
+        SnpGeneration::from_vcek(&vcek_of("Naples")).unwrap_err();
+    }
+
+    #[test]
+    fn test_vendor_certs_by_generation() {
+        let genoa_vcek = vcek_of("Genoa");
+        let verifier = fixture_verifier();
+        assert!(matches!(
+            verifier.vendor_certs_for(&genoa_vcek),
+            Err(CertError::MissingVendorCerts(SnpGeneration::Genoa))
+        ));
+
+        // the Milan evidence is still verified against the Milan chain
+        let verifier = verifier
+            .with_vendor_certs(SnpGeneration::Genoa, &unrelated_chain("Genoa"))
+            .unwrap();
+        verifier.vendor_certs_for(&genoa_vcek).unwrap();
+        let verify = |verifier: &AzSnpVtpm| {
+            verifier.verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        verify(&verifier).unwrap();
+
+        // and fails against another Milan chain
+        let verifier = verifier
+            .with_vendor_certs(SnpGeneration::Milan, &unrelated_chain("Milan"))
+            .unwrap();
+        verify(&verifier).unwrap_err();
+
+        // the chain of another generation is rejected
+        let err = fixture_verifier()
+            .with_vendor_certs(SnpGeneration::Genoa, MILAN_CERTS)
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "The Genoa cert chain has the ASK of Milan");
+        fixture_verifier()
+            .with_vendor_certs(SnpGeneration::Turin, b"")
+            .err()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_vendor_cert_chain() {
+        let [ask, ark, asvk] = <[X509; 3]>::try_from(X509::stack_from_pem(MILAN_CERTS).unwrap())
+            .ok()
+            .unwrap();
+        assert_eq!(SnpGeneration::from_ask(&ask).unwrap(), SnpGeneration::Milan);
+        SnpGeneration::from_ask(&ark).unwrap_err();
+
+        let certs = VendorCertificates::new(ask.clone(), ark.clone(), asvk.clone()).unwrap();
+        let mut verifier = fixture_verifier();
+        verifier.vendor_certs.clear();
+        verifier
+            .with_vendor_cert_chain(certs)
+            .unwrap()
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+
+        // an ASK not issued by the ARK is rejected at construction
+        let other =
+            X509::from_pem(include_bytes!("../../test_data/az-snp-vtpm/crl/issuer.pem")).unwrap();
+        VendorCertificates::new(other.clone(), ark, asvk.clone()).unwrap_err();
+        VendorCertificates::new(ask, other, asvk).unwrap_err();
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use super::clock::is_fresh;
+use super::{
+    ak_pub_der, extend_claim_with_event_log, extend_claim_with_init_data_bank,
+    extend_claim_with_sha384, verify_quote, AzSnpVtpm, EvidenceFormat, ReportDataBinding,
+};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{anyhow, bail, Result};
+use az_snp_vtpm::hcl::HclReport;
+use log::debug;
+use openssl::sha::{sha256, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+struct Session {
+    evidence_digest: [u8; 32],
+    verified_at: SystemTime,
+    claim: TeeEvidenceParsedClaim,
+}
+
+/// Verifier for periodic attestation heartbeats, which reuse the boot-time
+/// SNP report together with a fresh vTPM quote.
+///
+/// Sessions are keyed by the sha256 fingerprint of the AKpub. The first
+/// heartbeat of a session is verified like `AzSnpVtpm::evaluate`. Later
+/// heartbeats carrying the same HCL report and VCEK within `ttl` only get
+/// their quote, nonce, PCRs and init_data verified, the SNP report claims
+/// are taken from the session. A different report or an expired session
+/// triggers a full verification.
+pub struct HeartbeatVerifier {
+    verifier: AzSnpVtpm,
+    ttl: Duration,
+    sessions: Mutex<HashMap<[u8; 32], Session>>,
+}
+
+impl HeartbeatVerifier {
+    pub fn new(verifier: AzSnpVtpm, ttl: Duration) -> Self {
+        Self {
+            verifier,
+            ttl,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn verify_heartbeat(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        if let ReportData::NotProvided = expected_report_data {
+            bail!("unexpected empty report data");
+        }
+
+        let evidence = self
+            .verifier
+            .parse_evidence(evidence, EvidenceFormat::Json)?;
+
+        let report = self.verifier.unwrap_report(evidence.report)?;
+        let mut hasher = Sha256::new();
+        hasher.update(&report);
+        hasher.update(evidence.vcek.as_bytes());
+        // with the key binding, the SNP report is only reused for its key
+        if let (ReportDataBinding::KeySha512, ReportData::Value(key)) =
+            (self.verifier.report_data_binding, expected_report_data)
+        {
+            hasher.update(key);
+        }
+        let evidence_digest = hasher.finish();
+
+        let hcl_report = HclReport::new(report)?;
+        let fingerprint = sha256(&ak_pub_der(&hcl_report)?);
+        verify_quote(
+            &evidence.quote,
+            &hcl_report,
+            self.verifier.expected_nonce(expected_report_data),
+            expected_init_data_hash,
+            self.verifier.init_data_pcr,
+            &self.verifier.init_data_extend,
+            evidence.pcrs_sha384.as_deref(),
+        )?;
+
+        let mut claim = match self.cached_claim(&fingerprint, &evidence_digest)? {
+            Some(claim) => {
+                debug!("Reusing the SNP report verified for this heartbeat session");
+                claim
+            }
+            None => {
+                let claim = self.verifier.verify_report(
+                    hcl_report,
+                    &evidence.vcek,
+                    expected_report_data,
+                    None,
+                )?;
+                let mut sessions = self.lock_sessions()?;
+                let now = self.verifier.now();
+                sessions.retain(|_, session| is_fresh(now, session.verified_at, self.ttl));
+                sessions.insert(
+                    fingerprint,
+                    Session {
+                        evidence_digest,
+                        verified_at: now,
+                        claim: claim.clone(),
+                    },
+                );
+                claim
+            }
+        };
+        self.verifier
+            .bind_claim(&mut claim, &evidence.quote, expected_report_data)?;
+        extend_claim_with_sha384(&mut claim, &evidence.quote, evidence.pcrs_sha384.as_deref())?;
+        extend_claim_with_init_data_bank(
+            &mut claim,
+            expected_init_data_hash,
+            self.verifier.init_data_pcr,
+        )?;
+        extend_claim_with_event_log(&mut claim, &evidence.quote, evidence.event_log.as_deref())?;
+
+        Ok(claim)
+    }
+
+    /// Number of sessions whose SNP report can still be reused
+    pub fn active_sessions(&self) -> Result<usize> {
+        let sessions = self.lock_sessions()?;
+        Ok(sessions
+            .values()
+            .filter(|session| is_fresh(self.verifier.now(), session.verified_at, self.ttl))
+            .count())
+    }
+
+    fn cached_claim(
+        &self,
+        fingerprint: &[u8; 32],
+        evidence_digest: &[u8; 32],
+    ) -> Result<Option<TeeEvidenceParsedClaim>> {
+        let mut sessions = self.lock_sessions()?;
+        let claim = match sessions.get(fingerprint) {
+            Some(session)
+                if session.evidence_digest == *evidence_digest
+                    && is_fresh(self.verifier.now(), session.verified_at, self.ttl) =>
+            {
+                Some(session.claim.clone())
+            }
+            Some(_) => {
+                debug!("Heartbeat session expired or report changed");
+                sessions.remove(fingerprint);
+                None
+            }
+            None => None,
+        };
+        Ok(claim)
+    }
+
+    fn lock_sessions(&self) -> Result<std::sync::MutexGuard<'_, HashMap<[u8; 32], Session>>> {
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow!("Heartbeat session cache is poisoned"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::super::FixedClock;
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_verify_heartbeats() {
+        let verifier = HeartbeatVerifier::new(fixture_verifier(), Duration::from_secs(60));
+        let evidence = evidence_fixture(REPORT);
+
+        for _ in 0..3 {
+            verifier
+                .verify_heartbeat(
+                    &evidence,
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                )
+                .unwrap();
+        }
+        assert_eq!(verifier.active_sessions().unwrap(), 1);
+
+        // the fresh quote is still checked against the expected nonce
+        verifier
+            .verify_heartbeat(
+                &evidence,
+                &ReportData::Value(b"stale"),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_verify_heartbeat_report_change() {
+        let verifier = HeartbeatVerifier::new(fixture_verifier(), Duration::from_secs(60));
+        verifier
+            .verify_heartbeat(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+
+        // same AK, but a tampered SNP report
+        let mut wrong_report = *REPORT;
+        wrong_report[0x01a6] = 0;
+        verifier
+            .verify_heartbeat(
+                &evidence_fixture(&wrong_report),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+        assert_eq!(verifier.active_sessions().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_verify_heartbeat_ttl_boundary() {
+        let clock = Arc::new(FixedClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000),
+        ));
+        let verifier = HeartbeatVerifier::new(
+            AzSnpVtpm::new().unwrap().with_clock(clock.clone()),
+            Duration::from_secs(60),
+        );
+        verifier
+            .verify_heartbeat(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(verifier.active_sessions().unwrap(), 1);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(verifier.active_sessions().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_verify_heartbeat_expiry() {
+        let verifier = HeartbeatVerifier::new(fixture_verifier(), Duration::ZERO);
+        verifier
+            .verify_heartbeat(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        assert_eq!(verifier.active_sessions().unwrap(), 0);
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Deterministic device identities, e.g. to key a CMDB.
+//!
+//! An identity is derived only from fields which survive firmware updates:
+//!
+//! - `chip_id` is fused into the CPU and never changes, unless the guest
+//!   policy masks it, in which case the claim is empty and rejected.
+//! - `ak_fingerprint`, the sha256 of the vTPM AKpub, is stable for as long
+//!   as the vTPM state of the VM persists, but changes when the VM is
+//!   redeployed with a fresh vTPM.
+//!
+//! The reported TCB, the launch measurement and the PCRs change with
+//! firmware and guest updates and never contribute to an identity.
+
+use crate::TeeEvidenceParsedClaim;
+use anyhow::{bail, Context, Result};
+use openssl::sha::Sha256;
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Immutable claim field composing a device identity
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IdentityField {
+    ChipId,
+    AkFingerprint,
+}
+
+impl fmt::Display for IdentityField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChipId => write!(f, "chip_id"),
+            Self::AkFingerprint => write!(f, "ak_fingerprint"),
+        }
+    }
+}
+
+/// Derives device identities from a configurable set of immutable fields,
+/// the chip_id only by default
+#[derive(Clone, Debug)]
+pub struct DeviceIdentity {
+    fields: BTreeSet<IdentityField>,
+}
+
+impl Default for DeviceIdentity {
+    fn default() -> Self {
+        Self {
+            fields: BTreeSet::from([IdentityField::ChipId]),
+        }
+    }
+}
+
+impl DeviceIdentity {
+    pub fn new(fields: impl IntoIterator<Item = IdentityField>) -> Result<Self> {
+        let fields: BTreeSet<_> = fields.into_iter().collect();
+        if fields.is_empty() {
+            bail!("A device identity needs at least one field");
+        }
+        Ok(Self { fields })
+    }
+
+    /// The hex encoded sha256 over the name, length and value of each
+    /// field, in a fixed order, of a claim of `AzSnpVtpm`
+    pub fn device_identity(&self, claim: &TeeEvidenceParsedClaim) -> Result<String> {
+        let mut hasher = Sha256::new();
+        for field in &self.fields {
+            let name = field.to_string();
+            let value = claim[&name]
+                .as_str()
+                .and_then(|value| hex::decode(value).ok())
+                .with_context(|| format!("Claim has no valid {name}"))?;
+            if value.iter().all(|byte| *byte == 0) {
+                bail!("{name} is masked, it can't identify the device");
+            }
+            for part in [name.as_bytes(), &value] {
+                hasher.update(&(part.len() as u64).to_be_bytes());
+                hasher.update(part);
+            }
+        }
+        Ok(hex::encode(hasher.finish()))
+    }
+}
+
+/// The device identity of a claim of `AzSnpVtpm`, derived from its chip_id
+pub fn device_identity(claim: &TeeEvidenceParsedClaim) -> Result<String> {
+    DeviceIdentity::default().device_identity(claim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use serde_json::json;
+
+    #[test]
+    fn test_device_identity() {
+        let claim = fixture_verifier()
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        let identity = device_identity(&claim).unwrap();
+
+        // the same device after a firmware update
+        let mut updated = claim.clone();
+        updated["reported_tcb"]["microcode"] = json!(116);
+        updated["reported_tcb_microcode"] = json!("116");
+        updated["microcode_svn"] = json!(116);
+        updated["tpm"]["pcr00"] = json!("11".repeat(32));
+        assert_eq!(device_identity(&updated).unwrap(), identity);
+
+        let with_ak =
+            DeviceIdentity::new([IdentityField::ChipId, IdentityField::AkFingerprint]).unwrap();
+        let ak_identity = with_ak.device_identity(&claim).unwrap();
+        assert_ne!(ak_identity, identity);
+        assert_eq!(with_ak.device_identity(&updated).unwrap(), ak_identity);
+
+        let mut redeployed = claim.clone();
+        redeployed["ak_fingerprint"] = json!("22".repeat(32));
+        assert_eq!(device_identity(&redeployed).unwrap(), identity);
+        assert_ne!(with_ak.device_identity(&redeployed).unwrap(), ak_identity);
+
+        let mut masked = claim.clone();
+        masked["chip_id"] = json!("");
+        device_identity(&masked).unwrap_err();
+        masked["chip_id"] = json!("00".repeat(64));
+        device_identity(&masked).unwrap_err();
+        DeviceIdentity::new([]).unwrap_err();
+    }
+}
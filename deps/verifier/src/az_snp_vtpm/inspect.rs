@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Parsing of evidence without verifying it, to see what a rejected blob
+//! contains. None of the signatures, the nonce or the bindings of the
+//! evidence are checked, so the parsed claim must never be trusted.
+
+use super::{
+    chip_id_claim, claim_map, extend_claim, parse_vcek, validate_hcl_report, AzSnpVtpm, Evidence,
+    Tcb, INITDATA_PCR,
+};
+use crate::snp::parse_tee_evidence;
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::Result;
+use az_snp_vtpm::hcl::HclReport;
+use az_snp_vtpm::report::AttestationReport;
+use serde_json::Value;
+
+/// Outcome of `evaluate_verbose`
+#[derive(Debug)]
+pub struct VerboseVerification {
+    /// Whether the evidence has been verified
+    pub result: Result<()>,
+    /// The claim of the verified evidence. On failure, the claim `inspect`
+    /// parses from the evidence for triage, marked `untrusted: true`, or
+    /// None if the evidence can't be parsed at all.
+    pub claim: Option<TeeEvidenceParsedClaim>,
+}
+
+impl AzSnpVtpm {
+    /// Parse the claim of `evidence` WITHOUT verifying it, for debugging.
+    /// The claim is marked `untrusted: true`. The `vcek_tcb` is null if the
+    /// VCEK is missing or malformed.
+    pub fn inspect(evidence: &[u8]) -> Result<Value> {
+        let evidence = Evidence::from_slice(evidence)?;
+        validate_hcl_report(&evidence.report)?;
+        let hcl_report = HclReport::new(evidence.report)?;
+        let snp_report: AttestationReport = hcl_report.try_into()?;
+        let vcek_tcb = parse_vcek(&evidence.vcek)
+            .and_then(|vcek| Ok(vcek.0.to_der()?))
+            .and_then(|der| Tcb::from_vcek_der(&der))
+            .ok();
+
+        let mut claim = parse_tee_evidence(&snp_report);
+        extend_claim(&mut claim, &evidence.quote, INITDATA_PCR)?;
+        let map = claim_map(&mut claim)?;
+        map.insert(
+            "reported_tcb".into(),
+            serde_json::to_value(Tcb::from(&snp_report.reported_tcb))?,
+        );
+        map.insert(
+            "committed_tcb".into(),
+            serde_json::to_value(Tcb::from(&snp_report.committed_tcb))?,
+        );
+        map.insert(
+            "current_tcb".into(),
+            serde_json::to_value(Tcb::from(&snp_report.current_tcb))?,
+        );
+        map.insert(
+            "launch_tcb".into(),
+            serde_json::to_value(Tcb::from(&snp_report.launch_tcb))?,
+        );
+        map.insert("vcek_tcb".into(), serde_json::to_value(vcek_tcb)?);
+        map.insert(
+            "chip_id".into(),
+            Value::String(chip_id_claim(&snp_report.chip_id)),
+        );
+        map.insert("untrusted".into(), Value::Bool(true));
+        Ok(claim)
+    }
+
+    /// Same as `evaluate_blocking`, keeping on failure whatever claims can
+    /// be parsed from the evidence, for operators to tell why it failed.
+    /// Those claims are untrusted, `evaluate` never returns a claim on
+    /// failure.
+    pub fn evaluate_verbose(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> VerboseVerification {
+        match self.evaluate_blocking(evidence, expected_report_data, expected_init_data_hash) {
+            Ok(claim) => VerboseVerification {
+                result: Ok(()),
+                claim: Some(claim),
+            },
+            Err(e) => VerboseVerification {
+                result: Err(e),
+                claim: Self::inspect(evidence).ok(),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_verifier, quote_with_nonce, REPORT, REPORT_DATA,
+    };
+    use super::super::CertError;
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_inspect() {
+        let claim = AzSnpVtpm::inspect(&evidence_fixture(REPORT)).unwrap();
+        assert_eq!(claim["untrusted"], json!(true));
+        assert_eq!(claim["report_data"], json!(hex::encode(REPORT_DATA)));
+        assert_eq!(claim["reported_tcb"]["microcode"], json!(115));
+        assert_eq!(claim["vcek_tcb"], claim["reported_tcb"]);
+        assert_eq!(claim["tpm"].as_object().unwrap().len(), 24);
+        assert!(claim["measurement"].is_string());
+
+        AzSnpVtpm::inspect(b"{}").unwrap_err();
+    }
+
+    #[test]
+    fn test_inspect_unverifiable_evidence() {
+        // a corrupted SNP report signature, a quote with another nonce which
+        // the AK didn't sign and no VCEK
+        let mut report = REPORT.to_vec();
+        report[0x20 + 0x2a0] ^= 1;
+        let mut evidence: Value = serde_json::from_slice(&evidence_fixture(&report)).unwrap();
+        evidence["quote"] = serde_json::to_value(quote_with_nonce(b"other")).unwrap();
+        evidence["vcek"] = "".into();
+        let evidence = serde_json::to_vec(&evidence).unwrap();
+
+        let claim = AzSnpVtpm::inspect(&evidence).unwrap();
+        assert_eq!(claim["untrusted"], json!(true));
+        assert_eq!(claim["report_data"], json!(hex::encode(b"other")));
+        assert_eq!(claim["vcek_tcb"], Value::Null);
+
+        fixture_verifier()
+            .verify_evidence(
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+    }
+
+    #[test]
+    fn test_evaluate_verbose() {
+        let verifier = fixture_verifier();
+        let verbose = |evidence: &[u8]| {
+            verifier.evaluate_verbose(
+                evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+        };
+        let verified = verbose(&evidence_fixture(REPORT));
+        verified.result.unwrap();
+        let claim = verified.claim.unwrap();
+        assert_eq!(claim.get("untrusted"), None);
+
+        // a corrupted SNP report signature fails the SNP verification
+        let mut report = REPORT.to_vec();
+        report[0x20 + 0x2a0] ^= 1;
+        let failed = verbose(&evidence_fixture(&report));
+        let err = failed.result.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::SnpReportSignature(_))
+        ));
+        let partial = failed.claim.unwrap();
+        assert_eq!(partial["untrusted"], json!(true));
+        assert_eq!(partial["tpm"], claim["tpm"]);
+        assert_eq!(partial["report_data"], json!(hex::encode(REPORT_DATA)));
+
+        let failed = verbose(b"{}");
+        failed.result.unwrap_err();
+        assert!(failed.claim.is_none());
+    }
+}
@@ -0,0 +1,74 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Conversion of the Azure SNP vTPM claim into an in-toto Statement
+//! (https://github.com/in-toto/attestation/blob/main/spec/v1/statement.md),
+//! so attestation results can flow into SLSA/in-toto pipelines.
+
+use crate::TeeEvidenceParsedClaim;
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+
+pub const STATEMENT_TYPE: &str = "https://in-toto.io/Statement/v1";
+pub const PREDICATE_TYPE: &str = "https://confidential-containers.io/attestation/az-snp-vtpm/v1";
+const SUBJECT_NAME: &str = "az-snp-vtpm-guest";
+
+/// Build an in-toto Statement whose subject is the guest launch measurement
+/// and whose predicate carries the measurement, TCB and PCRs of the claim.
+pub fn to_intoto_statement(claim: &TeeEvidenceParsedClaim) -> Result<Value> {
+    let measurement = claim["measurement"]
+        .as_str()
+        .context("Claim has no measurement")?;
+
+    let statement = json!({
+        "_type": STATEMENT_TYPE,
+        "subject": [{
+            "name": SUBJECT_NAME,
+            "digest": { "sha384": measurement },
+        }],
+        "predicateType": PREDICATE_TYPE,
+        "predicate": {
+            "measurement": measurement,
+            "reported_tcb": claim["reported_tcb"],
+            "pcrs": claim["tpm"],
+            "report_data": claim["report_data"],
+            "init_data": claim["init_data"],
+        },
+    });
+    Ok(statement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+
+    #[test]
+    fn test_to_intoto_statement() {
+        let claim = fixture_verifier()
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        let statement = to_intoto_statement(&claim).unwrap();
+
+        assert_eq!(statement["_type"], STATEMENT_TYPE);
+        assert_eq!(statement["predicateType"], PREDICATE_TYPE);
+        let subject = statement["subject"].as_array().unwrap();
+        assert_eq!(subject.len(), 1);
+        let digest = subject[0]["digest"]["sha384"].as_str().unwrap();
+        assert_eq!(digest.len(), 96);
+
+        let predicate = statement["predicate"].as_object().unwrap();
+        assert_eq!(predicate["measurement"], digest);
+        assert_eq!(predicate["pcrs"].as_object().unwrap().len(), 24);
+        assert!(predicate["reported_tcb"].is_object());
+        assert_eq!(predicate["report_data"], hex::encode(REPORT_DATA));
+    }
+}
@@ -0,0 +1,222 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Fetching of the VCEK from the AMD Key Distribution Service (KDS) for
+//! evidence which omits it.
+//!
+//! The VCEK is identified by the chip_id and reported_tcb of the SNP report.
+//! Fetched VCEKs are cached in memory by (chip_id, reported_tcb). The
+//! fetched VCEK is verified against the AMD cert chain like an embedded one.
+
+use super::{AzSnpVtpm, Evidence, SnpGeneration, Tcb};
+use anyhow::{bail, Context, Result};
+use async_trait::async_trait;
+use az_snp_vtpm::hcl::HclReport;
+use az_snp_vtpm::report::AttestationReport;
+use openssl::x509::X509;
+use reqwest::StatusCode;
+use serde_json::{Map, Value};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
+const KDS_VCEK: &str = "/vcek/v1";
+
+/// HTTP client of the KDS
+#[async_trait]
+pub trait KdsClient: Send + Sync {
+    /// The body of a successful GET of `url`
+    async fn get(&self, url: &str) -> Result<Vec<u8>>;
+}
+
+/// KDS client over HTTPS
+pub struct HttpsKdsClient;
+
+#[async_trait]
+impl KdsClient for HttpsKdsClient {
+    async fn get(&self, url: &str) -> Result<Vec<u8>> {
+        let response = reqwest::get(url)
+            .await
+            .context("Unable to send request for VCEK")?;
+        match response.status() {
+            StatusCode::OK => Ok(response
+                .bytes()
+                .await
+                .context("Unable to read VCEK")?
+                .to_vec()),
+            status => bail!("Unable to fetch VCEK from {url}: {status}"),
+        }
+    }
+}
+
+/// URL of the VCEK of `generation` for `report`
+fn vcek_url(generation: SnpGeneration, report: &AttestationReport) -> String {
+    let tcb = &report.reported_tcb;
+    format!(
+        "{KDS_CERT_SITE}{KDS_VCEK}/{generation}/{}?blSPL={:02}&teeSPL={:02}&snpSPL={:02}&ucodeSPL={:02}",
+        hex::encode(report.chip_id),
+        tcb.bootloader,
+        tcb.tee,
+        tcb.snp,
+        tcb.microcode
+    )
+}
+
+/// PEM encoded VCEKs by chip_id and raw reported_tcb
+type VcekCache = HashMap<([u8; 64], u64), String>;
+
+/// Fetches the VCEKs of a CPU generation, caching them by chip_id and TCB
+pub(crate) struct VcekFetcher {
+    client: Box<dyn KdsClient>,
+    generation: SnpGeneration,
+    cache: Mutex<VcekCache>,
+}
+
+impl VcekFetcher {
+    /// The PEM encoded VCEK of the chip which issued `report`
+    async fn vcek(&self, report: &AttestationReport) -> Result<String> {
+        let key = (report.chip_id, Tcb::from(&report.reported_tcb).to_raw());
+        if let Some(vcek) = self.lock_cache().get(&key) {
+            return Ok(vcek.clone());
+        }
+
+        let der = self.client.get(&vcek_url(self.generation, report)).await?;
+        let vcek = X509::from_der(&der).context("Malformed VCEK from KDS")?;
+        let pem = String::from_utf8(vcek.to_pem().context("Failed to encode VCEK")?)
+            .context("Failed to encode VCEK")?;
+        self.lock_cache().insert(key, pem.clone());
+        Ok(pem)
+    }
+
+    fn lock_cache(&self) -> MutexGuard<'_, VcekCache> {
+        self.cache.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl AzSnpVtpm {
+    /// Fetch the VCEK of `generation` CPUs from the KDS with `client` when
+    /// the evidence passed to `evaluate` has an empty `vcek`.
+    pub fn with_kds_fetch(
+        mut self,
+        client: impl KdsClient + 'static,
+        generation: SnpGeneration,
+    ) -> Self {
+        self.vcek_fetcher = Some(VcekFetcher {
+            client: Box::new(client),
+            generation,
+            cache: Mutex::new(HashMap::new()),
+        });
+        self
+    }
+
+    /// `evidence` with its VCEK fetched from the KDS, if it omits it
+    pub(crate) async fn with_fetched_vcek<'a>(&self, evidence: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        let Some(fetcher) = &self.vcek_fetcher else {
+            return Ok(Cow::Borrowed(evidence));
+        };
+        let mut envelope: Map<String, Value> = serde_json::from_slice(evidence)
+            .context("Failed to deserialize Azure vTPM SEV-SNP evidence")?;
+        let parsed = Evidence::from_slice(evidence)?;
+        if !parsed.vcek.trim().is_empty() {
+            return Ok(Cow::Borrowed(evidence));
+        }
+
+        let hcl_report = HclReport::new(self.unwrap_report(parsed.report)?)?;
+        let snp_report: AttestationReport = hcl_report.try_into()?;
+        let vcek = fetcher
+            .vcek(&snp_report)
+            .await
+            .context("Failed to fetch the VCEK from the KDS")?;
+        envelope.insert("vcek".into(), Value::String(vcek));
+        Ok(Cow::Owned(serde_json::to_vec(&envelope)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA, VCEK};
+    use super::*;
+    use crate::{InitDataHash, ReportData, Verifier};
+    use std::sync::Arc;
+
+    /// KDS stub serving the fixture VCEK and recording the requests
+    #[derive(Clone, Default)]
+    struct StubKds {
+        requests: Arc<Mutex<Vec<String>>>,
+        unavailable: bool,
+    }
+
+    #[async_trait]
+    impl KdsClient for StubKds {
+        async fn get(&self, url: &str) -> Result<Vec<u8>> {
+            self.requests.lock().unwrap().push(url.to_string());
+            if self.unavailable {
+                bail!("KDS is unavailable");
+            }
+            Ok(X509::from_pem(VCEK.as_bytes()).unwrap().to_der().unwrap())
+        }
+    }
+
+    fn evidence_without_vcek() -> Vec<u8> {
+        let mut evidence: Value = serde_json::from_slice(&evidence_fixture(REPORT)).unwrap();
+        evidence["vcek"] = "".into();
+        serde_json::to_vec(&evidence).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_kds_fetch() {
+        let kds = StubKds::default();
+        let verifier = fixture_verifier().with_kds_fetch(kds.clone(), SnpGeneration::Milan);
+        let evidence = evidence_without_vcek();
+        let evaluate = || {
+            verifier.evaluate(
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+        };
+        evaluate().await.unwrap();
+        evaluate().await.unwrap();
+
+        let requests = kds.requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("https://kdsintf.amd.com/vcek/v1/Milan/"));
+        assert!(requests[0].ends_with("?blSPL=03&teeSPL=00&snpSPL=08&ucodeSPL=115"));
+
+        // an embedded VCEK is not fetched
+        verifier
+            .evaluate(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .await
+            .unwrap();
+        assert_eq!(kds.requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_kds_unavailable() {
+        let kds = StubKds {
+            unavailable: true,
+            ..Default::default()
+        };
+        let evidence = evidence_without_vcek();
+        let report_data = ReportData::Value(REPORT_DATA);
+
+        fixture_verifier()
+            .with_kds_fetch(kds, SnpGeneration::Milan)
+            .evaluate(&evidence, &report_data, &InitDataHash::NotProvided)
+            .await
+            .unwrap_err();
+
+        // without the KDS fetch, the empty VCEK is rejected
+        fixture_verifier()
+            .evaluate(&evidence, &report_data, &InitDataHash::NotProvided)
+            .await
+            .unwrap_err();
+    }
+}
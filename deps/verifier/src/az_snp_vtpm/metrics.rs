@@ -0,0 +1,113 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Verification metrics, recorded with the `metrics` facade to the
+//! installed recorder:
+//!
+//! - `az_snp_vtpm_verifications_total`, the successful verifications
+//! - `az_snp_vtpm_verification_failures_total`, the failed verifications,
+//!   labeled by the failing `step` (`other` if failing outside a step)
+//! - `az_snp_vtpm_step_duration_seconds`, the duration of each `step`
+
+use ::metrics::{counter, histogram};
+use std::cell::Cell;
+use std::time::Duration;
+
+const VERIFICATIONS: &str = "az_snp_vtpm_verifications_total";
+const FAILURES: &str = "az_snp_vtpm_verification_failures_total";
+const STEP_DURATION: &str = "az_snp_vtpm_step_duration_seconds";
+/// Label of the failures outside a verification step
+const OTHER_STEP: &str = "other";
+
+thread_local! {
+    /// The failed step of the verification running on this thread
+    static FAILED_STEP: Cell<Option<&'static str>> = const { Cell::new(None) };
+}
+
+/// Record the duration of the step `name`, and whether it failed
+pub(crate) fn record_step(name: &'static str, elapsed: Duration, passed: bool) {
+    histogram!(STEP_DURATION, "step" => name).record(elapsed.as_secs_f64());
+    if !passed {
+        FAILED_STEP.with(|failed| failed.set(Some(name)));
+    }
+}
+
+/// Run the verification `verify`, counting its success or its failed step.
+/// The failed step of an enclosing verification is kept.
+pub(crate) fn in_verification<T, E>(verify: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let outer = FAILED_STEP.with(Cell::take);
+    let result = verify();
+    let failed = FAILED_STEP.with(|failed| failed.replace(outer));
+    match &result {
+        Ok(_) => counter!(VERIFICATIONS).increment(1),
+        Err(_) => counter!(FAILURES, "step" => failed.unwrap_or(OTHER_STEP)).increment(1),
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+    /// A counter: its name, labels and count
+    type Counter = (String, Vec<String>, u64);
+
+    /// The counters, with their labels, and the labels of the histograms
+    fn snapshot(snapshotter: &Snapshotter) -> (Vec<Counter>, Vec<String>) {
+        let snapshot = snapshotter.snapshot().into_vec();
+        let label = |label: &::metrics::Label| format!("{}={}", label.key(), label.value());
+        let mut counters: Vec<_> = snapshot
+            .iter()
+            .filter_map(|(key, _, _, value)| match value {
+                DebugValue::Counter(count) => Some((
+                    key.key().name().to_string(),
+                    key.key().labels().map(label).collect(),
+                    *count,
+                )),
+                _ => None,
+            })
+            .collect();
+        counters.sort();
+        let histograms = snapshot
+            .iter()
+            .filter(|(_, _, _, value)| matches!(value, DebugValue::Histogram(_)))
+            .flat_map(|(key, _, _, _)| key.key().labels().map(label).collect::<Vec<_>>())
+            .collect();
+        (counters, histograms)
+    }
+
+    #[test]
+    fn test_verification_metrics() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let verifier = fixture_verifier();
+        let evaluate = |report: &[u8]| {
+            verifier.evaluate_blocking(
+                &evidence_fixture(report),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+        };
+        ::metrics::with_local_recorder(&recorder, || {
+            evaluate(REPORT).unwrap();
+            // the SNP report signature doesn't match
+            evaluate(&[&REPORT[..0x1a6], &[0], &REPORT[0x1a7..]].concat()).unwrap_err();
+        });
+
+        let (counters, histograms) = snapshot(&snapshotter);
+        assert_eq!(
+            counters,
+            [
+                (FAILURES.to_string(), vec!["step=snp".to_string()], 1),
+                (VERIFICATIONS.to_string(), vec![], 1),
+            ]
+        );
+        assert!(histograms.contains(&"step=snp".to_string()));
+        assert!(histograms.contains(&"step=nonce".to_string()));
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use super::{parse_vcek, AzSnpVtpm, Evidence, VcekTrust};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{bail, Context, Result};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+/// The inputs that determine the verdict on an evidence, in a canonical
+/// compact form: the quote is bincode encoded, the VCEK is DER and the whole
+/// structure is bincode encoded, instead of the JSON/PEM evidence encoding.
+#[derive(Serialize, Deserialize)]
+struct MinimalEvidence {
+    quote: Vec<u8>,
+    report: Vec<u8>,
+    vcek: Vec<u8>,
+    nonce: Vec<u8>,
+    policy_hash: [u8; 32],
+    pcrs_sha384: Option<Vec<Vec<u8>>>,
+    event_log: Option<Vec<u8>>,
+}
+
+impl AzSnpVtpm {
+    /// The configuration of the checks
+    pub(crate) fn policy(&self) -> Value {
+        json!({
+            "min_tcb": self.min_tcb,
+            "tcb_invariant": self.tcb_invariant,
+            "microcode_policy": self.microcode_policy,
+            "vmpl": self.vmpl,
+            "min_abi": self.min_abi,
+            "guest_policy": self.guest_policy,
+            "init_data_pcr": self.init_data_pcr,
+            "init_data_extend": self.init_data_extend,
+            "expected_pcrs": self
+                .expected_pcrs
+                .iter()
+                .map(|(index, pcr)| (format!("pcr{index:02}"), hex::encode(pcr)))
+                .collect::<BTreeMap<_, _>>(),
+            "secure_boot_pcr7": self
+                .secure_boot_pcr7
+                .as_ref()
+                .map(|enabled| enabled.iter().map(hex::encode).collect::<Vec<_>>()),
+            "strict_var_data_hash": self.strict_var_data_hash,
+            "strict_evidence_fields": self.strict_evidence_fields,
+            "report_data_binding": self.report_data_binding,
+            "allow_unmeasured_launch": self.allow_unmeasured_launch,
+            "allow_empty_report_data": self.allow_empty_report_data,
+            "reference_values": self.reference_values.to_json(),
+            "reference_value_resolver": self.resolver.is_some(),
+            "policy_bundle_version": self.policy_bundle.map(|bundle| bundle.version),
+            "vcek_crl": self.vcek_crl.as_ref().map(|crl| json!({
+                "source": crl.source,
+                "soft_fail": crl.soft_fail,
+            })),
+            "cert_chain_quorum": self.chain_quorum.as_ref().map(|quorum| quorum.to_json()),
+            "pinned_vcek": match &self.vcek_trust {
+                VcekTrust::Chain => None,
+                VcekTrust::PinnedVcek(der) => Some(hex::encode(openssl::sha::sha256(der))),
+            },
+            "ak_pinning": self.ak_pins.as_ref().map(|pins| format!("{:?}", pins.policy())),
+        })
+    }
+
+    /// Hash of the configuration of the checks, so that archived evidence is
+    /// only replayed against the policy it has been verified with.
+    pub fn policy_hash(&self) -> [u8; 32] {
+        openssl::sha::sha256(self.policy().to_string().as_bytes())
+    }
+
+    /// Encode the minimal set of bytes needed to reproduce the verdict on
+    /// `evidence` for the nonce `expected_report_data`, for archival.
+    pub fn minimal_evidence(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &[u8],
+    ) -> Result<Vec<u8>> {
+        let evidence = Evidence::from_slice(evidence)?;
+        let vcek = parse_vcek(&evidence.vcek)?.0;
+
+        let minimal = MinimalEvidence {
+            quote: bincode::serialize(&evidence.quote)?,
+            report: evidence.report,
+            vcek: vcek.to_der()?,
+            nonce: expected_report_data.to_vec(),
+            policy_hash: self.policy_hash(),
+            pcrs_sha384: evidence.pcrs_sha384,
+            event_log: evidence.event_log,
+        };
+        Ok(bincode::serialize(&minimal)?)
+    }
+
+    /// Verify evidence archived with `minimal_evidence`. The verifier must be
+    /// configured with the same policy as the one which archived it.
+    pub fn verify_minimal_evidence(
+        &self,
+        minimal: &[u8],
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let minimal: MinimalEvidence =
+            bincode::deserialize(minimal).context("Failed to deserialize minimal evidence")?;
+        if minimal.policy_hash != self.policy_hash() {
+            bail!("Minimal evidence has been archived under a different policy");
+        }
+
+        let vcek = X509::from_der(&minimal.vcek).context("Failed to parse VCEK")?;
+        let evidence = Evidence {
+            quote: bincode::deserialize(&minimal.quote)?,
+            report: minimal.report,
+            vcek: String::from_utf8(vcek.to_pem()?)?,
+            pcrs_sha384: minimal.pcrs_sha384,
+            event_log: minimal.event_log,
+        };
+        self.verify_evidence(
+            &serde_json::to_vec(&evidence)?,
+            &ReportData::Value(&minimal.nonce),
+            expected_init_data_hash,
+            None,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+
+    #[test]
+    fn test_verify_minimal_evidence() {
+        let verifier = fixture_verifier();
+        let evidence = evidence_fixture(REPORT);
+        let claim = verifier
+            .verify_evidence(
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+
+        let minimal = verifier.minimal_evidence(&evidence, REPORT_DATA).unwrap();
+        assert!(minimal.len() < evidence.len());
+
+        let replayed = verifier
+            .verify_minimal_evidence(&minimal, &InitDataHash::NotProvided)
+            .unwrap();
+        assert_eq!(claim, replayed);
+
+        // replaying under a different policy is refused
+        let verifier = verifier.with_tcb_invariant_check(true);
+        verifier
+            .verify_minimal_evidence(&minimal, &InitDataHash::NotProvided)
+            .unwrap_err();
+    }
+}
@@ -3,11 +3,51 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
+pub use self::ak_pin::AkChangePolicy;
+use self::ak_pin::AkPins;
+pub use self::builder::AzSnpVtpmBuilder;
+use self::bundle::AppliedBundle;
+pub use self::bundle::PolicyBundle;
+pub use self::chain::{chain_digest, chained_nonce, verify_chain_link, CHAIN_GENESIS};
+pub use self::channel::{verify_hkdf_nonce, HkdfNonce};
+pub use self::claims::{AkPub, AzSnpVtpmClaims};
+pub use self::clock::{Clock, FixedClock, SystemClock, VerificationTime};
+pub use self::crl::CrlSource;
+use self::crl::VcekCrl;
+pub use self::eventlog::MeasuredComponent;
+use self::events::EventEmitter;
+pub use self::events::{EventSink, EventVerdict, FieldChange, VerificationEvent};
+pub use self::generation::SnpGeneration;
+pub use self::heartbeat::HeartbeatVerifier;
+pub use self::identity::{device_identity, DeviceIdentity, IdentityField};
+pub use self::inspect::VerboseVerification;
+#[cfg(feature = "kds-fetch")]
+pub use self::kds::{HttpsKdsClient, KdsClient};
+pub use self::policy::{CheckKind, RequiredClaim};
+pub use self::posture::{posture_score, PostureScore};
+pub use self::provenance::{ProvenanceRecord, ProvenanceRecorder};
+pub use self::quorum::CertChainSource;
+use self::quorum::ChainQuorum;
+use self::reference::{verify_expected_measurements, CachingResolver};
+pub use self::reference::{ReferenceValueResolver, ReferenceValues};
+pub use self::session::{SessionId, SessionVerifier};
+use self::steps::step;
+pub use self::tcb::{AbiVersion, GuestPolicyConstraints, MicrocodePolicy, Tcb};
+pub use self::transcript::{StepStatus, VerificationReport, VerificationStep};
+pub use self::verdict::{Verdict, VerdictCache};
 use super::{TeeEvidenceParsedClaim, Verifier};
+use crate::az_vtpm::tpm::PcrBank;
+use crate::az_vtpm::vtpm::{
+    ak_pub_der, extend_claim, sha256_pcrs_by_index, sha384_pcrs_by_index, verify_nonce,
+    verify_nonce_segments, verify_pcrs, verify_quote_signature, verify_signature,
+};
+use crate::az_vtpm::{claim_map, verify_init_data, VtpmError, INITDATA_PCR};
+pub use crate::az_vtpm::{expected_init_data_pcr, InitDataExtend};
 use crate::snp::{
-    load_milan_cert_chain, parse_tee_evidence, verify_report_signature, VendorCertificates,
+    endorsement_key_type, load_milan_cert_chain, parse_tee_evidence, verify_report_signature,
+    verify_report_signed_by, verify_report_version, VendorCertificates,
 };
-use crate::{InitDataHash, ReportData};
+use crate::{ExpectedMeasurements, InitDataHash, ReportData, VerifyFailure};
 use anyhow::{bail, Context, Result};
 use async_trait::async_trait;
 use az_snp_vtpm::certs::Vcek;
@@ -15,25 +55,261 @@ use az_snp_vtpm::hcl::HclReport;
 use az_snp_vtpm::report::AttestationReport;
 use az_snp_vtpm::vtpm::Quote;
 use az_snp_vtpm::vtpm::QuoteError;
-use log::debug;
-use openssl::pkey::PKey;
+use az_snp_vtpm::vtpm::VerifyError;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use byteorder::{ByteOrder, LittleEndian};
+use log::{debug, warn};
+use openssl::asn1::Asn1Time;
+use openssl::pkey::{PKey, Public};
+use openssl::x509::{X509Ref, X509};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use sev::firmware::host::{CertTableEntry, CertType};
+use serde_json::{json, Value};
+use sev::firmware::host::CertTableEntry;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 
+mod ak_pin;
+#[cfg(feature = "rayon")]
+mod batch;
+mod builder;
+mod bundle;
+mod candidates;
+mod chain;
+mod channel;
+mod claims;
+mod clock;
+#[cfg(feature = "cms")]
+mod cms;
+mod crl;
+#[cfg(feature = "ear-claims")]
+pub mod ear;
+mod eventlog;
+mod events;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+mod generation;
+mod heartbeat;
+mod identity;
+mod inspect;
+#[cfg(feature = "intoto")]
+pub mod intoto;
+#[cfg(feature = "kds-fetch")]
+mod kds;
+#[cfg(feature = "metrics")]
+mod metrics;
+mod minimal;
+mod nested;
+mod policy;
+mod posture;
+mod provenance;
+mod quorum;
+mod reference;
+mod self_test;
+mod session;
+#[cfg(feature = "signed-result")]
+mod signed_result;
+mod steps;
+mod tcb;
+#[cfg(feature = "tracing")]
+mod trace_context;
+mod transcript;
+mod verdict;
+
 const HCL_VMPL_VALUE: u32 = 0;
-const INITDATA_PCR: usize = 8;
+/// Offset of the SNP report in the HCL report, after the HCL header
+const HCL_SNP_REPORT_OFFSET: usize = 0x20;
+/// Offset of the VMPL in the SNP report
+const SNP_VMPL_OFFSET: usize = 0x30;
+/// Magic of the HCL header
+const HCL_MAGIC: &[u8; 4] = b"HCLA";
+/// Size of the SNP report in the HCL report
+const SNP_REPORT_SIZE: usize = 0x4a0;
+/// Size of the runtime data header following the SNP report, before the
+/// var_data
+const HCL_RUNTIME_HEADER_SIZE: usize = 20;
+/// Hardware report type of an SNP report in the runtime data header
+const HCL_REPORT_TYPE_SNP: u32 = 2;
+/// PCR measuring the Secure Boot state and keys
+const SECURE_BOOT_PCR: usize = 7;
+/// PCRs of a bank of the vTPM
+pub(crate) const PCR_COUNT: usize = 24;
+
+/// What the VCEK of the evidence is trusted for
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum VcekTrust {
+    /// The VCEK must be endorsed by the AMD cert chain of its generation
+    #[default]
+    Chain,
+    /// The VCEK must be this DER encoded certificate, its chain isn't
+    /// verified. Only meant for single-host test setups.
+    PinnedVcek(Vec<u8>),
+}
+
+/// How the caller's expected report_data is bound to the evidence
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportDataBinding {
+    /// The expected report_data is the nonce of the vTPM quote, and the SNP
+    /// report_data binds the HCL var_data (and thus the AK) to the report.
+    /// The AK which verifies the quote must be the one of this var_data,
+    /// even if an external var_data hash is provided. The expected
+    /// report_data must be exactly the nonce, it isn't padded to the 64
+    /// bytes of the SNP report_data field.
+    #[default]
+    QuoteNonce,
+    /// Cross-TEE convention: the expected report_data is a public key and
+    /// the full 64 bytes of the SNP report_data must be its sha512. The HCL
+    /// var_data binding and the quote nonce are not checked, so the vTPM
+    /// claims are not bound to the SNP report in this mode.
+    KeySha512,
+}
+
+/// Versions of the evidence format this build understands
+const EVIDENCE_VERSIONS: &[u32] = &[1];
+
+/// Top-level fields of the evidence, including the envelope ones of
+/// `EvidenceEnvelope` and of the trace context
+const EVIDENCE_FIELDS: &[&str] = &[
+    "version",
+    "traceparent",
+    "quote",
+    "report",
+    "vcek",
+    "pcrs_sha384",
+    "event_log",
+];
+
+/// Encoding of an evidence
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvidenceFormat {
+    #[default]
+    Json,
+    /// CBOR with the fields of the JSON evidence, sparing gateways the JSON
+    /// decoding of the report and the VCEK
+    #[cfg(feature = "cbor")]
+    Cbor,
+}
+
+impl EvidenceFormat {
+    /// The format of a content type, e.g. `application/cbor`
+    pub fn from_content_type(content_type: &str) -> Result<Self> {
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        match media_type {
+            "application/json" => Ok(Self::Json),
+            #[cfg(feature = "cbor")]
+            "application/cbor" => Ok(Self::Cbor),
+            _ => bail!("Unsupported evidence content type {content_type}"),
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(&self, evidence: &[u8]) -> Result<T> {
+        let value = match self {
+            Self::Json => serde_json::from_slice(evidence)?,
+            #[cfg(feature = "cbor")]
+            Self::Cbor => ciborium::from_reader(evidence)?,
+        };
+        Ok(value)
+    }
+}
+
+/// The version of an evidence, ignoring the rest of it
+#[derive(Deserialize)]
+struct EvidenceEnvelope {
+    /// Absent from the unversioned evidence, version 1
+    version: Option<u32>,
+}
 
 #[derive(Serialize, Deserialize)]
 struct Evidence {
     quote: Quote,
     report: Vec<u8>,
     vcek: String,
+    /// Values of the SHA-384 PCRs selected by the quote, if it selects a
+    /// SHA-384 bank next to the SHA-256 one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pcrs_sha384: Option<Vec<Vec<u8>>>,
+    /// TCG2 event log of the vTPM
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event_log: Option<Vec<u8>>,
+}
+
+impl Evidence {
+    /// Parse a JSON evidence of a version this build understands
+    fn from_slice(evidence: &[u8]) -> Result<Self> {
+        Self::parse(evidence, EvidenceFormat::Json)
+    }
+
+    /// Parse an evidence of a version this build understands
+    fn parse(evidence: &[u8], format: EvidenceFormat) -> Result<Self> {
+        let envelope = format
+            .deserialize::<EvidenceEnvelope>(evidence)
+            .context("Failed to deserialize Azure vTPM SEV-SNP evidence")?;
+        let version = envelope.version.unwrap_or(1);
+        if !EVIDENCE_VERSIONS.contains(&version) {
+            return Err(CertError::UnsupportedEvidenceVersion {
+                found: version,
+                supported: EVIDENCE_VERSIONS,
+            }
+            .into());
+        }
+        format
+            .deserialize(evidence)
+            .context("Failed to deserialize Azure vTPM SEV-SNP evidence")
+    }
+
+    /// Reject the top-level fields of `evidence` missing from
+    /// `EVIDENCE_FIELDS`
+    fn check_fields(evidence: &[u8], format: EvidenceFormat) -> Result<()> {
+        let fields: BTreeMap<String, serde::de::IgnoredAny> = format
+            .deserialize(evidence)
+            .context("Failed to deserialize Azure vTPM SEV-SNP evidence")?;
+        if let Some(field) = fields
+            .keys()
+            .find(|field| !EVIDENCE_FIELDS.contains(&field.as_str()))
+        {
+            return Err(CertError::UnknownEvidenceField(field.clone()).into());
+        }
+        Ok(())
+    }
 }
 
 pub struct AzSnpVtpm {
-    vendor_certs: VendorCertificates,
+    vendor_certs: BTreeMap<SnpGeneration, VendorCertificates>,
+    min_tcb: Option<Tcb>,
+    tcb_invariant: bool,
+    microcode_policy: Option<MicrocodePolicy>,
+    vmpl: u32,
+    min_abi: Option<AbiVersion>,
+    guest_policy: GuestPolicyConstraints,
+    expected_pcrs: HashMap<usize, [u8; 32]>,
+    secure_boot_pcr7: Option<Vec<[u8; 32]>>,
+    init_data_pcr: usize,
+    init_data_extend: InitDataExtend,
+    strict_var_data_hash: bool,
+    strict_evidence_fields: bool,
+    report_data_binding: ReportDataBinding,
+    allow_unmeasured_launch: bool,
+    allow_empty_report_data: bool,
+    ak_pins: Option<AkPins>,
+    reference_values: ReferenceValues,
+    resolver: Option<CachingResolver>,
+    clock: Arc<dyn Clock>,
+    policy_signing_key: Option<PKey<Public>>,
+    policy_bundle: Option<AppliedBundle>,
+    vcek_crl: Option<VcekCrl>,
+    events: Option<EventEmitter>,
+    chain_quorum: Option<ChainQuorum>,
+    vcek_trust: VcekTrust,
+    #[cfg(feature = "kds-fetch")]
+    vcek_fetcher: Option<kds::VcekFetcher>,
+    #[cfg(feature = "signed-result")]
+    result_signer: Option<signed_result::ResultSigner>,
+    #[cfg(feature = "cms")]
+    transport_cert: Option<openssl::x509::X509>,
 }
 
 #[derive(Error, Debug)]
@@ -42,10 +318,116 @@ pub enum CertError {
     LoadMilanCert,
     #[error("TPM quote nonce doesn't match expected report_data")]
     NonceMismatch,
+    #[error("TPM quote nonce is {actual} bytes, expected report_data is {expected} bytes")]
+    NonceLength { expected: usize, actual: usize },
+    #[error("TPM quote nonce doesn't match the report_data segment at offset {offset}")]
+    NonceSegmentMismatch { offset: usize },
     #[error("SNP report report_data mismatch")]
     SnpReportMismatch,
-    #[error("VMPL of SNP report is not {0}")]
-    VmplIncorrect(u32),
+    #[error("Externally provided var_data hash doesn't match the HCL report's var_data")]
+    VarDataHashMismatch,
+    #[error("Evidence version {found} is not supported, supported versions are {supported:?}")]
+    UnsupportedEvidenceVersion {
+        found: u32,
+        supported: &'static [u32],
+    },
+    #[error("Evidence has the unknown field `{0}`")]
+    UnknownEvidenceField(String),
+    #[error("Malformed HCL report: {0}")]
+    MalformedHclReport(String),
+    #[error("The AKpub verifying the quote isn't bound to the SNP report by the HCL var_data")]
+    AkNotBound,
+    #[error("Recomputed PCR digest does not match the digest in Quote")]
+    PcrDigestMismatch,
+    #[error("PCR{index:02} is {actual}, expected {expected}")]
+    PcrMismatch {
+        index: usize,
+        expected: String,
+        actual: String,
+    },
+    #[error("Secure Boot isn't enabled, PCR07 is {0}")]
+    SecureBootDisabled(String),
+    #[error(
+        "VCEK has been issued for {component} SVN {vcek}, SNP report reported_tcb has {reported}"
+    )]
+    VcekTcbMismatch {
+        component: &'static str,
+        vcek: u8,
+        reported: u8,
+    },
+    #[error("SNP report reported_tcb {reported:?} is below the TCB floor {min:?} in {component}")]
+    TcbTooLow {
+        component: &'static str,
+        min: Tcb,
+        reported: Tcb,
+    },
+    #[error(
+        "SNP report committed_tcb {committed:?} is above current_tcb {current:?} in {component}"
+    )]
+    TcbInvariant {
+        component: &'static str,
+        committed: Tcb,
+        current: Tcb,
+    },
+    #[error("SNP report microcode SVN {0} is not allowed")]
+    MicrocodeNotAllowed(u8),
+    #[error("SNP guest policy ABI {reported} is below the minimum ABI {min}")]
+    AbiTooLow {
+        min: AbiVersion,
+        reported: AbiVersion,
+    },
+    #[error("SNP guest policy bit {0} is set, but the guest policy constraints forbid it")]
+    GuestPolicyBitSet(&'static str),
+    #[error("Quote carries {found} {bank} PCRs, but selects {selected}")]
+    PcrSelectionMismatch {
+        bank: &'static str,
+        selected: usize,
+        found: usize,
+    },
+    #[error("Replay of the event log doesn't reproduce PCR{0:02}")]
+    EventLogMismatch(usize),
+    #[error("VCEK with serial {0} has been revoked")]
+    VcekRevoked(String),
+    #[error("VCEK CRL is unavailable: {0}")]
+    CrlUnavailable(String),
+    #[error(
+        "Only {} of the required {required} cert chain sources validate the VCEK",
+        .agreed.len()
+    )]
+    ChainQuorumNotMet {
+        agreed: Vec<String>,
+        required: usize,
+    },
+    #[error("No AMD cert chain configured for {0} VCEKs")]
+    MissingVendorCerts(SnpGeneration),
+    #[error("VCEK is not valid at the time of verification")]
+    VcekNotValid,
+    #[error("{0} is not valid at the time of verification")]
+    VendorCertNotValid(&'static str),
+    #[error("SNP report launch measurement is all zero, the guest is unmeasured")]
+    UnmeasuredLaunch,
+    #[error("{0} doesn't match any reference value")]
+    ReferenceValueMismatch(RequiredClaim),
+    #[error("Signature of the policy bundle is invalid")]
+    PolicyBundleSignature,
+    #[error("Policy bundle version {0} has expired")]
+    PolicyBundleExpired(u64),
+    #[error("Policy bundle version {version} is older than the applied version {applied}")]
+    PolicyBundleRollback { applied: u64, version: u64 },
+    #[error("Unknown or expired attestation session")]
+    UnknownSession,
+    #[error("AK of the device doesn't match its pinned AK")]
+    AkChanged,
+    #[error("VMPL of SNP report is {reported}, expected {expected}")]
+    VmplIncorrect { expected: u32, reported: u32 },
+    #[error("Self-test verification of the embedded evidence failed")]
+    SelfTest(#[source] anyhow::Error),
+    #[error("VCEK isn't the pinned VCEK")]
+    VcekNotPinned,
+    #[error("SNP report is not endorsed by the VCEK")]
+    SnpReportSignature(#[source] anyhow::Error),
+    #[error("Expected init_data digest is different from the content of PCR{0}")]
+    InitDataMismatch(usize),
     #[error(transparent)]
     Quote(#[from] QuoteError),
     #[error(transparent)]
@@ -54,202 +436,2274 @@ pub enum CertError {
     Anyhow(#[from] anyhow::Error),
 }
 
+impl CertError {
+    /// The class of the failure, if it is one of `VerifyFailure`
+    pub fn failure(&self) -> Option<VerifyFailure> {
+        match self {
+            Self::NonceMismatch
+            | Self::NonceLength { .. }
+            | Self::NonceSegmentMismatch { .. }
+            | Self::SnpReportMismatch
+            | Self::VarDataHashMismatch
+            | Self::AkNotBound => Some(VerifyFailure::ReportData),
+            Self::PcrDigestMismatch
+            | Self::PcrMismatch { .. }
+            | Self::SecureBootDisabled(_)
+            | Self::EventLogMismatch(_) => Some(VerifyFailure::Pcr),
+            Self::SnpReportSignature(_) | Self::VcekTcbMismatch { .. } | Self::VcekNotPinned => {
+                Some(VerifyFailure::Signature)
+            }
+            Self::InitDataMismatch(_) => Some(VerifyFailure::InitData),
+            Self::VmplIncorrect { .. } => Some(VerifyFailure::Vmpl),
+            Self::Quote(_)
+            | Self::MalformedHclReport(_)
+            | Self::UnsupportedEvidenceVersion { .. }
+            | Self::UnknownEvidenceField(_) => Some(VerifyFailure::Deserialization),
+            Self::Anyhow(e) => classify_error(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<VtpmError> for CertError {
+    fn from(error: VtpmError) -> Self {
+        match error {
+            VtpmError::NonceMismatch => Self::NonceMismatch,
+            VtpmError::NonceLength { expected, actual } => Self::NonceLength { expected, actual },
+            VtpmError::NonceSegmentMismatch { offset } => Self::NonceSegmentMismatch { offset },
+            VtpmError::PcrDigestMismatch => Self::PcrDigestMismatch,
+            VtpmError::PcrSelectionMismatch {
+                bank,
+                selected,
+                found,
+            } => Self::PcrSelectionMismatch {
+                bank,
+                selected,
+                found,
+            },
+            VtpmError::InitDataMismatch(pcr) => Self::InitDataMismatch(pcr),
+            VtpmError::Quote(e) => Self::Quote(e),
+            VtpmError::Anyhow(e) => Self::Anyhow(e),
+        }
+    }
+}
+
+/// A failed vTPM check as its `CertError`, keeping the untyped errors as
+/// they are
+fn vtpm_error(error: VtpmError) -> anyhow::Error {
+    match error {
+        VtpmError::Anyhow(e) => e,
+        e => CertError::from(e).into(),
+    }
+}
+
+/// The class of an error of `evaluate`
+fn classify_error(error: &anyhow::Error) -> Option<VerifyFailure> {
+    if let Some(e) = error.downcast_ref::<CertError>() {
+        return e.failure();
+    }
+    if let Some(e) = error.downcast_ref::<VerifyError>() {
+        return match e {
+            VerifyError::SignatureMismatch => Some(VerifyFailure::Signature),
+            VerifyError::PcrMismatch => Some(VerifyFailure::Pcr),
+            _ => None,
+        };
+    }
+    if error.downcast_ref::<serde_json::Error>().is_some()
+        || error.downcast_ref::<bincode::Error>().is_some()
+    {
+        return Some(VerifyFailure::Deserialization);
+    }
+    #[cfg(feature = "cbor")]
+    if error
+        .downcast_ref::<ciborium::de::Error<std::io::Error>>()
+        .is_some()
+    {
+        return Some(VerifyFailure::Deserialization);
+    }
+    None
+}
+
 impl AzSnpVtpm {
+    /// The Milan cert chain is parsed once per process, the verifiers share
+    /// its certificates.
     pub fn new() -> Result<Self, CertError> {
         let Result::Ok(vendor_certs) = load_milan_cert_chain() else {
             return Err(CertError::LoadMilanCert);
         };
-        let vendor_certs = vendor_certs.clone();
-        Ok(Self { vendor_certs })
+        let vendor_certs = BTreeMap::from([(SnpGeneration::Milan, vendor_certs.clone())]);
+        Ok(Self {
+            vendor_certs,
+            min_tcb: None,
+            tcb_invariant: false,
+            microcode_policy: None,
+            vmpl: HCL_VMPL_VALUE,
+            min_abi: None,
+            guest_policy: GuestPolicyConstraints::default(),
+            expected_pcrs: HashMap::new(),
+            secure_boot_pcr7: None,
+            init_data_pcr: INITDATA_PCR,
+            init_data_extend: InitDataExtend::Zeroed,
+            strict_var_data_hash: false,
+            strict_evidence_fields: false,
+            report_data_binding: ReportDataBinding::QuoteNonce,
+            allow_unmeasured_launch: false,
+            allow_empty_report_data: false,
+            ak_pins: None,
+            reference_values: ReferenceValues::new(),
+            resolver: None,
+            clock: Arc::new(SystemClock),
+            policy_signing_key: None,
+            policy_bundle: None,
+            vcek_crl: None,
+            events: None,
+            chain_quorum: None,
+            vcek_trust: VcekTrust::Chain,
+            #[cfg(feature = "kds-fetch")]
+            vcek_fetcher: None,
+            #[cfg(feature = "signed-result")]
+            result_signer: None,
+            #[cfg(feature = "cms")]
+            transport_cert: None,
+        })
     }
-}
 
-pub(crate) fn extend_claim(claim: &mut TeeEvidenceParsedClaim, quote: &Quote) -> Result<()> {
-    let Value::Object(ref mut map) = claim else {
-        bail!("failed to extend the claim, not an object");
-    };
-    let pcrs: Vec<&[u8; 32]> = quote.pcrs_sha256().collect();
-    let mut tpm_values = serde_json::Map::new();
-    for (i, pcr) in pcrs.iter().enumerate() {
-        tpm_values.insert(format!("pcr{:02}", i), Value::String(hex::encode(pcr)));
+    /// Require every component of the SNP report's reported_tcb to be at
+    /// least the one of `min_tcb`.
+    pub fn with_min_tcb(mut self, min_tcb: Tcb) -> Self {
+        self.min_tcb = Some(min_tcb);
+        self
     }
-    map.insert("tpm".to_string(), Value::Object(tpm_values));
-    map.insert(
-        "init_data".into(),
-        Value::String(hex::encode(pcrs[INITDATA_PCR])),
-    );
-    map.insert(
-        "report_data".into(),
-        Value::String(hex::encode(quote.nonce()?)),
-    );
-    Ok(())
-}
 
-#[async_trait]
-impl Verifier for AzSnpVtpm {
-    /// The following verification steps are performed:
-    /// 1. TPM Quote has been signed by AK included in the HCL variable data
-    /// 2. Attestation report_data matches TPM Quote nonce
-    /// 3. TPM PCRs' digest matches the digest in the Quote
-    /// 4. SNP report's report_data field matches hashed HCL variable data
-    /// 5. SNP Report is genuine
-    /// 6. SNP Report has been issued in VMPL 0
-    /// 7. Init data hash matches TPM PCR[INITDATA_PCR]
-    async fn evaluate(
+    /// Like `with_min_tcb`, with the floor in AMD's raw 64-bit encoding.
+    pub fn with_min_tcb_raw(self, raw: u64) -> Result<Self> {
+        Ok(self.with_min_tcb(Tcb::from_raw(raw)?))
+    }
+
+    /// Require every component of the SNP report's committed_tcb to be at
+    /// most the one of its current_tcb, which a genuine firmware never
+    /// violates. Disabled by default.
+    pub fn with_tcb_invariant_check(mut self, check: bool) -> Self {
+        self.tcb_invariant = check;
+        self
+    }
+
+    /// Trust the VCEK following `trust`, by default its AMD cert chain
+    pub fn with_vcek_trust(mut self, trust: VcekTrust) -> Self {
+        self.vcek_trust = trust;
+        self
+    }
+
+    /// Use `clock` instead of the system clock for the time-based checks.
+    /// These include the validity period of the VCEK, so an expired VCEK
+    /// is rejected, by default as soon as the system clock is past it.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Verify the evidence at `time`, by default the system time. Same as
+    /// `with_clock` with a clock which doesn't move.
+    pub fn with_verification_time(self, time: VerificationTime) -> Self {
+        self.with_clock(Arc::new(time))
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    /// Constrain the microcode SVN of the SNP report's reported_tcb,
+    /// independently of `with_min_tcb`.
+    pub fn with_microcode_policy(mut self, policy: MicrocodePolicy) -> Self {
+        self.microcode_policy = Some(policy);
+        self
+    }
+
+    /// Require SNP reports to be issued at `vmpl` instead of VMPL 0, e.g.
+    /// for guest software running at VMPL 1 or 2 under a paravisor.
+    pub fn with_vmpl(mut self, vmpl: u32) -> Self {
+        self.vmpl = vmpl;
+        self
+    }
+
+    /// Require the minimum firmware ABI version set in the SNP guest policy
+    /// to be at least `min_abi`. As the firmware refuses to launch a guest
+    /// whose policy requires a newer ABI, this also guarantees the firmware
+    /// supports `min_abi`.
+    pub fn with_min_abi(mut self, min_abi: AbiVersion) -> Self {
+        self.min_abi = Some(min_abi);
+        self
+    }
+
+    /// Require the SNP guest policy to satisfy `constraints`, e.g. to reject
+    /// guests which can be debugged.
+    pub fn with_guest_policy(mut self, constraints: GuestPolicyConstraints) -> Self {
+        self.guest_policy = constraints;
+        self
+    }
+
+    /// Require the SHA-256 PCRs of the quote to have the values of
+    /// `expected_pcrs`, by PCR index. The PCRs which are not listed, e.g. all
+    /// but PCR4 and PCR7 to pin Secure Boot only, are not checked.
+    pub fn with_expected_pcrs(mut self, expected_pcrs: HashMap<usize, [u8; 32]>) -> Self {
+        self.expected_pcrs = expected_pcrs;
+        self
+    }
+
+    /// Require Secure Boot, attested by PCR7 having one of `enabled_pcr7`,
+    /// the PCR7 values of the guest images booted with Secure Boot enabled.
+    /// Unlike `with_expected_pcrs`, several images may be accepted.
+    pub fn with_secure_boot_required(mut self, enabled_pcr7: Vec<[u8; 32]>) -> Self {
+        self.secure_boot_pcr7 = Some(enabled_pcr7);
+        self
+    }
+
+    /// Expect the init_data to be bound to PCR `init_data_pcr` instead of
+    /// PCR8, e.g. PCR9 or a resettable PCR of the guest configuration.
+    pub fn with_init_data_pcr(mut self, init_data_pcr: usize) -> Self {
+        self.init_data_pcr = init_data_pcr;
+        self
+    }
+
+    /// Expect the guest to extend its init_data hash following `extend`.
+    /// Defaults to `InitDataExtend::Zeroed`.
+    pub fn with_init_data_extend(mut self, extend: InitDataExtend) -> Self {
+        self.init_data_extend = extend;
+        self
+    }
+
+    /// Require an externally provided var_data hash to equal the hash of the
+    /// HCL report's var_data. Disabled by default, in which case a mismatch
+    /// is only recorded in the `external_var_data_hash_matched` claim. With
+    /// the `ReportDataBinding::QuoteNonce` binding, the SNP report_data must
+    /// bind the HCL report's own var_data, and thus the AK, either way.
+    pub fn with_strict_var_data_hash(mut self, strict: bool) -> Self {
+        self.strict_var_data_hash = strict;
+        self
+    }
+
+    /// Reject evidence with top-level fields this build doesn't know of.
+    /// Disabled by default: an older verifier then accepts the evidence of a
+    /// newer attester, ignoring its additions, at the cost of a typo or a
+    /// schema drift of the attester going unnoticed, e.g. a misspelled
+    /// optional `pcrs_sha384` silently disabling the checks of its bank.
+    pub fn with_strict_evidence_fields(mut self, strict: bool) -> Self {
+        self.strict_evidence_fields = strict;
+        self
+    }
+
+    /// Select how the expected report_data is bound to the evidence.
+    /// Defaults to `ReportDataBinding::QuoteNonce`.
+    pub fn with_report_data_binding(mut self, binding: ReportDataBinding) -> Self {
+        self.report_data_binding = binding;
+        self
+    }
+
+    /// Accept SNP reports whose launch measurement is all zero. This should
+    /// only be needed in test setups, the check is enabled by default.
+    pub fn with_unmeasured_launch_allowed(mut self, allowed: bool) -> Self {
+        self.allow_unmeasured_launch = allowed;
+        self
+    }
+
+    /// Accept `ReportData::NotProvided`, skipping the checks of the expected
+    /// report_data, like those of an init_data hash which isn't provided.
+    /// Only for flows binding the evidence otherwise, e.g. with runtime data.
+    /// The claim records whether the report_data has been checked in
+    /// `report_data_checked`. Empty report_data is rejected by default.
+    pub fn with_empty_report_data_allowed(mut self, allowed: bool) -> Self {
+        self.allow_empty_report_data = allowed;
+        self
+    }
+
+    /// Expect the HCL report of the evidence to be wrapped in a CMS SignedData
+    /// envelope (DER) signed with `cert`, which is verified and removed
+    /// before the HCL report is parsed.
+    #[cfg(feature = "cms")]
+    pub fn with_cms_transport_cert(mut self, cert: openssl::x509::X509) -> Self {
+        self.transport_cert = Some(cert);
+        self
+    }
+
+    /// The HCL report of `report`, unwrapped from its CMS envelope if a
+    /// transport cert is configured, after checking the VMPL of its SNP
+    /// report
+    fn unwrap_report(&self, report: Vec<u8>) -> Result<Vec<u8>> {
+        #[cfg(feature = "cms")]
+        let report = match &self.transport_cert {
+            Some(cert) => cms::unwrap_signed_report(&report, cert)?,
+            None => report,
+        };
+        validate_hcl_report(&report)?;
+        step("vmpl", || verify_hcl_vmpl(&report, self.vmpl))?;
+        Ok(report)
+    }
+
+    /// Same as `evaluate`, without an async runtime. A VCEK CRL read from a
+    /// URL isn't fetched, it must have been fetched with `refresh_vcek_crl`,
+    /// and the VCEK is never fetched from the KDS.
+    pub fn evaluate_blocking(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        self.evaluate_claims(evidence, expected_report_data, expected_init_data_hash)?
+            .try_into()
+    }
+
+    /// Same as `evaluate_blocking`, for an evidence encoded in `format`
+    pub fn evaluate_format(
+        &self,
+        evidence: &[u8],
+        format: EvidenceFormat,
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let claims: AzSnpVtpmClaims = self
+            .verify_evidence_as(
+                evidence,
+                format,
+                expected_report_data,
+                expected_init_data_hash,
+                None,
+                None,
+            )?
+            .try_into()?;
+        claims.try_into()
+    }
+
+    /// Same as `evaluate`, but with a var_data hash computed by a trusted
+    /// frontend. The claim records in `external_var_data_hash_matched`
+    /// whether it equals the hash of the HCL report's var_data, a mismatch
+    /// is rejected with `with_strict_var_data_hash`.
+    pub fn evaluate_with_var_data_hash(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        var_data_hash: &[u8; 32],
+    ) -> Result<TeeEvidenceParsedClaim> {
+        self.verify_evidence(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            Some(var_data_hash),
+        )
+    }
+
+    /// Same as `evaluate_blocking`, but when `expected_runtime_data` is set,
+    /// the HCL var_data, the runtime data declared by the guest (e.g. a
+    /// public key for key wrapping) whose sha256 is in the SNP report_data,
+    /// must be exactly it. Needs the `ReportDataBinding::QuoteNonce` binding.
+    pub fn evaluate_with_runtime_data(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        expected_runtime_data: Option<Vec<u8>>,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        if expected_runtime_data.is_some()
+            && self.report_data_binding != ReportDataBinding::QuoteNonce
+        {
+            bail!("Runtime data is only bound to the SNP report_data with the quote nonce binding");
+        }
+        self.verify_evidence_as(
+            evidence,
+            EvidenceFormat::Json,
+            expected_report_data,
+            expected_init_data_hash,
+            None,
+            expected_runtime_data.as_deref(),
+        )
+    }
+
+    fn verify_evidence(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        external_var_data_hash: Option<&[u8; 32]>,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        self.verify_evidence_as(
+            evidence,
+            EvidenceFormat::Json,
+            expected_report_data,
+            expected_init_data_hash,
+            external_var_data_hash,
+            None,
+        )
+    }
+
+    fn verify_evidence_as(
         &self,
         evidence: &[u8],
+        format: EvidenceFormat,
         expected_report_data: &ReportData,
         expected_init_data_hash: &InitDataHash,
+        external_var_data_hash: Option<&[u8; 32]>,
+        expected_runtime_data: Option<&[u8]>,
     ) -> Result<TeeEvidenceParsedClaim> {
-        let ReportData::Value(expected_report_data) = expected_report_data else {
-            bail!("unexpected empty report data");
+        let verify = || {
+            self.deserialize_and_verify(
+                evidence,
+                format,
+                expected_report_data,
+                expected_init_data_hash,
+                external_var_data_hash,
+                expected_runtime_data,
+            )
         };
+        #[cfg(feature = "metrics")]
+        let verify = || self::metrics::in_verification(verify);
+        #[cfg(feature = "tracing")]
+        let verify = || self::steps::in_verification(verify);
+        #[cfg(feature = "tracing")]
+        let result = trace_context::in_verification_span(evidence, verify);
+        #[cfg(not(feature = "tracing"))]
+        let result = verify();
+        if let Some(events) = &self.events {
+            events.record(&result);
+        }
+        result
+    }
 
-        let evidence = serde_json::from_slice::<Evidence>(evidence)
-            .context("Failed to deserialize Azure vTPM SEV-SNP evidence")?;
+    fn deserialize_and_verify(
+        &self,
+        evidence: &[u8],
+        format: EvidenceFormat,
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        external_var_data_hash: Option<&[u8; 32]>,
+        expected_runtime_data: Option<&[u8]>,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        if let ReportData::NotProvided = expected_report_data {
+            if !self.allow_empty_report_data {
+                bail!("unexpected empty report data");
+            }
+        }
+
+        let evidence = self.parse_evidence(evidence, format)?;
+
+        self.verify_parsed_evidence(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            external_var_data_hash,
+            expected_runtime_data,
+        )
+    }
 
-        let hcl_report = HclReport::new(evidence.report)?;
-        verify_signature(&evidence.quote, &hcl_report)?;
+    /// Parse `evidence`, checking its fields in strict mode
+    fn parse_evidence(&self, evidence: &[u8], format: EvidenceFormat) -> Result<Evidence> {
+        if self.strict_evidence_fields {
+            Evidence::check_fields(evidence, format)?;
+        }
+        Evidence::parse(evidence, format)
+    }
+
+    fn verify_parsed_evidence(
+        &self,
+        evidence: Evidence,
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        external_var_data_hash: Option<&[u8; 32]>,
+        expected_runtime_data: Option<&[u8]>,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let report = self.unwrap_report(evidence.report)?;
+        // the var_data is bound to the SNP report_data by the quote nonce
+        // binding, unless an external var_data hash is
+        if let Some(expected) = expected_runtime_data {
+            step("runtime_data", || {
+                verify_runtime_data(hcl_var_data(&report)?, expected)
+            })?;
+        }
+        let runtime_data = match (self.report_data_binding, external_var_data_hash) {
+            (ReportDataBinding::QuoteNonce, None) => {
+                Some(runtime_data_claim(hcl_var_data(&report)?))
+            }
+            _ => None,
+        };
+        let hcl_report = HclReport::new(report)?;
+        let pcrs_sha384 = evidence.pcrs_sha384.as_deref();
+        verify_quote(
+            &evidence.quote,
+            &hcl_report,
+            self.expected_nonce(expected_report_data),
+            expected_init_data_hash,
+            self.init_data_pcr,
+            &self.init_data_extend,
+            pcrs_sha384,
+        )?;
+
+        let mut claim = self.verify_report(
+            hcl_report,
+            &evidence.vcek,
+            expected_report_data,
+            external_var_data_hash,
+        )?;
+        if let Some(runtime_data) = runtime_data {
+            claim_map(&mut claim)?.insert("runtime_data".into(), runtime_data);
+        }
+        self.bind_claim(&mut claim, &evidence.quote, expected_report_data)?;
+        extend_claim_with_sha384(&mut claim, &evidence.quote, pcrs_sha384)?;
+        extend_claim_with_init_data_bank(&mut claim, expected_init_data_hash, self.init_data_pcr)?;
+        extend_claim_with_event_log(&mut claim, &evidence.quote, evidence.event_log.as_deref())?;
+
+        Ok(claim)
+    }
+
+    /// The nonce the vTPM quote must carry, if any
+    fn expected_nonce<'a, 'b>(
+        &self,
+        expected_report_data: &'a ReportData<'b>,
+    ) -> &'a ReportData<'b> {
+        match self.report_data_binding {
+            ReportDataBinding::QuoteNonce => expected_report_data,
+            ReportDataBinding::KeySha512 => &ReportData::NotProvided,
+        }
+    }
 
-        verify_nonce(&evidence.quote, expected_report_data)?;
+    /// Add the vTPM claims, check the measurements against their reference
+    /// values and the SNP report claims against the expected report_data,
+    /// if they bind it and it is provided.
+    fn bind_claim(
+        &self,
+        claim: &mut TeeEvidenceParsedClaim,
+        quote: &Quote,
+        expected_report_data: &ReportData,
+    ) -> Result<()> {
+        verify_pcr_policy(quote, &self.expected_pcrs)?;
+        if let Some(enabled_pcr7) = &self.secure_boot_pcr7 {
+            verify_secure_boot(quote, enabled_pcr7)?;
+        }
+        extend_claim(claim, quote, self.init_data_pcr)?;
+        self.verify_reference_values(claim)?;
+        if let (ReportDataBinding::KeySha512, ReportData::Value(key)) =
+            (self.report_data_binding, expected_report_data)
+        {
+            // the SNP report_data, checked by `verify_report`, is the key hash
+            claim_map(claim)?.insert(
+                "report_data".into(),
+                Value::String(hex::encode(openssl::sha::sha512(key))),
+            );
+        }
+        claim_map(claim)?.insert(
+            "report_data_checked".into(),
+            Value::Bool(!matches!(expected_report_data, ReportData::NotProvided)),
+        );
+        Ok(())
+    }
 
-        verify_pcrs(&evidence.quote)?;
+    /// Verify that the SNP report is genuine and bound to the HCL report, or
+    /// with the key binding to the expected report_data, returning the
+    /// claims parsed from the SNP report.
+    fn verify_report(
+        &self,
+        hcl_report: HclReport,
+        vcek: &str,
+        expected_report_data: &ReportData,
+        external_var_data_hash: Option<&[u8; 32]>,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        self.verify_policy_bundle()?;
 
         let var_data_hash = hcl_report.var_data_sha256();
-        let snp_report = hcl_report.try_into()?;
-        verify_report_data(&var_data_hash, &snp_report)?;
+        let external_matched = external_var_data_hash.map(|external| *external == var_data_hash);
+        if self.strict_var_data_hash && external_matched == Some(false) {
+            return Err(CertError::VarDataHashMismatch.into());
+        }
+        let ak_pub = ak_pub_der(&hcl_report)?;
+        let ak_fingerprint = openssl::sha::sha256(&ak_pub);
+        let snp_report: AttestationReport = hcl_report.try_into()?;
+        if self.report_data_binding == ReportDataBinding::QuoteNonce {
+            // the SNP report_data binds the HCL var_data even when the
+            // external hash differs, only the AK binding is then checked
+            let verify = match external_matched {
+                Some(false) => verify_ak_binding,
+                _ => verify_report_data,
+            };
+            step("report_data", || {
+                verify(&var_data_hash, &snp_report.report_data)
+            })?;
+        } else {
+            match expected_report_data {
+                ReportData::Value(key) => step("report_data", || {
+                    verify_key_report_data(&snp_report.report_data, key)
+                })?,
+                ReportData::Segments(_) => {
+                    bail!("report_data segments need the quote nonce binding")
+                }
+                ReportData::NotProvided => {
+                    transcript::skip("report_data", "no expected report_data")
+                }
+            }
+        }
 
-        let vcek = Vcek::from_pem(&evidence.vcek)?;
-        verify_snp_report(&snp_report, &vcek, &self.vendor_certs)?;
+        let vcek = parse_vcek(vcek)?;
+        let chain_sources = step("snp", || match (&self.vcek_trust, &self.chain_quorum) {
+            (VcekTrust::PinnedVcek(pinned), _) => {
+                verify_pinned_snp_report(&snp_report, &vcek, pinned, self.vmpl)?;
+                Ok(None)
+            }
+            (VcekTrust::Chain, Some(quorum)) => quorum
+                .verify(&snp_report, &vcek, self.vmpl, self.now())
+                .map(Some),
+            (VcekTrust::Chain, None) => {
+                let vendor_certs = self.vendor_certs_for(&vcek.0)?;
+                verify_snp_report(&snp_report, &vcek, vendor_certs, self.vmpl)?;
+                verify_vendor_certs_validity(vendor_certs, self.now())?;
+                Ok(None)
+            }
+        })?;
+        verify_validity(&vcek.0, self.now())?;
+        if let Some(crl) = &self.vcek_crl {
+            crl.check(&vcek.0, self.now())?;
+        }
 
-        let pcrs: Vec<&[u8; 32]> = evidence.quote.pcrs_sha256().collect();
-        verify_init_data(expected_init_data_hash, &pcrs)?;
+        if !self.allow_unmeasured_launch {
+            verify_measured_launch(&snp_report)?;
+        }
 
-        let mut claim = parse_tee_evidence(&snp_report);
-        extend_claim(&mut claim, &evidence.quote)?;
+        let vcek_der = vcek.0.to_der().context("Failed to get raw VCEK data")?;
+        let vcek_tcb = Tcb::from_vcek_der(&vcek_der)?;
+        if let Some(min_tcb) = &self.min_tcb {
+            verify_min_tcb(&snp_report, min_tcb)?;
+        }
+        if self.tcb_invariant {
+            verify_tcb_invariant(&snp_report)?;
+        }
+        if let Some(policy) = &self.microcode_policy {
+            verify_microcode(&snp_report, policy)?;
+        }
+        if let Some(min_abi) = &self.min_abi {
+            verify_min_abi(&snp_report, min_abi)?;
+        }
+        verify_guest_policy(&snp_report, &self.guest_policy)?;
 
+        let ak_changed = match &self.ak_pins {
+            Some(ak_pins) => ak_pins.check(&snp_report.chip_id, &ak_fingerprint)?,
+            None => None,
+        };
+
+        let mut claim = parse_tee_evidence(&snp_report);
+        extend_claim_with_tcb(&mut claim, &snp_report, &vcek_tcb)?;
+        let map = claim_map(&mut claim)?;
+        map.insert(
+            "chip_id".into(),
+            Value::String(chip_id_claim(&snp_report.chip_id)),
+        );
+        map.insert(
+            "ak_fingerprint".into(),
+            Value::String(hex::encode(ak_fingerprint)),
+        );
+        let ak_pub = AkPub {
+            der: STANDARD.encode(&ak_pub),
+            sha256: hex::encode(ak_fingerprint),
+        };
+        map.insert("ak_pub".into(), serde_json::to_value(ak_pub)?);
+        if let Some(matched) = external_matched {
+            claim_map(&mut claim)?.insert(
+                "external_var_data_hash_matched".into(),
+                Value::Bool(matched),
+            );
+        }
+        if let Some(sources) = chain_sources {
+            claim_map(&mut claim)?.insert("cert_chain_sources".into(), sources.into());
+        }
+        if let Some(changed) = ak_changed {
+            claim_map(&mut claim)?.insert("ak_changed".into(), Value::Bool(changed));
+        }
         Ok(claim)
     }
 }
 
-fn verify_nonce(quote: &Quote, report_data: &[u8]) -> Result<(), CertError> {
-    let nonce = quote.nonce()?;
-    if nonce != report_data[..] {
-        return Err(CertError::NonceMismatch);
+/// Verify the vTPM quote against the AK of the HCL report and the expected
+/// nonce and init_data.
+fn verify_quote(
+    quote: &Quote,
+    hcl_report: &HclReport,
+    expected_nonce: &ReportData,
+    expected_init_data_hash: &InitDataHash,
+    init_data_pcr: usize,
+    init_data_extend: &InitDataExtend,
+    pcrs_sha384: Option<&[Vec<u8>]>,
+) -> Result<()> {
+    step("signature", || verify_signature(quote, hcl_report))?;
+    verify_quote_contents(
+        quote,
+        expected_nonce,
+        expected_init_data_hash,
+        init_data_pcr,
+        init_data_extend,
+        pcrs_sha384,
+    )
+}
+
+/// Verify the vTPM quote against the DER encoded `ak_pub` and the expected
+/// nonce and init_data.
+fn verify_quote_with_ak(
+    quote: &Quote,
+    ak_pub: &[u8],
+    expected_nonce: &ReportData,
+    expected_init_data_hash: &InitDataHash,
+    init_data_pcr: usize,
+    init_data_extend: &InitDataExtend,
+) -> Result<()> {
+    step("signature", || verify_quote_signature(quote, ak_pub))?;
+    verify_quote_contents(
+        quote,
+        expected_nonce,
+        expected_init_data_hash,
+        init_data_pcr,
+        init_data_extend,
+        None,
+    )
+}
+
+fn verify_quote_contents(
+    quote: &Quote,
+    expected_nonce: &ReportData,
+    expected_init_data_hash: &InitDataHash,
+    init_data_pcr: usize,
+    init_data_extend: &InitDataExtend,
+    pcrs_sha384: Option<&[Vec<u8>]>,
+) -> Result<()> {
+    match expected_nonce {
+        ReportData::Value(expected_nonce) => step("nonce", || {
+            verify_nonce(quote, expected_nonce).map_err(vtpm_error)
+        })?,
+        ReportData::Segments(segments) => step("nonce", || {
+            verify_nonce_segments(quote, segments).map_err(vtpm_error)
+        })?,
+        ReportData::NotProvided => transcript::skip("nonce", "the quote nonce isn't bound"),
+    }
+
+    step("pcr", || {
+        verify_pcrs(quote, pcrs_sha384).map_err(vtpm_error)
+    })?;
+
+    let pcrs: BTreeMap<usize, &[u8]> = match init_data_bank(expected_init_data_hash) {
+        PcrBank::Sha256 => sha256_pcrs_by_index(quote)
+            .map_err(vtpm_error)?
+            .into_iter()
+            .map(|(index, pcr)| (index, pcr.as_slice()))
+            .collect(),
+        PcrBank::Sha384 => sha384_pcrs_by_index(
+            quote,
+            pcrs_sha384.context("The init_data is SHA-384, but no SHA-384 PCRs are provided")?,
+        )
+        .map_err(vtpm_error)?,
+    };
+    match expected_init_data_hash {
+        InitDataHash::Value(_) => step("init_data", || {
+            verify_init_data(
+                expected_init_data_hash,
+                &pcrs,
+                init_data_pcr,
+                init_data_extend,
+            )
+            .map_err(vtpm_error)
+        })?,
+        InitDataHash::NotProvided => transcript::skip("init_data", "no expected init_data hash"),
     }
-    debug!("TPM report_data verification completed successfully");
     Ok(())
 }
 
-fn verify_signature(quote: &Quote, hcl_report: &HclReport) -> Result<()> {
-    let ak_pub = hcl_report.ak_pub().context("Failed to get AKpub")?;
-    let der = ak_pub.key.try_to_der()?;
-    let ak_pub = PKey::public_key_from_der(&der).context("Failed to parse AKpub")?;
+/// The PCR bank the init_data is bound to, following the length of the
+/// expected init_data hash. SHA-256 without one.
+fn init_data_bank(expected_init_data_hash: &InitDataHash) -> PcrBank {
+    match expected_init_data_hash {
+        InitDataHash::Value(hash) if hash.len() == PcrBank::Sha384.digest_len() => PcrBank::Sha384,
+        _ => PcrBank::Sha256,
+    }
+}
 
-    quote
-        .verify_signature(&ak_pub)
-        .context("vTPM quote is not signed by AKpub")?;
-    debug!("Signature verification completed successfully");
+/// Add the PCR bank of the init_data as `init_data_bank`. The init_data
+/// claim is the SHA-384 PCR if the init_data is bound to that bank.
+fn extend_claim_with_init_data_bank(
+    claim: &mut TeeEvidenceParsedClaim,
+    expected_init_data_hash: &InitDataHash,
+    init_data_pcr: usize,
+) -> Result<()> {
+    let bank = init_data_bank(expected_init_data_hash);
+    let map = claim_map(claim)?;
+    if bank == PcrBank::Sha384 {
+        let init_data = map
+            .get("tpm_sha384")
+            .and_then(|pcrs| pcrs.get(format!("pcr{init_data_pcr:02}")))
+            .cloned()
+            .with_context(|| format!("Quote has no SHA-384 init_data PCR{init_data_pcr}"))?;
+        map.insert("init_data".into(), init_data);
+    }
+    map.insert("init_data_bank".into(), Value::String(bank.name().into()));
     Ok(())
 }
 
-fn verify_pcrs(quote: &Quote) -> Result<()> {
-    quote
-        .verify_pcrs()
-        .context("Digest of PCRs does not match digest in Quote")?;
-    debug!("PCR verification completed successfully");
-    Ok(())
+/// The expected report_data, hex encoded, or its segments with their
+/// offsets
+fn report_data_json(expected_report_data: &ReportData) -> Value {
+    match expected_report_data {
+        ReportData::Value(value) => Value::String(hex::encode(value)),
+        ReportData::Segments(segments) => segments
+            .iter()
+            .map(|(offset, value)| json!({ "offset": offset, "value": hex::encode(value) }))
+            .collect(),
+        ReportData::NotProvided => Value::Null,
+    }
+}
+
+/// The hex encoded chip_id, or an empty string when the guest policy masks
+/// it to zero, so that a masked chip_id is told apart from a missing one
+fn chip_id_claim(chip_id: &[u8]) -> String {
+    if chip_id.iter().all(|byte| *byte == 0) {
+        return String::new();
+    }
+    hex::encode(chip_id)
 }
 
-fn verify_report_data(
-    var_data_hash: &[u8; 32],
+fn extend_claim_with_tcb(
+    claim: &mut TeeEvidenceParsedClaim,
     snp_report: &AttestationReport,
-) -> Result<(), CertError> {
-    if *var_data_hash != snp_report.report_data[..32] {
-        return Err(CertError::SnpReportMismatch);
+    vcek_tcb: &Tcb,
+) -> Result<()> {
+    let map = claim_map(claim)?;
+    map.insert(
+        "reported_tcb".into(),
+        serde_json::to_value(Tcb::from(&snp_report.reported_tcb))?,
+    );
+    map.insert(
+        "committed_tcb".into(),
+        serde_json::to_value(Tcb::from(&snp_report.committed_tcb))?,
+    );
+    map.insert(
+        "current_tcb".into(),
+        serde_json::to_value(Tcb::from(&snp_report.current_tcb))?,
+    );
+    map.insert(
+        "launch_tcb".into(),
+        serde_json::to_value(Tcb::from(&snp_report.launch_tcb))?,
+    );
+    map.insert("vcek_tcb".into(), serde_json::to_value(vcek_tcb)?);
+    map.insert(
+        "microcode_svn".into(),
+        Value::from(snp_report.reported_tcb.microcode),
+    );
+    Ok(())
+}
+
+/// Add the SHA-384 PCRs, if any, as `tpm_sha384`, named after their index
+/// in the quote's selection.
+fn extend_claim_with_sha384(
+    claim: &mut TeeEvidenceParsedClaim,
+    quote: &Quote,
+    pcrs_sha384: Option<&[Vec<u8>]>,
+) -> Result<()> {
+    let Some(pcrs_sha384) = pcrs_sha384 else {
+        return Ok(());
+    };
+    let mut tpm_values = serde_json::Map::new();
+    for (index, pcr) in sha384_pcrs_by_index(quote, pcrs_sha384).map_err(vtpm_error)? {
+        tpm_values.insert(format!("pcr{index:02}"), Value::String(hex::encode(pcr)));
     }
-    debug!("SNP report_data verification completed successfully");
+    claim_map(claim)?.insert("tpm_sha384".into(), Value::Object(tpm_values));
     Ok(())
 }
 
-fn verify_snp_report(
-    snp_report: &AttestationReport,
-    vcek: &Vcek,
-    vendor_certs: &VendorCertificates,
-) -> Result<(), CertError> {
-    let vcek_data = vcek.0.to_der().context("Failed to get raw VCEK data")?;
-    let cert_chain = [CertTableEntry::new(CertType::VCEK, vcek_data)];
-    verify_report_signature(snp_report, &cert_chain, vendor_certs)?;
+/// Replay the event log, if any, against the quoted PCRs and add the
+/// measured components as `event_log`
+fn extend_claim_with_event_log(
+    claim: &mut TeeEvidenceParsedClaim,
+    quote: &Quote,
+    event_log: Option<&[u8]>,
+) -> Result<()> {
+    let Some(event_log) = event_log else {
+        return Ok(());
+    };
+    let pcrs = sha256_pcrs_by_index(quote).map_err(vtpm_error)?;
+    let components = eventlog::replay_event_log(event_log, &pcrs)?;
+    claim_map(claim)?.insert("event_log".into(), serde_json::to_value(components)?);
+    Ok(())
+}
+
+#[async_trait]
+impl Verifier for AzSnpVtpm {
+    fn evidence_kind(&self) -> &'static str {
+        "az-snp-vtpm"
+    }
+
+    fn can_handle(&self, evidence: &[u8]) -> bool {
+        Evidence::from_slice(evidence).is_ok()
+    }
+
+    fn classify_error(&self, error: &anyhow::Error) -> Option<VerifyFailure> {
+        classify_error(error)
+    }
+
+    /// The following verification steps are performed:
+    /// 1. TPM Quote has been signed by AK included in the HCL variable data
+    /// 2. Attestation report_data matches TPM Quote nonce
+    /// 3. TPM PCRs' digest matches the digest in the Quote, both as checked by
+    ///    the library and independently recomputed from the selected bank
+    /// 4. SNP report's report_data field matches hashed HCL variable data
+    /// 5. SNP Report is genuine
+    ///    VCEK is within its validity period, expired VCEKs are rejected
+    /// 6. SNP Report has been issued in the configured VMPL, VMPL 0 unless
+    ///    set with `with_vmpl`
+    ///    SNP Report's launch measurement is not all zero, unless allowed
+    ///    (optionally) SNP Report's reported_tcb equals the VCEK's TCB
+    /// 7. Init data hash matches TPM PCR[init_data_pcr], PCR8 by default
+    ///
+    /// With the `kds-fetch` feature, an omitted VCEK is fetched from the KDS
+    /// first, if enabled with `with_kds_fetch`.
+    ///
+    /// The quote checks (1-3, 7) run before the SNP report checks (4-6).
+    async fn evaluate(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        if let Err(e) = self.refresh_vcek_crl().await {
+            warn!("Failed to refresh the VCEK CRL: {e:#}");
+        }
+        #[cfg(feature = "kds-fetch")]
+        let evidence = &*self.with_fetched_vcek(evidence).await?;
+        self.evaluate_blocking(evidence, expected_report_data, expected_init_data_hash)
+    }
+
+    /// The launch measurement, the SHA-256 PCRs of the quote and the
+    /// host_data are checked against `expected`, in addition to the
+    /// configured reference values.
+    async fn evaluate_with_expected(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        expected: &ExpectedMeasurements,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let claim = self
+            .evaluate(evidence, expected_report_data, expected_init_data_hash)
+            .await?;
+        verify_expected_measurements(&claim, expected)?;
+        Ok(claim)
+    }
+}
+
+/// Verify that the SHA-256 PCRs of the quote listed in `expected_pcrs` have
+/// their expected values.
+fn verify_pcr_policy(
+    quote: &Quote,
+    expected_pcrs: &HashMap<usize, [u8; 32]>,
+) -> Result<(), CertError> {
+    let pcrs = sha256_pcrs_by_index(quote)?;
+    let mut indices: Vec<&usize> = expected_pcrs.keys().collect();
+    indices.sort();
+    for index in indices {
+        let expected = &expected_pcrs[index];
+        let Some(actual) = pcrs.get(index) else {
+            return Err(CertError::PcrMismatch {
+                index: *index,
+                expected: hex::encode(expected),
+                actual: "missing".into(),
+            });
+        };
+        if *actual != expected {
+            return Err(CertError::PcrMismatch {
+                index: *index,
+                expected: hex::encode(expected),
+                actual: hex::encode(actual),
+            });
+        }
+    }
+    debug!("PCR policy verification completed successfully");
+    Ok(())
+}
+
+/// Verify that PCR7 of the quote, which measures the Secure Boot state and
+/// keys, is one of the `enabled_pcr7` values
+fn verify_secure_boot(quote: &Quote, enabled_pcr7: &[[u8; 32]]) -> Result<(), CertError> {
+    let pcrs = sha256_pcrs_by_index(quote)?;
+    let pcr7 = pcrs
+        .get(&SECURE_BOOT_PCR)
+        .copied()
+        .ok_or_else(|| CertError::SecureBootDisabled("missing".into()))?;
+    if !enabled_pcr7.contains(pcr7) {
+        return Err(CertError::SecureBootDisabled(hex::encode(pcr7)));
+    }
+    debug!("Secure Boot verification completed successfully");
+    Ok(())
+}
+
+/// Verify that the SNP `report_data` starts with `var_data_hash`. A
+/// report_data too short to hold it doesn't match.
+fn verify_report_data(var_data_hash: &[u8; 32], report_data: &[u8]) -> Result<(), CertError> {
+    if report_data.get(..32) != Some(var_data_hash.as_slice()) {
+        return Err(CertError::SnpReportMismatch);
+    }
+    debug!("SNP report_data verification completed successfully");
+    Ok(())
+}
+
+/// Verify that the HCL `var_data`, whose sha256 the SNP report_data must
+/// bind, is the runtime data the guest is expected to have declared
+fn verify_runtime_data(var_data: &[u8], expected: &[u8]) -> Result<(), CertError> {
+    if var_data != expected {
+        return Err(CertError::SnpReportMismatch);
+    }
+    Ok(())
+}
+
+/// Verify that the AK which verified the quote, from the HCL var_data with
+/// the hash `ak_var_data_hash`, is the one the SNP report_data binds. This
+/// ties the quote to this very report when an externally provided var_data
+/// hash disagrees with the HCL report's.
+fn verify_ak_binding(ak_var_data_hash: &[u8; 32], report_data: &[u8]) -> Result<(), CertError> {
+    if report_data.get(..32) != Some(ak_var_data_hash.as_slice()) {
+        return Err(CertError::AkNotBound);
+    }
+    Ok(())
+}
+
+/// Verify that the SNP `report_data` is the sha512 of `key`
+fn verify_key_report_data(report_data: &[u8; 64], key: &[u8]) -> Result<(), CertError> {
+    if *report_data != openssl::sha::sha512(key) {
+        return Err(CertError::SnpReportMismatch);
+    }
+    debug!("SNP report_data key binding verification completed successfully");
+    Ok(())
+}
+
+fn verify_snp_report(
+    snp_report: &AttestationReport,
+    vcek: &Vcek,
+    vendor_certs: &VendorCertificates,
+    expected_vmpl: u32,
+) -> Result<(), CertError> {
+    verify_snp_signature(snp_report, vcek, vendor_certs)?;
+    verify_vmpl(snp_report, expected_vmpl)
+}
+
+fn verify_snp_signature(
+    snp_report: &AttestationReport,
+    vcek: &Vcek,
+    vendor_certs: &VendorCertificates,
+) -> Result<(), CertError> {
+    let vcek_data = vcek.0.to_der().context("Failed to get raw VCEK data")?;
+    verify_report_version(snp_report)?;
+    verify_vcek_tcb(snp_report, &Tcb::from_vcek_der(&vcek_data)?)?;
+    // a VLEK is endorsed by the ASVK instead of the ASK
+    let cert_chain = [CertTableEntry::new(
+        endorsement_key_type(&vcek.0),
+        vcek_data,
+    )];
+    verify_report_signature(snp_report, &cert_chain, vendor_certs)
+        .map_err(CertError::SnpReportSignature)
+}
+
+/// Verify the signature of the SNP report by `vcek`, which must be the
+/// DER encoded `pinned` VCEK, without verifying its chain
+fn verify_pinned_snp_report(
+    snp_report: &AttestationReport,
+    vcek: &Vcek,
+    pinned: &[u8],
+    expected_vmpl: u32,
+) -> Result<(), CertError> {
+    let vcek_data = vcek.0.to_der().context("Failed to get raw VCEK data")?;
+    verify_report_version(snp_report)?;
+    verify_vcek_tcb(snp_report, &Tcb::from_vcek_der(&vcek_data)?)?;
+    verify_report_signed_by(snp_report, &vcek.0).map_err(CertError::SnpReportSignature)?;
+    if vcek_data != pinned {
+        return Err(CertError::VcekNotPinned);
+    }
+    verify_vmpl(snp_report, expected_vmpl)
+}
+
+/// Verify the AMD cert chain ARK, ASK and VCEK and the signature of the SNP
+/// `report` by the VCEK, on its own, without the vTPM and HCL checks of
+/// `AzSnpVtpm`. The VCEK is PEM or base64 DER encoded. A VLEK is accepted
+/// too, with the ARK and ASVK chain. Neither the VMPL nor
+/// the validity periods of the certificates are checked.
+pub fn verify_snp_chain(
+    report: &AttestationReport,
+    vcek_pem: &str,
+    certs: &VendorCertificates,
+) -> Result<(), CertError> {
+    verify_snp_signature(report, &parse_vcek(vcek_pem)?, certs)
+}
+
+/// Check the layout of an HCL report of an SNP report before parsing it:
+/// the magic, the hardware report type and the sizes of the SNP report and
+/// of the var_data.
+pub(crate) fn validate_hcl_report(report: &[u8]) -> Result<(), CertError> {
+    let runtime_data = HCL_SNP_REPORT_OFFSET + SNP_REPORT_SIZE;
+    let var_data = runtime_data + HCL_RUNTIME_HEADER_SIZE;
+    if report.len() < var_data {
+        return Err(CertError::MalformedHclReport(format!(
+            "{} bytes are too short for the headers and SNP report of {var_data} bytes",
+            report.len()
+        )));
+    }
+    if report[..HCL_MAGIC.len()] != *HCL_MAGIC {
+        return Err(CertError::MalformedHclReport(format!(
+            "bad magic 0x{}",
+            hex::encode(&report[..HCL_MAGIC.len()])
+        )));
+    }
+    let report_type = LittleEndian::read_u32(&report[runtime_data + 8..]);
+    if report_type != HCL_REPORT_TYPE_SNP {
+        return Err(CertError::MalformedHclReport(format!(
+            "hardware report type {report_type} isn't SEV-SNP"
+        )));
+    }
+    let var_data_size = LittleEndian::read_u32(&report[runtime_data + 16..]) as usize;
+    if report.len() - var_data < var_data_size {
+        return Err(CertError::MalformedHclReport(format!(
+            "var_data of {var_data_size} bytes is truncated to {} bytes",
+            report.len() - var_data
+        )));
+    }
+    Ok(())
+}
+
+/// The var_data of the raw HCL `report`, the runtime data declared by the
+/// guest, e.g. its keys
+fn hcl_var_data(report: &[u8]) -> Result<&[u8], CertError> {
+    validate_hcl_report(report)?;
+    let runtime_data = HCL_SNP_REPORT_OFFSET + SNP_REPORT_SIZE;
+    let var_data = runtime_data + HCL_RUNTIME_HEADER_SIZE;
+    let var_data_size = LittleEndian::read_u32(&report[runtime_data + 16..]) as usize;
+    Ok(&report[var_data..var_data + var_data_size])
+}
+
+/// The `runtime_data` claim of the var_data declared by the guest: `raw`,
+/// base64 encoded, and `json`, parsed, if it is a JSON document
+fn runtime_data_claim(runtime_data: &[u8]) -> Value {
+    let mut claim = json!({ "raw": STANDARD.encode(runtime_data) });
+    if let Ok(parsed) = serde_json::from_slice::<Value>(runtime_data) {
+        claim["json"] = parsed;
+    }
+    claim
+}
+
+/// Verify the VMPL of the SNP report in the raw HCL `report`, before its
+/// var_data is trusted
+fn verify_hcl_vmpl(report: &[u8], expected_vmpl: u32) -> Result<(), CertError> {
+    let offset = HCL_SNP_REPORT_OFFSET + SNP_VMPL_OFFSET;
+    let vmpl = report
+        .get(offset..offset + 4)
+        .context("HCL report is too short")?;
+    let vmpl = LittleEndian::read_u32(vmpl);
+    if vmpl != expected_vmpl {
+        return Err(CertError::VmplIncorrect {
+            expected: expected_vmpl,
+            reported: vmpl,
+        });
+    }
+    Ok(())
+}
+
+fn verify_vmpl(snp_report: &AttestationReport, expected_vmpl: u32) -> Result<(), CertError> {
+    if snp_report.vmpl != expected_vmpl {
+        return Err(CertError::VmplIncorrect {
+            expected: expected_vmpl,
+            reported: snp_report.vmpl,
+        });
+    }
+    Ok(())
+}
+
+fn verify_microcode(
+    snp_report: &AttestationReport,
+    policy: &MicrocodePolicy,
+) -> Result<(), CertError> {
+    let microcode = snp_report.reported_tcb.microcode;
+    if !policy.allows(microcode) {
+        return Err(CertError::MicrocodeNotAllowed(microcode));
+    }
+    debug!("Microcode SVN verification completed successfully");
+    Ok(())
+}
+
+fn verify_min_abi(snp_report: &AttestationReport, min_abi: &AbiVersion) -> Result<(), CertError> {
+    let reported = AbiVersion {
+        major: snp_report.policy.abi_major() as u8,
+        minor: snp_report.policy.abi_minor() as u8,
+    };
+    if reported < *min_abi {
+        return Err(CertError::AbiTooLow {
+            min: *min_abi,
+            reported,
+        });
+    }
+    debug!("Guest policy ABI verification completed successfully");
+    Ok(())
+}
+
+fn verify_guest_policy(
+    snp_report: &AttestationReport,
+    constraints: &GuestPolicyConstraints,
+) -> Result<(), CertError> {
+    if constraints.no_debug && snp_report.policy.debug_allowed() != 0 {
+        return Err(CertError::GuestPolicyBitSet("DEBUG"));
+    }
+    if constraints.no_migration && snp_report.policy.migrate_ma_allowed() != 0 {
+        return Err(CertError::GuestPolicyBitSet("MIGRATE_MA"));
+    }
+    if let Some(min_abi) = &constraints.min_abi {
+        verify_min_abi(snp_report, min_abi)?;
+    }
+    debug!("Guest policy verification completed successfully");
+    Ok(())
+}
+
+/// Convert the time of verification for comparisons with certificate times
+pub(crate) fn asn1_time(now: SystemTime) -> Result<Asn1Time> {
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .context("Time of verification is before the epoch")?
+        .as_secs();
+    Asn1Time::from_unix(
+        secs.try_into()
+            .context("Time of verification is too late")?,
+    )
+    .context("Failed to convert the time of verification")
+}
+
+/// Whether `now` is within the validity period of `cert`, bounds included
+fn is_valid_at(cert: &X509Ref, now: SystemTime) -> Result<bool> {
+    let now = asn1_time(now)?;
+    let not_before = now
+        .compare(cert.not_before())
+        .context("Failed to compare notBefore")?;
+    let not_after = now
+        .compare(cert.not_after())
+        .context("Failed to compare notAfter")?;
+    Ok(not_before != Ordering::Less && not_after != Ordering::Greater)
+}
+
+/// Verify that `now` is within the validity period of `cert`, bounds
+/// included.
+fn verify_validity(cert: &X509Ref, now: SystemTime) -> Result<(), CertError> {
+    if !is_valid_at(cert, now)? {
+        return Err(CertError::VcekNotValid);
+    }
+    debug!("VCEK validity verification completed successfully");
+    Ok(())
+}
+
+/// Parse the `vcek` of the evidence, PEM encoded or base64 encoded DER
+pub(crate) fn parse_vcek(vcek: &str) -> Result<Vcek> {
+    if let Ok(vcek) = Vcek::from_pem(vcek) {
+        return Ok(vcek);
+    }
+    let der = STANDARD
+        .decode(vcek.trim())
+        .context("VCEK is neither PEM nor base64 encoded DER")?;
+    let vcek = X509::from_der(&der).context("Failed to parse DER encoded VCEK")?;
+    Ok(Vcek(vcek))
+}
+
+/// Verify that `now` is within the validity periods of the ARK and ASK
+fn verify_vendor_certs_validity(
+    vendor_certs: &VendorCertificates,
+    now: SystemTime,
+) -> Result<(), CertError> {
+    for (name, cert) in [("ARK", &vendor_certs.ark), ("ASK", &vendor_certs.ask)] {
+        if !is_valid_at(cert, now)? {
+            return Err(CertError::VendorCertNotValid(name));
+        }
+    }
+    Ok(())
+}
+
+fn verify_measured_launch(snp_report: &AttestationReport) -> Result<(), CertError> {
+    if snp_report.measurement.iter().all(|b| *b == 0) {
+        return Err(CertError::UnmeasuredLaunch);
+    }
+    Ok(())
+}
+
+/// A VCEK endorses the reported_tcb its SPL extensions have been issued for
+/// only, whatever TCB the report it signs claims
+fn verify_vcek_tcb(snp_report: &AttestationReport, vcek_tcb: &Tcb) -> Result<(), CertError> {
+    let reported = Tcb::from(&snp_report.reported_tcb);
+    if let Some((component, vcek, reported)) = vcek_tcb.component_mismatch(&reported) {
+        return Err(CertError::VcekTcbMismatch {
+            component,
+            vcek,
+            reported,
+        });
+    }
+    Ok(())
+}
+
+fn verify_min_tcb(snp_report: &AttestationReport, min_tcb: &Tcb) -> Result<(), CertError> {
+    let reported = Tcb::from(&snp_report.reported_tcb);
+    if let Some(component) = reported.component_below(min_tcb) {
+        return Err(CertError::TcbTooLow {
+            component,
+            min: *min_tcb,
+            reported,
+        });
+    }
+    debug!("TCB floor verification completed successfully");
+    Ok(())
+}
+
+fn verify_tcb_invariant(snp_report: &AttestationReport) -> Result<(), CertError> {
+    let committed = Tcb::from(&snp_report.committed_tcb);
+    let current = Tcb::from(&snp_report.current_tcb);
+    if let Some(component) = current.component_below(&committed) {
+        return Err(CertError::TcbInvariant {
+            component,
+            committed,
+            current,
+        });
+    }
+    debug!("TCB invariant verification completed successfully");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::az_vtpm::vtpm::{verify_pcr_count, verify_pcr_digest};
+    use serde_json::json;
+    use std::time::Duration;
+
+    pub(super) const REPORT: &[u8; 2600] =
+        include_bytes!("../../test_data/az-snp-vtpm/hcl-report.bin");
+    pub(super) const QUOTE: &[u8; 1170] = include_bytes!("../../test_data/az-snp-vtpm/quote.bin");
+    pub(super) const VCEK: &str = include_str!("../../test_data/az-snp-vtpm/vcek.pem");
+    pub(super) const REPORT_DATA: &[u8] = "challenge".as_bytes();
+    /// 2024-01-01, within the validity of the VCEK fixture
+    pub(super) const FIXTURE_NOW: u64 = 1704067200;
+
+    /// A clock pinned to `FIXTURE_NOW`
+    pub(super) fn fixture_clock() -> Arc<FixedClock> {
+        Arc::new(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(FIXTURE_NOW),
+        ))
+    }
+
+    /// A verifier whose clock is pinned to `FIXTURE_NOW`, so that the tests
+    /// don't start failing once the VCEK fixture has expired
+    pub(super) fn fixture_verifier() -> AzSnpVtpm {
+        AzSnpVtpm::new().unwrap().with_clock(fixture_clock())
+    }
+
+    /// Serialized evidence made of the committed quote and VCEK and `report`
+    pub(super) fn evidence_fixture(report: &[u8]) -> Vec<u8> {
+        let evidence = Evidence {
+            quote: bincode::deserialize(QUOTE).unwrap(),
+            report: report.to_vec(),
+            vcek: VCEK.to_string(),
+            pcrs_sha384: None,
+            event_log: None,
+        };
+        serde_json::to_vec(&evidence).unwrap()
+    }
+
+    /// A VCEK-like certificate issued by `SEV-<product>`, self-signed
+    pub(super) fn vcek_of(product: &str) -> X509 {
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::hash::MessageDigest;
+        use openssl::nid::Nid;
+        use openssl::x509::X509Name;
+
+        let key = PKey::from_ec_key(
+            EcKey::generate(&EcGroup::from_curve_name(Nid::SECP384R1).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let mut issuer = X509Name::builder().unwrap();
+        issuer
+            .append_entry_by_nid(Nid::COMMONNAME, &format!("SEV-{product}"))
+            .unwrap();
+        let issuer = issuer.build();
+        let mut builder = X509::builder().unwrap();
+        builder.set_issuer_name(&issuer).unwrap();
+        builder.set_subject_name(&issuer).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha384()).unwrap();
+        builder.build()
+    }
+
+    /// A PEM bundle of a `SEV-<product>` chain unrelated to the fixtures,
+    /// whose ASK, ARK and ASVK are the same self-signed certificate
+    pub(super) fn unrelated_chain(product: &str) -> Vec<u8> {
+        vcek_of(product).to_pem().unwrap().repeat(3)
+    }
+
+    /// The fixture quote with its nonce replaced by `nonce`
+    pub(super) fn quote_with_nonce(nonce: &[u8]) -> Quote {
+        // bincode: signature, message and PCRs, each prefixed by a u64 length
+        let signature = &QUOTE[..8 + 256];
+        let message = &QUOTE[8 + 256 + 8..8 + 256 + 8 + 122];
+        let pcrs = &QUOTE[8 + 256 + 8 + 122..];
+        // magic, type and qualifiedSigner precede the extraData
+        let extra_data = 4 + 2 + 2 + 0x22;
+        let extra_data_len = u16::from_be_bytes([message[extra_data], message[extra_data + 1]]);
+
+        let mut new_message = message[..extra_data].to_vec();
+        new_message.extend((nonce.len() as u16).to_be_bytes());
+        new_message.extend(nonce);
+        new_message.extend(&message[extra_data + 2 + extra_data_len as usize..]);
+
+        let mut quote = signature.to_vec();
+        quote.extend((new_message.len() as u64).to_le_bytes());
+        quote.extend(new_message);
+        quote.extend(pcrs);
+        bincode::deserialize(&quote).unwrap()
+    }
+
+    #[test]
+    fn test_milan_cert_chain_cached() {
+        let milan = load_milan_cert_chain().as_ref().unwrap();
+        assert!(std::ptr::eq(
+            milan,
+            load_milan_cert_chain().as_ref().unwrap()
+        ));
+
+        // the verifiers share the parsed certificates instead of reparsing them
+        for _ in 0..2 {
+            let verifier = fixture_verifier();
+            let certs = &verifier.vendor_certs[&SnpGeneration::Milan];
+            // an X509Ref is the address of the underlying OpenSSL X509
+            assert!(std::ptr::eq(&*certs.ark, &*milan.ark));
+            assert!(std::ptr::eq(&*certs.ask, &*milan.ask));
+            assert!(std::ptr::eq(&*certs.asvk, &*milan.asvk));
+        }
+    }
+
+    #[test]
+    fn test_verify_snp_report() {
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let snp_report = hcl_report.try_into().unwrap();
+        let vcek = Vcek::from_pem(include_str!("../../test_data/az-snp-vtpm/vcek.pem")).unwrap();
+        let vendor_certs = load_milan_cert_chain().as_ref().unwrap();
+        verify_snp_report(&snp_report, &vcek, vendor_certs, HCL_VMPL_VALUE).unwrap();
+    }
+
+    #[test]
+    fn test_verify_vmpl() {
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let mut snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        snp_report.vmpl = 2;
+        verify_vmpl(&snp_report, 2).unwrap();
+        assert!(matches!(
+            verify_vmpl(&snp_report, HCL_VMPL_VALUE),
+            Err(CertError::VmplIncorrect {
+                expected: 0,
+                reported: 2
+            })
+        ));
+
+        // the fixture is a VMPL 0 report
+        let err = fixture_verifier()
+            .with_vmpl(2)
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::VmplIncorrect {
+                expected: 2,
+                reported: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_malformed_evidence_is_an_error() {
+        let verify = |evidence: &[u8]| {
+            fixture_verifier().verify_evidence(
+                evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        // every truncation of the HCL report up to the end of its var_data
+        let runtime_data = HCL_SNP_REPORT_OFFSET + SNP_REPORT_SIZE;
+        for len in 0..runtime_data + HCL_RUNTIME_HEADER_SIZE + 1110 {
+            verify(&evidence_fixture(&REPORT[..len])).unwrap_err();
+        }
+        let mut huge_var_data = *REPORT;
+        huge_var_data[runtime_data + 16..runtime_data + 20].copy_from_slice(&[0xff; 4]);
+        verify(&evidence_fixture(&huge_var_data)).unwrap_err();
+
+        for evidence in [
+            &b""[..],
+            b"\xff\x00",
+            b"null",
+            b"[]",
+            b"[null]",
+            b"{}",
+            br#"{"version": null, "report": [], "vcek": "", "quote": null}"#,
+        ] {
+            verify(evidence).unwrap_err();
+            AzSnpVtpm::inspect(evidence).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_validate_hcl_report() {
+        validate_hcl_report(REPORT).unwrap();
+
+        let detail = |report: &[u8]| match validate_hcl_report(report) {
+            Err(CertError::MalformedHclReport(detail)) => detail,
+            other => panic!("unexpected {other:?}"),
+        };
+        assert_eq!(
+            detail(&[]),
+            "0 bytes are too short for the headers and SNP report of 1236 bytes"
+        );
+        assert_eq!(
+            detail(&REPORT[..0x100]),
+            "256 bytes are too short for the headers and SNP report of 1236 bytes"
+        );
+        assert_eq!(
+            detail(&REPORT[..2000]),
+            "var_data of 1110 bytes is truncated to 764 bytes"
+        );
+        let mut bad_magic = *REPORT;
+        bad_magic[..4].copy_from_slice(b"HCLB");
+        assert_eq!(detail(&bad_magic), "bad magic 0x48434c42");
+        let mut tdx = *REPORT;
+        tdx[HCL_SNP_REPORT_OFFSET + SNP_REPORT_SIZE + 8] = 4;
+        assert_eq!(detail(&tdx), "hardware report type 4 isn't SEV-SNP");
+
+        let err = fixture_verifier()
+            .verify_evidence(
+                &evidence_fixture(&[]),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::MalformedHclReport(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_hcl_vmpl() {
+        verify_hcl_vmpl(REPORT, HCL_VMPL_VALUE).unwrap();
+        verify_hcl_vmpl(&REPORT[..0x50], HCL_VMPL_VALUE).unwrap_err();
+
+        let mut tampered = *REPORT;
+        tampered[HCL_SNP_REPORT_OFFSET + SNP_VMPL_OFFSET] = 1;
+        let verify = |verifier: AzSnpVtpm| {
+            verifier.verify_evidence(
+                &evidence_fixture(&tampered),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        let err = verify(fixture_verifier()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::VmplIncorrect {
+                expected: 0,
+                reported: 1
+            })
+        ));
+
+        // the VMPL of the tampered report is expected, its signature isn't valid
+        let err = verify(fixture_verifier().with_vmpl(1)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::SnpReportSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_snp_chain() {
+        let certs =
+            VendorCertificates::from_pem(include_bytes!("../snp/milan_ask_ark_asvk.pem")).unwrap();
+        let parse = || -> AttestationReport {
+            HclReport::new(REPORT.to_vec()).unwrap().try_into().unwrap()
+        };
+        let report = parse();
+        verify_snp_chain(&report, VCEK, &certs).unwrap();
+
+        let mut tampered = parse();
+        tampered.report_data[0] ^= 1;
+        assert!(matches!(
+            verify_snp_chain(&tampered, VCEK, &certs),
+            Err(CertError::SnpReportSignature(_))
+        ));
+
+        // the chain of another root doesn't endorse the VCEK
+        let other = include_bytes!("../../test_data/az-snp-vtpm/crl/issuer.pem");
+        let other = VendorCertificates::from_pem(&[&other[..], other, other].concat()).unwrap();
+        verify_snp_chain(&report, VCEK, &other).unwrap_err();
+        verify_snp_chain(&report, "not a VCEK", &certs).unwrap_err();
+    }
+
+    #[test]
+    fn test_verify_snp_chain_vlek() {
+        let certs =
+            VendorCertificates::from_pem(include_bytes!("../snp/milan_ask_ark_asvk.pem")).unwrap();
+        let vlek = STANDARD.encode(include_bytes!("../../test_data/snp/test-vlek.der"));
+        let report = include_bytes!("../../test_data/snp/test-vlek-report.bin");
+        let mut report: AttestationReport = bincode::deserialize(report).unwrap();
+        verify_snp_chain(&report, &vlek, &certs).unwrap();
+
+        report.report_data[0] ^= 1;
+        assert!(matches!(
+            verify_snp_chain(&report, &vlek, &certs),
+            Err(CertError::SnpReportSignature(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_snp_report_failure() {
+        let mut wrong_report = REPORT.clone();
+        // messing with snp report
+        wrong_report[0x01a6] = 0;
+        let hcl_report = HclReport::new(wrong_report.to_vec()).unwrap();
+        let snp_report = hcl_report.try_into().unwrap();
+        let vcek = Vcek::from_pem(include_str!("../../test_data/az-snp-vtpm/vcek.pem")).unwrap();
+        let vendor_certs = load_milan_cert_chain().as_ref().unwrap();
+        let err = verify_snp_report(&snp_report, &vcek, vendor_certs, HCL_VMPL_VALUE).unwrap_err();
+        assert_eq!(err.failure(), Some(VerifyFailure::Signature));
+        assert!(matches!(
+            err,
+            CertError::VcekTcbMismatch {
+                component: "snp",
+                vcek: 8,
+                reported: 0,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_verify_measured_launch() {
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let mut snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        verify_measured_launch(&snp_report).unwrap();
+
+        snp_report.measurement = [0u8; 48];
+        assert!(matches!(
+            verify_measured_launch(&snp_report),
+            Err(CertError::UnmeasuredLaunch)
+        ));
+    }
+
+    #[test]
+    fn test_verify_vcek_tcb() {
+        let certs =
+            VendorCertificates::from_pem(include_bytes!("../snp/milan_ask_ark_asvk.pem")).unwrap();
+        let vcek = parse_vcek(VCEK).unwrap();
+        let vcek_tcb = Tcb::from_vcek_der(&vcek.0.to_der().unwrap()).unwrap();
+        let report = || -> AttestationReport {
+            HclReport::new(REPORT.to_vec()).unwrap().try_into().unwrap()
+        };
+        verify_vcek_tcb(&report(), &vcek_tcb).unwrap();
+
+        // a report claiming a higher TCB than the VCEK has been issued for
+        let mut higher = report();
+        higher.reported_tcb.microcode = vcek_tcb.microcode + 1;
+        let err = verify_snp_chain(&higher, VCEK, &certs).unwrap_err();
+        assert_eq!(err.failure(), Some(VerifyFailure::Signature));
+        assert!(matches!(
+            err,
+            CertError::VcekTcbMismatch { component: "microcode", vcek, reported }
+                if vcek == vcek_tcb.microcode && reported == vcek + 1
+        ));
+
+        // a VCEK issued for a higher bootloader SPL than the report has
+        let mut vcek_tcb = vcek_tcb;
+        vcek_tcb.bootloader += 1;
+        assert!(matches!(
+            verify_vcek_tcb(&report(), &vcek_tcb),
+            Err(CertError::VcekTcbMismatch {
+                component: "bootloader",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_pinned_vcek() {
+        let verify = |verifier: AzSnpVtpm| {
+            verifier.verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        let vcek = parse_vcek(VCEK).unwrap().0.to_der().unwrap();
+        // the chain isn't verified, an unrelated Milan chain doesn't matter
+        let other = include_bytes!("../../test_data/az-snp-vtpm/crl/issuer.pem");
+        let pinned = |der: Vec<u8>| {
+            fixture_verifier()
+                .with_vendor_certs(SnpGeneration::Milan, &unrelated_chain("Milan"))
+                .unwrap()
+                .with_vcek_trust(VcekTrust::PinnedVcek(der))
+        };
+        verify(pinned(vcek)).unwrap();
+
+        let other_der = X509::from_pem(other).unwrap().to_der().unwrap();
+        let err = verify(pinned(other_der)).unwrap_err();
+        let err = err.downcast_ref::<CertError>().unwrap();
+        assert!(matches!(err, CertError::VcekNotPinned));
+        assert_eq!(err.failure(), Some(VerifyFailure::Signature));
+    }
+
+    #[test]
+    fn test_verify_tcb_invariant() {
+        let claim = fixture_verifier()
+            .with_tcb_invariant_check(true)
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        let tcb = json!({"bootloader": 3, "tee": 0, "snp": 8, "microcode": 115});
+        assert_eq!(claim["reported_tcb"], tcb);
+        assert_eq!(claim["committed_tcb"], tcb);
+        assert_eq!(claim["launch_tcb"], tcb);
+        assert_eq!(
+            claim["current_tcb"],
+            json!({"bootloader": 3, "tee": 0, "snp": 8, "microcode": 210})
+        );
+
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let mut snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        verify_tcb_invariant(&snp_report).unwrap();
+        snp_report.committed_tcb.microcode = snp_report.current_tcb.microcode;
+        verify_tcb_invariant(&snp_report).unwrap();
+        snp_report.committed_tcb.snp = snp_report.current_tcb.snp + 1;
+        assert!(matches!(
+            verify_tcb_invariant(&snp_report),
+            Err(CertError::TcbInvariant {
+                component: "snp",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_verify_key_report_data() {
+        let key = b"ephemeral public key";
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let snp_report: AttestationReport = hcl_report.try_into().unwrap();
+
+        // the fixture's report_data binds the HCL var_data
+        assert!(matches!(
+            verify_key_report_data(&snp_report.report_data, key),
+            Err(CertError::SnpReportMismatch)
+        ));
+
+        let report_data = openssl::sha::sha512(key);
+        verify_key_report_data(&report_data, key).unwrap();
+        verify_key_report_data(&report_data, b"another key").unwrap_err();
+    }
+
+    /// The fixture HCL report with the SNP `report_data`, signed by a VCEK
+    /// made of the fixture VCEK with a new key, returned DER encoded. The
+    /// VCEK can only be pinned, its chain is broken.
+    fn resigned_report(report_data: &[u8; 64]) -> (Vec<u8>, Vec<u8>) {
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::ecdsa::EcdsaSig;
+        use openssl::nid::Nid;
+
+        let key = EcKey::generate(&EcGroup::from_curve_name(Nid::SECP384R1).unwrap()).unwrap();
+        let vcek = parse_vcek(VCEK).unwrap().0;
+        let spki = vcek.public_key().unwrap().public_key_to_der().unwrap();
+        let new_spki = PKey::from_ec_key(key.clone())
+            .unwrap()
+            .public_key_to_der()
+            .unwrap();
+        assert_eq!(spki.len(), new_spki.len());
+        let mut vcek_der = vcek.to_der().unwrap();
+        let at = vcek_der
+            .windows(spki.len())
+            .position(|window| window == spki)
+            .unwrap();
+        vcek_der[at..at + spki.len()].copy_from_slice(&new_spki);
+
+        let snp = HCL_SNP_REPORT_OFFSET;
+        let mut report = REPORT.to_vec();
+        report[snp + 0x50..snp + 0x90].copy_from_slice(report_data);
+        let signature =
+            EcdsaSig::sign(&openssl::sha::sha384(&report[snp..snp + 0x2a0]), &key).unwrap();
+        // r and s are little-endian, zero padded to 72 bytes
+        for (offset, component) in [(0x2a0, signature.r()), (0x2a0 + 72, signature.s())] {
+            let mut bytes = component.to_vec_padded(72).unwrap();
+            bytes.reverse();
+            report[snp + offset..snp + offset + 72].copy_from_slice(&bytes);
+        }
+        (report, vcek_der)
+    }
+
+    #[test]
+    fn test_evaluate_key_sha512_binding() {
+        let verifier = fixture_verifier().with_report_data_binding(ReportDataBinding::KeySha512);
+        // the quote nonce isn't checked, the SNP report_data is
+        let err = verifier
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::SnpReportMismatch)
+        ));
+
+        // the SNP report_data is the sha512 of the key
+        let key = b"ephemeral public key";
+        let (report, vcek) = resigned_report(&openssl::sha::sha512(key));
+        let mut evidence: Value = serde_json::from_slice(&evidence_fixture(&report)).unwrap();
+        evidence["vcek"] = json!(STANDARD.encode(&vcek));
+        let claim = verifier
+            .with_vcek_trust(VcekTrust::PinnedVcek(vcek))
+            .verify_evidence(
+                &serde_json::to_vec(&evidence).unwrap(),
+                &ReportData::Value(key),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            claim["report_data"],
+            json!(hex::encode(openssl::sha::sha512(key)))
+        );
+        assert_eq!(claim["report_data_checked"], json!(true));
+    }
+
+    #[test]
+    fn test_parse_vcek() {
+        let pem = parse_vcek(VCEK).unwrap();
+        let der = STANDARD.encode(pem.0.to_der().unwrap());
+        assert_eq!(
+            parse_vcek(&der).unwrap().0.to_der().unwrap(),
+            pem.0.to_der().unwrap()
+        );
+        parse_vcek("not a certificate").err().unwrap();
+        parse_vcek(&STANDARD.encode(b"not a certificate"))
+            .err()
+            .unwrap();
+
+        let verify = |vcek: &str| {
+            let mut evidence: Value = serde_json::from_slice(&evidence_fixture(REPORT)).unwrap();
+            evidence["vcek"] = vcek.into();
+            fixture_verifier().verify_evidence(
+                &serde_json::to_vec(&evidence).unwrap(),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        assert_eq!(verify(&der).unwrap(), verify(VCEK).unwrap());
+    }
+
+    #[test]
+    fn test_verify_vcek_validity() {
+        // the VCEK fixture is valid from 2023-05-02 to 2030-05-02 21:22:19 UTC
+        let not_before = UNIX_EPOCH + Duration::from_secs(1683062539);
+        let not_after = UNIX_EPOCH + Duration::from_secs(1903987339);
+        let clock = Arc::new(FixedClock::new(not_before));
+        let verifier = AzSnpVtpm::new().unwrap().with_clock(clock.clone());
+        let verify = || {
+            verifier.verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+
+        verify().unwrap();
+        clock.set(not_after);
+        verify().unwrap();
+
+        for time in [
+            not_after + Duration::from_secs(1),
+            not_before - Duration::from_secs(1),
+        ] {
+            clock.set(time);
+            let err = verify().unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::VcekNotValid)
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verification_time() {
+        let verify = |time| {
+            AzSnpVtpm::new()
+                .unwrap()
+                .with_verification_time(time)
+                .verify_evidence(
+                    &evidence_fixture(REPORT),
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                    None,
+                )
+        };
+        // within the validity of the VCEK fixture, then past its expiry
+        verify(VerificationTime::Fixed(
+            UNIX_EPOCH + Duration::from_secs(FIXTURE_NOW),
+        ))
+        .unwrap();
+        let err = verify(VerificationTime::Fixed(
+            UNIX_EPOCH + Duration::from_secs(1903987340),
+        ))
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::VcekNotValid)
+        ));
+    }
+
+    #[test]
+    fn test_verify_vendor_certs_validity() {
+        // the Milan ARK and ASK are valid from 2020-10-22 to 2045-10-22
+        let vendor_certs = load_milan_cert_chain().as_ref().unwrap();
+        let vcek_not_before = UNIX_EPOCH + Duration::from_secs(1683062539);
+        verify_vendor_certs_validity(vendor_certs, vcek_not_before).unwrap();
+
+        for time in [
+            UNIX_EPOCH + Duration::from_secs(1577836800),
+            UNIX_EPOCH + Duration::from_secs(2398377600),
+        ] {
+            assert!(matches!(
+                verify_vendor_certs_validity(vendor_certs, time),
+                Err(CertError::VendorCertNotValid("ARK"))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_microcode_policy() {
+        // reported_tcb microcode of the fixture is 115
+        let verify = |policy| {
+            fixture_verifier()
+                .with_microcode_policy(policy)
+                .verify_evidence(
+                    &evidence_fixture(REPORT),
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                    None,
+                )
+        };
+
+        let claim = verify(MicrocodePolicy::Allowed([115, 120].into())).unwrap();
+        assert_eq!(claim["microcode_svn"], json!(115));
+        verify(MicrocodePolicy::Minimum(115)).unwrap();
+
+        for policy in [
+            MicrocodePolicy::Allowed([114, 116].into()),
+            MicrocodePolicy::Minimum(116),
+        ] {
+            let err = verify(policy).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::MicrocodeNotAllowed(115))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_min_abi() {
+        // the guest policy of the fixture is 0x3001f, requiring ABI 0.31
+        let verify = |major, minor| {
+            fixture_verifier()
+                .with_min_abi(AbiVersion { major, minor })
+                .verify_evidence(
+                    &evidence_fixture(REPORT),
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                    None,
+                )
+        };
+
+        let claim = verify(0, 31).unwrap();
+        assert_eq!(claim["policy_abi_major"], "0");
+        assert_eq!(claim["policy_abi_minor"], "31");
+        verify(0, 0).unwrap();
+
+        for (major, minor) in [(0, 32), (1, 0)] {
+            let err = verify(major, minor).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::AbiTooLow {
+                    reported: AbiVersion {
+                        major: 0,
+                        minor: 31
+                    },
+                    ..
+                })
+            ));
+        }
+    }
+
+    #[test]
+    fn test_verify_guest_policy() {
+        let strict = GuestPolicyConstraints {
+            no_debug: true,
+            no_migration: true,
+            min_abi: Some(AbiVersion {
+                major: 0,
+                minor: 31,
+            }),
+        };
+        let snp_report = |report: Vec<u8>| -> AttestationReport {
+            HclReport::new(report).unwrap().try_into().unwrap()
+        };
+        verify_guest_policy(&snp_report(REPORT.to_vec()), &strict).unwrap();
+
+        // set DEBUG, bit 19 of the guest policy at offset 0x08 of the report
+        let mut debug = REPORT.to_vec();
+        debug[32 + 0x08 + 2] |= 1 << 3;
+        let debug = snp_report(debug);
+        assert_ne!(debug.policy.debug_allowed(), 0);
+        assert!(matches!(
+            verify_guest_policy(&debug, &strict),
+            Err(CertError::GuestPolicyBitSet("DEBUG"))
+        ));
+        verify_guest_policy(&debug, &GuestPolicyConstraints::default()).unwrap();
+
+        // set MIGRATE_MA, bit 18
+        let mut migration = REPORT.to_vec();
+        migration[32 + 0x08 + 2] |= 1 << 2;
+        assert!(matches!(
+            verify_guest_policy(&snp_report(migration), &strict),
+            Err(CertError::GuestPolicyBitSet("MIGRATE_MA"))
+        ));
+
+        let newer_abi = GuestPolicyConstraints {
+            min_abi: Some(AbiVersion { major: 1, minor: 0 }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            verify_guest_policy(&snp_report(REPORT.to_vec()), &newer_abi),
+            Err(CertError::AbiTooLow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_verify_min_tcb_raw() {
+        // reported_tcb of the fixture is 0x7308000000000003
+        let verify = |raw| {
+            fixture_verifier()
+                .with_min_tcb_raw(raw)
+                .unwrap()
+                .verify_evidence(
+                    &evidence_fixture(REPORT),
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                    None,
+                )
+        };
+        // at and above the floor
+        verify(0x7308_0000_0000_0003).unwrap();
+        verify(0x7000_0000_0000_0002).unwrap();
+
+        for (raw, below) in [
+            (0x7408_0000_0000_0003, "microcode"),
+            (0x7308_0000_0000_0004, "bootloader"),
+            (0x7309_0000_0000_0003, "snp"),
+        ] {
+            let err = verify(raw).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<CertError>(),
+                Some(CertError::TcbTooLow { component, .. }) if *component == below
+            ));
+        }
+    }
+
+    #[test]
+    fn test_chip_id_claim() {
+        let snp_report: AttestationReport =
+            HclReport::new(REPORT.to_vec()).unwrap().try_into().unwrap();
+        let chip_id = chip_id_claim(&snp_report.chip_id);
+        assert_eq!(chip_id, hex::encode(snp_report.chip_id));
+        assert_eq!(chip_id.len(), 128);
 
-    if snp_report.vmpl != HCL_VMPL_VALUE {
-        return Err(CertError::VmplIncorrect(HCL_VMPL_VALUE));
+        // MaskChipId
+        assert_eq!(chip_id_claim(&[0; 64]), "");
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_extend_claim_with_tcb() {
+        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
+        let snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        let vcek = Vcek::from_pem(VCEK).unwrap();
+        let vcek_tcb = Tcb::from_vcek_der(&vcek.0.to_der().unwrap()).unwrap();
+        let mut claim = parse_tee_evidence(&snp_report);
+        extend_claim_with_tcb(&mut claim, &snp_report, &vcek_tcb).unwrap();
 
-pub(crate) fn verify_init_data(expected: &InitDataHash, pcrs: &[&[u8; 32]]) -> Result<()> {
-    let InitDataHash::Value(expected_init_data_hash) = expected else {
-        debug!("No expected value, skipping init_data verification");
-        return Ok(());
-    };
+        let tcb = json!({"bootloader": 3, "tee": 0, "snp": 8, "microcode": 115});
+        assert_eq!(claim["reported_tcb"], tcb);
+        assert_eq!(claim["committed_tcb"], tcb);
+        assert_eq!(claim["vcek_tcb"], tcb);
+    }
 
-    debug!("Check the binding of PCR{INITDATA_PCR}");
+    #[test]
+    fn test_evaluate_blocking() {
+        let verifier = fixture_verifier();
+        let claim = verifier
+            .evaluate_blocking(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        assert_eq!(claim["report_data"], json!(hex::encode(REPORT_DATA)));
 
-    // sha256(0x00 * 32 || expected_init_data_hash)
-    let mut input = [0u8; 64];
-    input[32..].copy_from_slice(expected_init_data_hash);
-    let digest = openssl::sha::sha256(&input);
+        verifier
+            .evaluate_blocking(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(b"stale"),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+    }
 
-    let init_data_pcr = pcrs[INITDATA_PCR];
-    if &digest != init_data_pcr {
-        bail!("Expected init_data digest is different from the content of PCR{INITDATA_PCR}");
+    #[test]
+    fn test_evaluate_with_var_data_hash() {
+        let verifier = fixture_verifier().with_strict_var_data_hash(true);
+        let var_data_hash = HclReport::new(REPORT.to_vec()).unwrap().var_data_sha256();
+        let claim = verifier
+            .evaluate_with_var_data_hash(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                &var_data_hash,
+            )
+            .unwrap();
+        assert_eq!(claim["external_var_data_hash_matched"], json!(true));
     }
-    Ok(())
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use az_snp_vtpm::vtpm::VerifyError;
-    use serde_json::json;
+    #[test]
+    fn test_runtime_data_claim() {
+        // the fixture's var_data is a JSON document with the guest's keys
+        let claim = fixture_verifier()
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        let var_data = hcl_var_data(REPORT).unwrap();
+        assert_eq!(var_data.len(), 1110);
+        assert_eq!(
+            claim["runtime_data"]["raw"],
+            json!(STANDARD.encode(var_data))
+        );
+        assert_eq!(claim["runtime_data"]["json"]["keys"][0]["kid"], "HCLAkPub");
 
-    const REPORT: &[u8; 2600] = include_bytes!("../../test_data/az-snp-vtpm/hcl-report.bin");
-    const QUOTE: &[u8; 1170] = include_bytes!("../../test_data/az-snp-vtpm/quote.bin");
-    const REPORT_DATA: &[u8] = "challenge".as_bytes();
+        // opaque runtime data is only surfaced base64 encoded
+        let claim = runtime_data_claim(&[0, 1, 2]);
+        assert_eq!(claim, json!({ "raw": "AAEC" }));
 
-    #[test]
-    fn test_verify_snp_report() {
-        let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
-        let snp_report = hcl_report.try_into().unwrap();
-        let vcek = Vcek::from_pem(include_str!("../../test_data/az-snp-vtpm/vcek.pem")).unwrap();
-        let vendor_certs = load_milan_cert_chain().as_ref().unwrap();
-        verify_snp_report(&snp_report, &vcek, vendor_certs).unwrap();
+        // the var_data isn't bound to the SNP report with the key binding
+        let claim = fixture_verifier()
+            .with_report_data_binding(ReportDataBinding::KeySha512)
+            .with_empty_report_data_allowed(true)
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::NotProvided,
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        assert!(claim.get("runtime_data").is_none());
     }
 
     #[test]
-    fn test_verify_snp_report_failure() {
-        let mut wrong_report = REPORT.clone();
-        // messing with snp report
-        wrong_report[0x01a6] = 0;
-        let hcl_report = HclReport::new(wrong_report.to_vec()).unwrap();
-        let snp_report = hcl_report.try_into().unwrap();
-        let vcek = Vcek::from_pem(include_str!("../../test_data/az-snp-vtpm/vcek.pem")).unwrap();
-        let vendor_certs = load_milan_cert_chain().as_ref().unwrap();
+    fn test_evaluate_with_runtime_data() {
+        // the fixture's var_data is the guest's keys, among them the AK
+        let runtime_data = hcl_var_data(REPORT).unwrap().to_vec();
+        assert!(runtime_data.starts_with(b"{\"keys\":"));
+        let evaluate = |verifier: &AzSnpVtpm, runtime_data: Option<Vec<u8>>| {
+            verifier.evaluate_with_runtime_data(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                runtime_data,
+            )
+        };
+        let verifier = fixture_verifier();
+        let claim = evaluate(&verifier, Some(runtime_data.clone())).unwrap();
         assert_eq!(
-            verify_snp_report(&snp_report, &vcek, vendor_certs)
-                .unwrap_err()
-                .to_string(),
-            "SNP version mismatch",
+            claim["runtime_data"]["raw"],
+            json!(STANDARD.encode(&runtime_data))
         );
+        evaluate(&verifier, None).unwrap();
+
+        let mut tampered = runtime_data.clone();
+        tampered[10] ^= 1;
+        let err = evaluate(&verifier, Some(tampered)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::SnpReportMismatch)
+        ));
+
+        let verifier = verifier.with_report_data_binding(ReportDataBinding::KeySha512);
+        evaluate(&verifier, Some(runtime_data)).unwrap_err();
+    }
+
+    #[test]
+    fn test_evaluate_with_var_data_hash_failure() {
+        let mut var_data_hash = HclReport::new(REPORT.to_vec()).unwrap().var_data_sha256();
+        var_data_hash[0] ^= 1;
+        let evaluate = |verifier: AzSnpVtpm| {
+            verifier.evaluate_with_var_data_hash(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                &var_data_hash,
+            )
+        };
+        // the mismatch is only recorded unless strict
+        let claim = evaluate(fixture_verifier()).unwrap();
+        assert_eq!(claim["external_var_data_hash_matched"], json!(false));
+
+        let err = evaluate(fixture_verifier().with_strict_var_data_hash(true)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::VarDataHashMismatch)
+        ));
     }
 
     #[test]
     fn test_verify_report_data() {
         let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
         let var_data_hash = hcl_report.var_data_sha256();
-        let snp_report = hcl_report.try_into().unwrap();
-        verify_report_data(&var_data_hash, &snp_report).unwrap();
+        let snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        verify_report_data(&var_data_hash, &snp_report.report_data).unwrap();
+
+        // a report_data too short to hold the hash
+        let err = verify_report_data(&var_data_hash, &snp_report.report_data[..31]);
+        assert!(matches!(err, Err(CertError::SnpReportMismatch)));
+        let err = verify_ak_binding(&var_data_hash, &[]);
+        assert!(matches!(err, Err(CertError::AkNotBound)));
     }
 
     #[test]
@@ -258,13 +2712,84 @@ mod tests {
         wrong_report[0x06e0] += 1;
         let hcl_report = HclReport::new(wrong_report.to_vec()).unwrap();
         let var_data_hash = hcl_report.var_data_sha256();
-        let snp_report = hcl_report.try_into().unwrap();
-        assert_eq!(
-            verify_report_data(&var_data_hash, &snp_report)
-                .unwrap_err()
-                .to_string(),
-            "SNP report report_data mismatch"
-        );
+        let snp_report: AttestationReport = hcl_report.try_into().unwrap();
+        assert!(matches!(
+            verify_report_data(&var_data_hash, &snp_report.report_data),
+            Err(CertError::SnpReportMismatch)
+        ));
+    }
+
+    /// The fixture quote signed by `key` instead of the fixture AK
+    fn quote_signed_by(key: &PKey<openssl::pkey::Private>) -> Quote {
+        let message = &QUOTE[8 + 256 + 8..8 + 256 + 8 + 122];
+        let mut signer =
+            openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), key).unwrap();
+        let signature = signer.sign_oneshot_to_vec(message).unwrap();
+
+        let mut quote = (signature.len() as u64).to_le_bytes().to_vec();
+        quote.extend(signature);
+        quote.extend(&QUOTE[8 + 256..]);
+        bincode::deserialize(&quote).unwrap()
+    }
+
+    #[test]
+    fn test_spliced_ak_rejected() {
+        // the quote is signed by another AK, which replaces the AK of the
+        // HCL var_data, whose hash then no longer matches the SNP report
+        let rsa = openssl::rsa::Rsa::generate(2048).unwrap();
+        let modulus = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(rsa.n().to_vec());
+        let key = PKey::from_rsa(rsa).unwrap();
+        let ak = REPORT
+            .windows(b"HCLAkPub".len())
+            .position(|w| w == b"HCLAkPub")
+            .unwrap();
+        let n = ak
+            + REPORT[ak..]
+                .windows(5)
+                .position(|w| w == b"\"n\":\"")
+                .unwrap()
+            + 5;
+        let mut report = REPORT.to_vec();
+        report[n..n + modulus.len()].copy_from_slice(modulus.as_bytes());
+
+        let evidence = Evidence {
+            quote: quote_signed_by(&key),
+            report: report.clone(),
+            vcek: VCEK.to_string(),
+            pcrs_sha384: None,
+            event_log: None,
+        };
+        let hcl_report = HclReport::new(report).unwrap();
+        verify_signature(&evidence.quote, &hcl_report).unwrap();
+
+        let evidence = serde_json::to_vec(&evidence).unwrap();
+        let var_data_hash = HclReport::new(REPORT.to_vec()).unwrap().var_data_sha256();
+        let verifier = fixture_verifier();
+        let err = verifier
+            .evaluate_with_var_data_hash(
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                &var_data_hash,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::AkNotBound)
+        ));
+
+        let err = verifier
+            .verify_evidence(
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::SnpReportMismatch)
+        ));
     }
 
     #[test]
@@ -281,14 +2806,12 @@ mod tests {
         let wrong_quote: Quote = bincode::deserialize(&quote).unwrap();
 
         let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
-        assert_eq!(
-            verify_signature(&wrong_quote, &hcl_report)
-                .unwrap_err()
-                .downcast_ref::<VerifyError>()
-                .unwrap()
-                .to_string(),
-            VerifyError::SignatureMismatch.to_string()
-        );
+        let err = verify_signature(&wrong_quote, &hcl_report).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<VerifyError>(),
+            Some(VerifyError::SignatureMismatch)
+        ));
+        assert_eq!(classify_error(&err), Some(VerifyFailure::Signature));
     }
 
     #[test]
@@ -306,6 +2829,210 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_evidence_kind() {
+        let verifier = fixture_verifier();
+        assert_eq!(verifier.evidence_kind(), "az-snp-vtpm");
+        assert!(verifier.can_handle(&evidence_fixture(REPORT)));
+        assert!(!verifier.can_handle(b"{}"));
+        assert!(!verifier.can_handle(br#"{"quote": {}, "report": [], "vcek": ""}"#));
+        assert!(!verifier.can_handle(REPORT));
+    }
+
+    #[test]
+    fn test_evidence_version() {
+        let verify = |evidence: &Value| {
+            fixture_verifier().verify_evidence(
+                &serde_json::to_vec(evidence).unwrap(),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        // the unversioned evidence is version 1
+        let mut evidence: Value = serde_json::from_slice(&evidence_fixture(REPORT)).unwrap();
+        verify(&evidence).unwrap();
+        evidence["version"] = json!(1);
+        verify(&evidence).unwrap();
+
+        evidence["version"] = json!(2);
+        evidence["attestation"] = evidence["quote"].take();
+        let err = verify(&evidence).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::UnsupportedEvidenceVersion {
+                found: 2,
+                supported: [1]
+            })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "Evidence version 2 is not supported, supported versions are [1]"
+        );
+        assert!(!fixture_verifier().can_handle(&serde_json::to_vec(&evidence).unwrap()));
+
+        evidence["version"] = json!("2");
+        verify(&evidence).unwrap_err();
+    }
+
+    #[test]
+    fn test_empty_report_data() {
+        let verify = |verifier: AzSnpVtpm, report_data: &ReportData| {
+            verifier.verify_evidence(
+                &evidence_fixture(REPORT),
+                report_data,
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        let claim = verify(fixture_verifier(), &ReportData::Value(REPORT_DATA)).unwrap();
+        assert_eq!(claim["report_data_checked"], json!(true));
+
+        // rejected by default
+        let err = verify(fixture_verifier(), &ReportData::NotProvided).unwrap_err();
+        assert_eq!(err.to_string(), "unexpected empty report data");
+
+        let allowed = || fixture_verifier().with_empty_report_data_allowed(true);
+        let claim = verify(allowed(), &ReportData::NotProvided).unwrap();
+        assert_eq!(claim["report_data_checked"], json!(false));
+        // the SNP report is still bound to the HCL var_data
+        assert_eq!(claim["report_data"], json!(hex::encode(REPORT_DATA)));
+
+        // a provided report_data is still checked
+        verify(allowed(), &ReportData::Value(b"stale")).unwrap_err();
+        let claim = verify(
+            allowed().with_report_data_binding(ReportDataBinding::KeySha512),
+            &ReportData::NotProvided,
+        )
+        .unwrap();
+        assert_eq!(claim["report_data_checked"], json!(false));
+    }
+
+    #[test]
+    fn test_strict_evidence_fields() {
+        let verify = |verifier: AzSnpVtpm, evidence: &Value| {
+            verifier.verify_evidence(
+                &serde_json::to_vec(evidence).unwrap(),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        let strict = || fixture_verifier().with_strict_evidence_fields(true);
+        let mut evidence: Value = serde_json::from_slice(&evidence_fixture(REPORT)).unwrap();
+        evidence["version"] = json!(1);
+        verify(strict(), &evidence).unwrap();
+
+        evidence["foo"] = json!("bar");
+        verify(fixture_verifier(), &evidence).unwrap();
+        let err = verify(strict(), &evidence).unwrap_err();
+        let err = err.downcast_ref::<CertError>().unwrap();
+        assert!(matches!(err, CertError::UnknownEvidenceField(field) if field == "foo"));
+        assert_eq!(err.failure(), Some(VerifyFailure::Deserialization));
+        assert_eq!(err.to_string(), "Evidence has the unknown field `foo`");
+    }
+
+    #[test]
+    fn test_evaluate_format() {
+        let verifier = fixture_verifier();
+        let evidence = evidence_fixture(REPORT);
+        let evaluate = |evidence: &[u8], format| {
+            verifier.evaluate_format(
+                evidence,
+                format,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+        };
+        let expected = verifier
+            .evaluate_blocking(
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        assert_eq!(evaluate(&evidence, EvidenceFormat::Json).unwrap(), expected);
+
+        assert_eq!(
+            EvidenceFormat::from_content_type("application/json; charset=utf-8").unwrap(),
+            EvidenceFormat::Json
+        );
+        EvidenceFormat::from_content_type("text/plain").unwrap_err();
+
+        #[cfg(feature = "cbor")]
+        {
+            let mut cbor = vec![];
+            ciborium::into_writer(&Evidence::from_slice(&evidence).unwrap(), &mut cbor).unwrap();
+            assert_eq!(evaluate(&cbor, EvidenceFormat::Cbor).unwrap(), expected);
+            assert_eq!(
+                EvidenceFormat::from_content_type("application/cbor").unwrap(),
+                EvidenceFormat::Cbor
+            );
+
+            let err = evaluate(&evidence, EvidenceFormat::Cbor).unwrap_err();
+            assert_eq!(classify_error(&err), Some(VerifyFailure::Deserialization));
+        }
+    }
+
+    #[test]
+    fn test_classify_error() {
+        let classify =
+            |verifier: AzSnpVtpm, evidence: &[u8], report_data: &[u8], init_data: InitDataHash| {
+                let err = verifier
+                    .evaluate_blocking(evidence, &ReportData::Value(report_data), &init_data)
+                    .unwrap_err();
+                verifier.classify_error(&err)
+            };
+        let verifier = fixture_verifier;
+        let evidence = evidence_fixture(REPORT);
+        let no_init_data = || InitDataHash::NotProvided;
+
+        assert_eq!(
+            classify(verifier(), b"{}", REPORT_DATA, no_init_data()),
+            Some(VerifyFailure::Deserialization)
+        );
+        assert_eq!(
+            classify(verifier(), &evidence, b"egnellahc", no_init_data()),
+            Some(VerifyFailure::ReportData)
+        );
+        assert_eq!(
+            classify(
+                verifier(),
+                &evidence,
+                REPORT_DATA,
+                InitDataHash::Value(&[0; 32])
+            ),
+            Some(VerifyFailure::InitData)
+        );
+        assert_eq!(
+            classify(
+                verifier().with_expected_pcrs(HashMap::from([(0, [0; 32])])),
+                &evidence,
+                REPORT_DATA,
+                no_init_data()
+            ),
+            Some(VerifyFailure::Pcr)
+        );
+        assert_eq!(
+            classify(
+                verifier().with_vmpl(1),
+                &evidence,
+                REPORT_DATA,
+                no_init_data()
+            ),
+            Some(VerifyFailure::Vmpl)
+        );
+        assert_eq!(
+            classify(
+                verifier(),
+                &evidence_fixture(&[&REPORT[..0x1a6], &[0], &REPORT[0x1a7..]].concat()),
+                REPORT_DATA,
+                no_init_data()
+            ),
+            Some(VerifyFailure::Signature)
+        );
+    }
+
     #[test]
     fn test_verify_quote_nonce() {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
@@ -317,13 +3044,124 @@ mod tests {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
         let mut wrong_report_data = REPORT_DATA.to_vec();
         wrong_report_data.reverse();
-        verify_nonce(&quote, &wrong_report_data).unwrap_err();
+        assert!(matches!(
+            verify_nonce(&quote, &wrong_report_data),
+            Err(VtpmError::NonceMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_verify_quote_nonce_length() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        assert!(matches!(
+            verify_nonce(&quote, &REPORT_DATA[..4]),
+            Err(VtpmError::NonceLength {
+                expected: 4,
+                actual: 9
+            })
+        ));
+        assert!(matches!(
+            verify_nonce(&quote, &[REPORT_DATA, b"-and-more"].concat()),
+            Err(VtpmError::NonceLength {
+                expected: 18,
+                actual: 9
+            })
+        ));
+
+        // a 32-byte nonce isn't zero-filled to the 64-byte SNP field
+        let nonce = [7u8; 32];
+        let quote = quote_with_nonce(&nonce);
+        verify_nonce(&quote, &nonce).unwrap();
+        assert!(matches!(
+            verify_nonce(&quote, &[nonce, [0; 32]].concat()),
+            Err(VtpmError::NonceLength {
+                expected: 64,
+                actual: 32
+            })
+        ));
+    }
+
+    #[test]
+    fn test_verify_nonce_segments() {
+        // a server nonce followed by a client-derived value
+        let nonce = [[1u8; 32], [2u8; 32]].concat();
+        let quote = quote_with_nonce(&nonce);
+        let server = (0, vec![1u8; 32]);
+        verify_nonce_segments(&quote, &[server.clone(), (32, vec![2u8; 32])]).unwrap();
+        // the other bytes are unconstrained
+        verify_nonce_segments(&quote, &[(4, vec![1u8; 8])]).unwrap();
+
+        assert!(matches!(
+            verify_nonce_segments(&quote, &[server.clone(), (32, vec![3u8; 32])]),
+            Err(VtpmError::NonceSegmentMismatch { offset: 32 })
+        ));
+        assert!(matches!(
+            verify_nonce_segments(&quote, &[server, (48, vec![2u8; 32])]),
+            Err(VtpmError::NonceSegmentMismatch { offset: 48 })
+        ));
+        verify_nonce_segments(&quote, &[]).unwrap_err();
+    }
+
+    #[test]
+    fn test_report_data_segments() {
+        let verify = |verifier: AzSnpVtpm, segments: Vec<(usize, Vec<u8>)>| {
+            verifier.evaluate_blocking(
+                &evidence_fixture(REPORT),
+                &ReportData::Segments(segments),
+                &InitDataHash::NotProvided,
+            )
+        };
+        let claim = verify(
+            fixture_verifier(),
+            vec![(0, b"chall".to_vec()), (5, b"enge".to_vec())],
+        )
+        .unwrap();
+        assert_eq!(claim["report_data_checked"], true);
+
+        let err = verify(
+            fixture_verifier(),
+            vec![(0, b"chall".to_vec()), (5, b"ange".to_vec())],
+        )
+        .unwrap_err();
+        assert_eq!(classify_error(&err), Some(VerifyFailure::ReportData));
+
+        let verifier = fixture_verifier().with_report_data_binding(ReportDataBinding::KeySha512);
+        verify(verifier, vec![(0, b"chall".to_vec())]).unwrap_err();
+    }
+
+    #[test]
+    fn test_incomplete_pcr_set() {
+        // the fixture PCRs are prefixed by their u64 count
+        let pcrs = 8 + 256 + 8 + 122;
+        let mut truncated = QUOTE[..pcrs].to_vec();
+        truncated.extend(8u64.to_le_bytes());
+        truncated.extend(&QUOTE[pcrs + 8..pcrs + 8 + 8 * 32]);
+        let quote: Quote = bincode::deserialize(&truncated).unwrap();
+        assert_eq!(quote.pcrs_sha256().count(), 8);
+
+        assert!(matches!(
+            verify_pcrs(&quote, None),
+            Err(VtpmError::PcrSelectionMismatch {
+                bank: "sha256",
+                selected: 24,
+                found: 8
+            })
+        ));
+        let err = extend_claim(&mut json!({}), &quote, INITDATA_PCR).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<VtpmError>(),
+            Some(VtpmError::PcrSelectionMismatch {
+                bank: "sha256",
+                selected: 24,
+                found: 8
+            })
+        ));
     }
 
     #[test]
     fn test_verify_pcrs() {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
-        verify_pcrs(&quote).unwrap();
+        verify_pcrs(&quote, None).unwrap();
     }
 
     #[test]
@@ -332,16 +3170,273 @@ mod tests {
         quote[0x0169] = 0;
         let wrong_quote: Quote = bincode::deserialize(&quote).unwrap();
 
+        let err = vtpm_error(verify_pcrs(&wrong_quote, None).unwrap_err());
+        assert!(matches!(
+            err.downcast_ref::<VerifyError>(),
+            Some(VerifyError::PcrMismatch)
+        ));
+        assert_eq!(classify_error(&err), Some(VerifyFailure::Pcr));
+    }
+
+    #[test]
+    fn test_verify_pcr_digest() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        verify_pcr_digest(&quote, None).unwrap();
+    }
+
+    #[test]
+    fn test_verify_pcr_digest_failure() {
+        let mut quote = *QUOTE;
+        // messing with the value of PCR23
+        quote[QUOTE.len() - 1] ^= 1;
+        let wrong_quote: Quote = bincode::deserialize(&quote).unwrap();
+
+        assert!(matches!(
+            verify_pcr_digest(&wrong_quote, None),
+            Err(VtpmError::PcrDigestMismatch)
+        ));
+    }
+
+    /// An unsigned quote of PCR0 and PCR1 in both the SHA-256 and SHA-384
+    /// banks, and the values of its SHA-384 PCRs
+    fn dual_bank_quote() -> (Quote, Vec<Vec<u8>>) {
+        dual_bank_quote_of(&[[1u8; 32], [2u8; 32]], vec![vec![3u8; 48], vec![4u8; 48]])
+    }
+
+    /// An unsigned quote of the first PCRs in both the SHA-256 and SHA-384
+    /// banks, with the given values
+    fn dual_bank_quote_of(
+        sha256_pcrs: &[[u8; 32]],
+        sha384_pcrs: Vec<Vec<u8>>,
+    ) -> (Quote, Vec<Vec<u8>>) {
+        let composite = [sha256_pcrs.concat(), sha384_pcrs.concat()].concat();
+        let select = |count: usize| {
+            let mut bitmap = [0u8; 3];
+            for index in 0..count {
+                bitmap[index / 8] |= 1 << (index % 8);
+            }
+            bitmap
+        };
+
+        let mut message = vec![];
+        message.extend(0xff54_4347u32.to_be_bytes());
+        message.extend(0x8018u16.to_be_bytes());
+        // empty qualifiedSigner and extraData, clockInfo and firmwareVersion
+        message.extend([0; 2 + 2 + 17 + 8]);
+        message.extend(2u32.to_be_bytes());
+        message.extend([0x00, 0x0b, 3]);
+        message.extend(select(sha256_pcrs.len()));
+        message.extend([0x00, 0x0c, 3]);
+        message.extend(select(sha384_pcrs.len()));
+        message.extend(32u16.to_be_bytes());
+        message.extend(openssl::sha::sha256(&composite));
+
+        // bincode: signature, message and PCRs, each prefixed by a u64 length
+        let mut quote = vec![];
+        quote.extend(256u64.to_le_bytes());
+        quote.extend([0; 256]);
+        quote.extend((message.len() as u64).to_le_bytes());
+        quote.extend(message);
+        quote.extend((sha256_pcrs.len() as u64).to_le_bytes());
+        quote.extend(sha256_pcrs.concat());
+        (bincode::deserialize(&quote).unwrap(), sha384_pcrs)
+    }
+
+    /// An unsigned quote of the SHA-256 PCRs at `indices`, each filled with
+    /// its index
+    pub(super) fn sparse_quote(indices: &[usize]) -> Quote {
+        let pcrs: Vec<[u8; 32]> = indices.iter().map(|index| [*index as u8; 32]).collect();
+        let mut bitmap = [0u8; 3];
+        for index in indices {
+            bitmap[index / 8] |= 1 << (index % 8);
+        }
+
+        let mut message = vec![];
+        message.extend(0xff54_4347u32.to_be_bytes());
+        message.extend(0x8018u16.to_be_bytes());
+        // empty qualifiedSigner and extraData, clockInfo and firmwareVersion
+        message.extend([0; 2 + 2 + 17 + 8]);
+        message.extend(1u32.to_be_bytes());
+        message.extend([0x00, 0x0b, 3]);
+        message.extend(bitmap);
+        message.extend(32u16.to_be_bytes());
+        message.extend(openssl::sha::sha256(&pcrs.concat()));
+
+        let mut quote = vec![];
+        quote.extend(256u64.to_le_bytes());
+        quote.extend([0; 256]);
+        quote.extend((message.len() as u64).to_le_bytes());
+        quote.extend(message);
+        quote.extend((pcrs.len() as u64).to_le_bytes());
+        quote.extend(pcrs.concat());
+        bincode::deserialize(&quote).unwrap()
+    }
+
+    #[test]
+    fn test_extend_claim_sparse_selection() {
+        let quote = sparse_quote(&[0, 4, 7, 8]);
+        verify_pcr_digest(&quote, None).unwrap();
+
+        let mut claim = json!({});
+        extend_claim(&mut claim, &quote, INITDATA_PCR).unwrap();
         assert_eq!(
-            verify_pcrs(&wrong_quote)
-                .unwrap_err()
-                .downcast_ref::<VerifyError>()
-                .unwrap()
-                .to_string(),
-            VerifyError::PcrMismatch.to_string()
+            claim["tpm"],
+            json!({
+                "pcr00": "00".repeat(32),
+                "pcr04": "04".repeat(32),
+                "pcr07": "07".repeat(32),
+                "pcr08": "08".repeat(32),
+            })
+        );
+        assert_eq!(claim["init_data"], "08".repeat(32));
+
+        // the PCRs are looked up by index by all the checks
+        verify_pcr_count(&quote).unwrap();
+        verify_pcr_policy(&quote, &HashMap::from([(7, [7; 32]), (8, [8; 32])])).unwrap();
+        let err = verify_pcr_policy(&quote, &HashMap::from([(1, [1; 32])])).unwrap_err();
+        assert!(matches!(err, CertError::PcrMismatch { index: 1, .. }));
+        verify_secure_boot(&quote, &[[7; 32]]).unwrap();
+
+        let quote = sparse_quote(&[0, 4, 7]);
+        let err = extend_claim(&mut json!({}), &quote, INITDATA_PCR).unwrap_err();
+        assert!(err.to_string().contains("no init_data PCR8"));
+    }
+
+    #[test]
+    fn test_verify_pcrs_sha384() {
+        let (quote, pcrs_sha384) = dual_bank_quote();
+        verify_pcrs(&quote, Some(pcrs_sha384.as_slice())).unwrap();
+        // the SHA-384 bank is selected, but its values are missing
+        verify_pcrs(&quote, None).unwrap_err();
+
+        let mut wrong_pcrs = pcrs_sha384.clone();
+        wrong_pcrs[1][0] ^= 1;
+        assert!(matches!(
+            verify_pcr_digest(&quote, Some(wrong_pcrs.as_slice())),
+            Err(VtpmError::PcrDigestMismatch)
+        ));
+        verify_pcr_digest(&quote, Some(&pcrs_sha384[..1])).unwrap_err();
+
+        // the SHA-256 only quote doesn't select a SHA-384 bank
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        verify_pcrs(&quote, Some(pcrs_sha384.as_slice())).unwrap_err();
+    }
+
+    #[test]
+    fn test_extend_claim_with_sha384() {
+        let (quote, pcrs_sha384) = dual_bank_quote();
+        let mut claim = json!({});
+        extend_claim_with_sha384(&mut claim, &quote, None).unwrap();
+        assert_eq!(claim, json!({}));
+
+        extend_claim_with_sha384(&mut claim, &quote, Some(pcrs_sha384.as_slice())).unwrap();
+        assert_eq!(
+            claim["tpm_sha384"],
+            json!({"pcr00": "03".repeat(48), "pcr01": "04".repeat(48)})
         );
     }
 
+    #[test]
+    fn test_evaluate_event_log() {
+        let verify = |event_log| {
+            let mut evidence: Value = serde_json::from_slice(&evidence_fixture(REPORT)).unwrap();
+            evidence["event_log"] = json!(event_log);
+            fixture_verifier().verify_evidence(
+                &serde_json::to_vec(&evidence).unwrap(),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        // a log extending no PCR leaves nothing to compare
+        let claim = verify(eventlog::tests::event_log(&[])).unwrap();
+        assert_eq!(claim["event_log"], json!([]));
+
+        // the fixture PCRs aren't reproduced by a synthetic event
+
+        let err = verify(eventlog::tests::event_log(&[(10, 0xd, [1; 32])])).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::EventLogMismatch(10))
+        ));
+    }
+
+    #[test]
+    fn test_verify_pcr_policy() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let pcrs: Vec<[u8; 32]> = quote.pcrs_sha256().copied().collect();
+
+        let all = pcrs.iter().copied().enumerate().collect();
+        verify_pcr_policy(&quote, &all).unwrap();
+        verify_pcr_policy(&quote, &HashMap::new()).unwrap();
+
+        // only PCR4 and PCR7 are pinned
+        let mut secure_boot = HashMap::from([(4, pcrs[4]), (7, pcrs[7])]);
+        verify_pcr_policy(&quote, &secure_boot).unwrap();
+
+        let mut wrong = pcrs[7];
+        wrong[0] ^= 1;
+        secure_boot.insert(7, wrong);
+        let err = verify_pcr_policy(&quote, &secure_boot).unwrap_err();
+        assert!(matches!(
+            &err,
+            CertError::PcrMismatch { index: 7, expected, actual }
+                if *expected == hex::encode(wrong) && *actual == hex::encode(pcrs[7])
+        ));
+        assert!(err.to_string().starts_with("PCR07 is "));
+
+        let missing = HashMap::from([(24, pcrs[0])]);
+        verify_pcr_policy(&quote, &missing).unwrap_err();
+    }
+
+    #[test]
+    fn test_evaluate_expected_pcrs() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let pcr7 = *quote.pcrs_sha256().nth(7).unwrap();
+        let verify = |expected_pcrs| {
+            fixture_verifier()
+                .with_expected_pcrs(expected_pcrs)
+                .verify_evidence(
+                    &evidence_fixture(REPORT),
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                    None,
+                )
+        };
+        verify(HashMap::from([(7, pcr7)])).unwrap();
+
+        let err = verify(HashMap::from([(7, [0; 32])])).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::PcrMismatch { index: 7, .. })
+        ));
+    }
+
+    #[test]
+    fn test_secure_boot_required() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let pcr7 = *quote.pcrs_sha256().nth(7).unwrap();
+        let verify = |enabled_pcr7| {
+            fixture_verifier()
+                .with_secure_boot_required(enabled_pcr7)
+                .verify_evidence(
+                    &evidence_fixture(REPORT),
+                    &ReportData::Value(REPORT_DATA),
+                    &InitDataHash::NotProvided,
+                    None,
+                )
+        };
+        verify(vec![[1; 32], pcr7]).unwrap();
+
+        let err = verify(vec![[1; 32]]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::SecureBootDisabled(actual)) if *actual == hex::encode(pcr7)
+        ));
+        assert_eq!(classify_error(&err), Some(VerifyFailure::Pcr));
+        verify(vec![]).unwrap_err();
+    }
+
     #[test]
     fn test_verify_init_data() {
         let quote = QUOTE.clone();
@@ -361,29 +3456,234 @@ mod tests {
         )
         .unwrap();
 
-        let mut pcrs: Vec<&[u8; 32]> = quote.pcrs_sha256().collect();
-        pcrs[INITDATA_PCR] = &digest;
+        let mut pcrs: BTreeMap<usize, &[u8]> = quote
+            .pcrs_sha256()
+            .map(|pcr| pcr.as_slice())
+            .enumerate()
+            .collect();
+        pcrs.insert(INITDATA_PCR, &digest);
+
+        verify_init_data(
+            &InitDataHash::Value(&init_data_hash),
+            &pcrs,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap();
+
+        assert_eq!(expected_init_data_pcr(&init_data_hash), digest);
+        assert_eq!(
+            InitDataExtend::Zeroed
+                .expected_pcr(PcrBank::Sha256, &init_data_hash)
+                .unwrap(),
+            digest
+        );
+    }
+
+    #[test]
+    fn test_verify_init_data_pcr9() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let init_data_hash = [1u8; 32];
+        let digest = openssl::sha::sha256(&[[0u8; 32], init_data_hash].concat());
+        let mut pcrs: BTreeMap<usize, &[u8]> = quote
+            .pcrs_sha256()
+            .map(|pcr| pcr.as_slice())
+            .enumerate()
+            .collect();
+        pcrs.insert(9, &digest);
+        let init_data_hash = InitDataHash::Value(&init_data_hash);
+
+        verify_init_data(&init_data_hash, &pcrs, 9, &InitDataExtend::Zeroed).unwrap();
+        verify_init_data(
+            &init_data_hash,
+            &pcrs,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap_err();
+        let err =
+            verify_init_data(&init_data_hash, &pcrs, 24, &InitDataExtend::Zeroed).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("doesn't select the init_data PCR24"));
+
+        let mut claim = json!({});
+        extend_claim(&mut claim, &quote, 9).unwrap();
+        assert_eq!(claim["init_data"], claim["tpm"]["pcr09"]);
+        extend_claim(&mut claim, &quote, 24).unwrap_err();
+    }
+
+    #[test]
+    fn test_init_data_extend() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let init_data_hash = [1u8; 32];
+        let initial = [2u8; 32];
+        let zeroed = openssl::sha::sha256(&[[0u8; 32], init_data_hash].concat());
+        let event =
+            openssl::sha::sha256(&[[0u8; 32], openssl::sha::sha256(&init_data_hash)].concat());
+        let from_initial = openssl::sha::sha256(&[initial, init_data_hash].concat());
+        let verify = |pcr: &[u8], extend: &InitDataExtend| {
+            let mut pcrs: BTreeMap<usize, &[u8]> = quote
+                .pcrs_sha256()
+                .map(|pcr| pcr.as_slice())
+                .enumerate()
+                .collect();
+            pcrs.insert(INITDATA_PCR, pcr);
+            verify_init_data(
+                &InitDataHash::Value(&init_data_hash),
+                &pcrs,
+                INITDATA_PCR,
+                extend,
+            )
+        };
+
+        let schemes = [
+            (zeroed, InitDataExtend::Zeroed),
+            (event, InitDataExtend::Event),
+            (from_initial, InitDataExtend::Initial(initial.to_vec())),
+        ];
+        for (pcr, extend) in &schemes {
+            for (other, other_extend) in &schemes {
+                assert_eq!(
+                    verify(other, extend).is_ok(),
+                    pcr == other,
+                    "{other_extend:?}"
+                );
+            }
+        }
+        assert_eq!(InitDataExtend::default(), InitDataExtend::Zeroed);
+
+        // the initial value must be a PCR of the bank of the init_data hash
+        let err = verify(&zeroed, &InitDataExtend::Initial(vec![0; 48])).unwrap_err();
+        assert!(err.to_string().contains("isn't a sha256 PCR"));
+
+        let verifier = fixture_verifier().with_init_data_extend(InitDataExtend::Event);
+        assert_eq!(verifier.policy()["init_data_extend"], json!("event"));
+    }
+
+    #[test]
+    fn test_verify_init_data_sha384() {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let init_data_hash = hex::decode(
+            "448b55207ff5de9e648329cbbb1f9698847a47ac0aaf6abe756c7f705981aa35\
+             e58cc32e7135b8f9aa8aa9e68de8cbb8",
+        )
+        .unwrap();
+
+        // sha384(0x00 * 48 || "448b...") == "2f5d..."
+        let digest = hex::decode(
+            "2f5d42dde4fd5e7aa49f679f7912ba36d875faae5384d46523b274c8803670c1\
+             d16ecc3b62553610e410ce4c6adee822",
+        )
+        .unwrap();
+
+        let mut pcrs: BTreeMap<usize, &[u8]> = quote
+            .pcrs_sha256()
+            .map(|pcr| pcr.as_slice())
+            .enumerate()
+            .collect();
+        let init_data_hash = InitDataHash::Value(&init_data_hash);
+
+        // a 48-byte init_data hash can't be bound to a sha256 PCR
+        verify_init_data(
+            &init_data_hash,
+            &pcrs,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap_err();
+
+        pcrs.insert(INITDATA_PCR, &digest);
+        verify_init_data(
+            &init_data_hash,
+            &pcrs,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap();
+    }
+
+    /// A dual bank quote of all the PCRs, with the init_data `hash` in the
+    /// SHA-384 PCR8 and its truncation in the SHA-256 PCR8
+    fn init_data_sha384_quote(hash: &[u8]) -> (Quote, Vec<Vec<u8>>) {
+        let mut sha256_pcrs = [[0u8; 32]; PCR_COUNT];
+        sha256_pcrs[INITDATA_PCR] = openssl::sha::sha256(&[&[0u8; 32][..], &hash[..32]].concat());
+        let mut sha384_pcrs = vec![vec![0u8; 48]; PCR_COUNT];
+        sha384_pcrs[INITDATA_PCR] = openssl::sha::sha384(&[&[0u8; 48][..], hash].concat()).to_vec();
+        dual_bank_quote_of(&sha256_pcrs, sha384_pcrs)
+    }
+
+    #[test]
+    fn test_verify_init_data_banks() {
+        let hash = [7u8; 48];
+        let (quote, pcrs_sha384) = init_data_sha384_quote(&hash);
+        let verify = |init_data_hash: &[u8], pcrs_sha384: Option<&[Vec<u8>]>| {
+            verify_quote_contents(
+                &quote,
+                &ReportData::NotProvided,
+                &InitDataHash::Value(init_data_hash),
+                INITDATA_PCR,
+                &InitDataExtend::Zeroed,
+                pcrs_sha384,
+            )
+        };
+        verify(&hash, Some(&pcrs_sha384)).unwrap();
+        verify(&hash[..32], Some(&pcrs_sha384)).unwrap();
+        verify(&hash[..32], None).unwrap_err();
+        // the SHA-384 bank is selected but its values are missing
+        verify(&hash, None).unwrap_err();
 
-        verify_init_data(&InitDataHash::Value(&init_data_hash), &pcrs).unwrap();
+        let mut wrong = hash;
+        wrong[0] ^= 1;
+        let err = verify(&wrong, Some(&pcrs_sha384)).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::InitDataMismatch(INITDATA_PCR))
+        ));
+
+        let mut claim = json!({});
+        extend_claim(&mut claim, &quote, INITDATA_PCR).unwrap();
+        extend_claim_with_sha384(&mut claim, &quote, Some(&pcrs_sha384)).unwrap();
+        let sha256_claim = claim.clone();
+        extend_claim_with_init_data_bank(&mut claim, &InitDataHash::Value(&hash), INITDATA_PCR)
+            .unwrap();
+        assert_eq!(claim["init_data_bank"], "sha384");
+        assert_eq!(claim["init_data"], claim["tpm_sha384"]["pcr08"]);
+
+        let mut claim = sha256_claim;
+        extend_claim_with_init_data_bank(&mut claim, &InitDataHash::NotProvided, INITDATA_PCR)
+            .unwrap();
+        assert_eq!(claim["init_data_bank"], "sha256");
+        assert_eq!(claim["init_data"], claim["tpm"]["pcr08"]);
     }
 
     #[test]
     fn test_verify_init_data_failure() {
         let quote = QUOTE.clone();
         let quote: Quote = bincode::deserialize(&quote).unwrap();
-        let pcrs: Vec<&[u8; 32]> = quote.pcrs_sha256().collect();
-        let mut init_data = pcrs[INITDATA_PCR].clone();
+        let pcrs: BTreeMap<usize, &[u8]> = quote
+            .pcrs_sha256()
+            .map(|pcr| pcr.as_slice())
+            .enumerate()
+            .collect();
+        let mut init_data = pcrs[&INITDATA_PCR].to_vec();
         init_data[0] = init_data[0] ^ 1;
         let init_data_hash = InitDataHash::Value(&init_data);
 
-        verify_init_data(&init_data_hash, &pcrs).unwrap_err();
+        verify_init_data(
+            &init_data_hash,
+            &pcrs,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap_err();
     }
 
     #[test]
     fn test_extend_claim() {
         let mut claim = json!({"some": "thing"});
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
-        extend_claim(&mut claim, &quote).unwrap();
+        extend_claim(&mut claim, &quote, INITDATA_PCR).unwrap();
 
         let map = claim.as_object().unwrap();
         assert_eq!(map.len(), 4);
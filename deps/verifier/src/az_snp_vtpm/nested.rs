@@ -0,0 +1,273 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Layered attestation of an L2 guest running under an L1 confidential VM.
+//!
+//! The L1 is attested like any Azure SNP vTPM guest. The L2 provides its
+//! own vTPM quote, signed by an L2 AK. The L2 AK is bound to the L1 by an
+//! L1 quote whose nonce is the sha256 of the L2 AKpub and whose PCRs are the
+//! ones of the attested L1 state.
+
+use super::{
+    ak_pub_der, claim_map, extend_claim, sha256_pcrs_by_index, verify_init_data, verify_nonce,
+    verify_pcrs, verify_quote_signature, vtpm_error, AzSnpVtpm, Evidence, InitDataExtend,
+};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{bail, Context, Result};
+use az_snp_vtpm::hcl::HclReport;
+use az_snp_vtpm::vtpm::Quote;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Serialize, Deserialize)]
+struct L2Evidence {
+    quote: Quote,
+    /// DER encoded L2 AKpub
+    ak_pub: Vec<u8>,
+    /// L1 quote binding the L2 AKpub to the L1 state
+    ak_binding: Quote,
+}
+
+#[derive(Serialize, Deserialize)]
+struct NestedEvidence {
+    l1: Evidence,
+    l2: L2Evidence,
+}
+
+impl AzSnpVtpm {
+    /// Verify the evidence of an L2 guest and of the L1 CVM hosting it. The
+    /// L1 quote must carry `expected_l1_report_data`, the L2 quote
+    /// `expected_report_data` and the L2 init_data. The claim holds the
+    /// claims of both layers under `l1` and `l2`.
+    pub fn evaluate_nested(
+        &self,
+        evidence: &[u8],
+        expected_l1_report_data: &ReportData,
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let Some(expected_l1_report_data) = expected_l1_report_data.value()? else {
+            bail!("unexpected empty L1 report data");
+        };
+        let Some(expected_report_data) = expected_report_data.value()? else {
+            bail!("unexpected empty report data");
+        };
+
+        let evidence = serde_json::from_slice::<NestedEvidence>(evidence)
+            .context("Failed to deserialize nested Azure vTPM SEV-SNP evidence")?;
+
+        let l1_report = HclReport::new(self.unwrap_report(evidence.l1.report.clone())?)?;
+        let l1_ak_pub = ak_pub_der(&l1_report)?;
+        let l1_pcrs: Vec<[u8; 32]> = evidence.l1.quote.pcrs_sha256().copied().collect();
+
+        let l1_claim = self.verify_parsed_evidence(
+            evidence.l1,
+            &ReportData::Value(expected_l1_report_data),
+            &InitDataHash::NotProvided,
+            None,
+            None,
+        )?;
+        let l2_claim = verify_l2(
+            &evidence.l2,
+            &l1_ak_pub,
+            &l1_pcrs,
+            expected_report_data,
+            expected_init_data_hash,
+            self.init_data_pcr,
+            &self.init_data_extend,
+        )?;
+
+        Ok(json!({ "l1": l1_claim, "l2": l2_claim }))
+    }
+}
+
+/// Verify that the L2 AK is bound to the L1 with AK `l1_ak_pub` and PCRs
+/// `l1_pcrs`, and the L2 quote against the L2 AK.
+fn verify_l2(
+    l2: &L2Evidence,
+    l1_ak_pub: &[u8],
+    l1_pcrs: &[[u8; 32]],
+    expected_report_data: &[u8],
+    expected_init_data_hash: &InitDataHash,
+    init_data_pcr: usize,
+    init_data_extend: &InitDataExtend,
+) -> Result<TeeEvidenceParsedClaim> {
+    let binding = &l2.ak_binding;
+    verify_quote_signature(binding, l1_ak_pub).context("L2 AK binding is not signed by L1 AK")?;
+    let ak_pub_hash = openssl::sha::sha256(&l2.ak_pub);
+    verify_nonce(binding, &ak_pub_hash)
+        .map_err(vtpm_error)
+        .context("L2 AK binding is for another AK")?;
+    verify_pcrs(binding, None).map_err(vtpm_error)?;
+    if !binding.pcrs_sha256().eq(l1_pcrs.iter()) {
+        bail!("L2 AK binding PCRs don't match the attested L1 PCRs");
+    }
+
+    let quote = &l2.quote;
+    verify_quote_signature(quote, &l2.ak_pub)?;
+    verify_nonce(quote, expected_report_data).map_err(vtpm_error)?;
+    verify_pcrs(quote, None).map_err(vtpm_error)?;
+    let pcrs = sha256_pcrs_by_index(quote)
+        .map_err(vtpm_error)?
+        .into_iter()
+        .map(|(index, pcr)| (index, pcr.as_slice()))
+        .collect();
+    verify_init_data(
+        expected_init_data_hash,
+        &pcrs,
+        init_data_pcr,
+        init_data_extend,
+    )
+    .map_err(vtpm_error)?;
+
+    let mut claim = json!({});
+    extend_claim(&mut claim, quote, init_data_pcr)?;
+    claim_map(&mut claim)?.insert(
+        "ak_pub_sha256".into(),
+        Value::String(hex::encode(ak_pub_hash)),
+    );
+    Ok(claim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, QUOTE, REPORT, REPORT_DATA};
+    use super::super::{CertError, INITDATA_PCR};
+    use super::*;
+    use openssl::pkey::PKey;
+
+    // The L1 AK of the hcl-report.bin fixture is held by a real vTPM, the
+    // L2 AK binding fixture is signed by a test L1 AK instead.
+    const TEST_L1_AK: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/nested/l1-ak.pem");
+    const AK_BINDING: &[u8] =
+        include_bytes!("../../test_data/az-snp-vtpm/nested/l1-ak-binding.bin");
+    const L2_QUOTE: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/nested/l2-quote.bin");
+    const L2_AK: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/nested/l2-ak.der");
+    const L2_REPORT_DATA: &[u8] = b"l2-challenge";
+
+    fn l2_fixture() -> L2Evidence {
+        L2Evidence {
+            quote: bincode::deserialize(L2_QUOTE).unwrap(),
+            ak_pub: L2_AK.to_vec(),
+            ak_binding: bincode::deserialize(AK_BINDING).unwrap(),
+        }
+    }
+
+    fn l1_fixture() -> (Vec<u8>, Vec<[u8; 32]>) {
+        let ak_pub = PKey::public_key_from_pem(TEST_L1_AK).unwrap();
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        (
+            ak_pub.public_key_to_der().unwrap(),
+            quote.pcrs_sha256().copied().collect(),
+        )
+    }
+
+    #[test]
+    fn test_verify_l2() {
+        let (l1_ak_pub, l1_pcrs) = l1_fixture();
+        let claim = verify_l2(
+            &l2_fixture(),
+            &l1_ak_pub,
+            &l1_pcrs,
+            L2_REPORT_DATA,
+            &InitDataHash::NotProvided,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap();
+        assert_eq!(claim["report_data"], hex::encode(L2_REPORT_DATA));
+        assert_eq!(claim["tpm"].as_object().unwrap().len(), 24);
+        assert_eq!(
+            claim["ak_pub_sha256"],
+            hex::encode(openssl::sha::sha256(L2_AK))
+        );
+
+        let err = verify_l2(
+            &l2_fixture(),
+            &l1_ak_pub,
+            &l1_pcrs,
+            REPORT_DATA,
+            &InitDataHash::NotProvided,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::NonceLength {
+                expected: 9,
+                actual: 12
+            })
+        ));
+    }
+
+    #[test]
+    fn test_verify_l2_binding_failure() {
+        let (l1_ak_pub, mut l1_pcrs) = l1_fixture();
+
+        // L2 AK not bound by the L1 AK
+        let mut l2 = l2_fixture();
+        l2.ak_pub = PKey::public_key_from_pem(TEST_L1_AK)
+            .unwrap()
+            .public_key_to_der()
+            .unwrap();
+        verify_l2(
+            &l2,
+            &l1_ak_pub,
+            &l1_pcrs,
+            L2_REPORT_DATA,
+            &InitDataHash::NotProvided,
+            INITDATA_PCR,
+            &InitDataExtend::Zeroed,
+        )
+        .unwrap_err();
+
+        // L2 AK bound to another L1 state
+        l1_pcrs[0][0] ^= 1;
+        assert_eq!(
+            verify_l2(
+                &l2_fixture(),
+                &l1_ak_pub,
+                &l1_pcrs,
+                L2_REPORT_DATA,
+                &InitDataHash::NotProvided,
+                INITDATA_PCR,
+                &InitDataExtend::Zeroed,
+            )
+            .unwrap_err()
+            .to_string(),
+            "L2 AK binding PCRs don't match the attested L1 PCRs"
+        );
+    }
+
+    #[test]
+    fn test_evaluate_nested() {
+        let evidence = NestedEvidence {
+            l1: serde_json::from_slice(&evidence_fixture(REPORT)).unwrap(),
+            l2: l2_fixture(),
+        };
+        let verifier = fixture_verifier();
+
+        // the L1 layer verifies, but the binding isn't signed by its AK
+        let err = verifier
+            .evaluate_nested(
+                &serde_json::to_vec(&evidence).unwrap(),
+                &ReportData::Value(REPORT_DATA),
+                &ReportData::Value(L2_REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+        assert_eq!(err.to_string(), "L2 AK binding is not signed by L1 AK");
+
+        verifier
+            .evaluate_nested(
+                &serde_json::to_vec(&evidence).unwrap(),
+                &ReportData::Value(b"stale"),
+                &ReportData::Value(L2_REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,379 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use super::{AzSnpVtpm, GuestPolicyConstraints, ReportDataBinding, VcekTrust, SECURE_BOOT_PCR};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// A claim field read by the checks of a configured verifier
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RequiredClaim {
+    ReportData,
+    Measurement,
+    ReportedTcb,
+    PolicyAbi,
+    PolicyDebug,
+    PolicyMigrateMa,
+    HostData,
+    Pcr(usize),
+}
+
+impl fmt::Display for RequiredClaim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReportData => write!(f, "report_data"),
+            Self::Measurement => write!(f, "measurement"),
+            Self::ReportedTcb => write!(f, "reported_tcb"),
+            Self::PolicyAbi => write!(f, "policy_abi"),
+            Self::PolicyDebug => write!(f, "policy_debug_allowed"),
+            Self::PolicyMigrateMa => write!(f, "policy_migrate_ma"),
+            Self::HostData => write!(f, "host_data"),
+            Self::Pcr(index) => write!(f, "pcr{index:02}"),
+        }
+    }
+}
+
+/// A check of the verification pipeline
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CheckKind {
+    /// The expected report_data must be provided
+    ReportDataRequired,
+    /// The evidence has no unknown fields
+    StrictEvidenceFields,
+    /// The vTPM quote is signed by the AK of the HCL report
+    QuoteSignature,
+    /// The vTPM quote nonce is the expected report_data
+    QuoteNonce,
+    /// The PCR digest of the quote matches the PCR values
+    PcrDigest,
+    /// The init_data hash, if provided, is bound to the init_data PCR
+    InitData,
+    /// The policy bundle hasn't expired
+    PolicyBundleExpiry,
+    /// An external var_data hash equals the one of the HCL report
+    StrictVarDataHash,
+    /// The SNP report_data binds the HCL var_data and its AK
+    VarDataBinding,
+    /// The SNP report is signed by a VCEK chaining to the AMD root
+    VcekChain,
+    /// The cert chain is confirmed by a quorum of sources
+    CertChainQuorum,
+    /// The SNP report is signed by the pinned VCEK
+    PinnedVcek,
+    /// The VCEK has been issued for the TCB of the SNP report
+    VcekTcb,
+    /// The SNP report has been issued in the expected VMPL
+    Vmpl,
+    /// The VCEK is valid at the verification time
+    VcekValidity,
+    /// The VCEK isn't revoked
+    VcekRevocation,
+    /// The launch measurement isn't all zero
+    MeasuredLaunch,
+    /// The reported_tcb is at least the minimum TCB
+    MinTcb,
+    /// The committed TCB is at most the current TCB
+    TcbInvariant,
+    /// The microcode SVN is allowed
+    Microcode,
+    /// The firmware ABI version is at least the minimum one
+    MinAbi,
+    /// The guest policy satisfies the constraints
+    GuestPolicy,
+    /// The AK of a chip is pinned
+    AkPinning,
+    /// PCRs have their expected values
+    PcrPolicy,
+    /// PCR7 attests Secure Boot
+    SecureBoot,
+    /// Measurements match their reference values
+    ReferenceValues,
+}
+
+impl fmt::Display for CheckKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::ReportDataRequired => "report_data_required",
+            Self::StrictEvidenceFields => "strict_evidence_fields",
+            Self::QuoteSignature => "quote_signature",
+            Self::QuoteNonce => "quote_nonce",
+            Self::PcrDigest => "pcr_digest",
+            Self::InitData => "init_data",
+            Self::PolicyBundleExpiry => "policy_bundle_expiry",
+            Self::StrictVarDataHash => "strict_var_data_hash",
+            Self::VarDataBinding => "var_data_binding",
+            Self::VcekChain => "vcek_chain",
+            Self::CertChainQuorum => "cert_chain_quorum",
+            Self::PinnedVcek => "pinned_vcek",
+            Self::VcekTcb => "vcek_tcb",
+            Self::Vmpl => "vmpl",
+            Self::VcekValidity => "vcek_validity",
+            Self::VcekRevocation => "vcek_revocation",
+            Self::MeasuredLaunch => "measured_launch",
+            Self::MinTcb => "min_tcb",
+            Self::TcbInvariant => "tcb_invariant",
+            Self::Microcode => "microcode",
+            Self::MinAbi => "min_abi",
+            Self::GuestPolicy => "guest_policy",
+            Self::AkPinning => "ak_pinning",
+            Self::PcrPolicy => "pcr_policy",
+            Self::SecureBoot => "secure_boot",
+            Self::ReferenceValues => "reference_values",
+        };
+        f.write_str(name)
+    }
+}
+
+impl AzSnpVtpm {
+    /// The claim fields the checks of this verifier read, e.g. to tell
+    /// whether a claim can possibly satisfy the configured policy.
+    ///
+    /// The init_data PCR is listed since it is read whenever an init_data
+    /// hash is expected.
+    pub fn required_claims(&self) -> BTreeSet<RequiredClaim> {
+        let mut claims = BTreeSet::from([
+            RequiredClaim::ReportData,
+            RequiredClaim::Pcr(self.init_data_pcr),
+        ]);
+        if self.min_tcb.is_some() || self.microcode_policy.is_some() {
+            claims.insert(RequiredClaim::ReportedTcb);
+        }
+        if self.min_abi.is_some() || self.guest_policy.min_abi.is_some() {
+            claims.insert(RequiredClaim::PolicyAbi);
+        }
+        if self.guest_policy.no_debug {
+            claims.insert(RequiredClaim::PolicyDebug);
+        }
+        if self.guest_policy.no_migration {
+            claims.insert(RequiredClaim::PolicyMigrateMa);
+        }
+        if !self.allow_unmeasured_launch {
+            claims.insert(RequiredClaim::Measurement);
+        }
+        claims.extend(self.expected_pcrs.keys().copied().map(RequiredClaim::Pcr));
+        if self.secure_boot_pcr7.is_some() {
+            claims.insert(RequiredClaim::Pcr(SECURE_BOOT_PCR));
+        }
+        claims.extend(self.reference_values.measurements());
+        claims
+    }
+
+    /// The checks this verifier enforces with its configuration, in the
+    /// order of the pipeline, e.g. for a KBS to advertise its assurance
+    /// level. The checks which depend on the evidence or on the request,
+    /// like the init_data binding, are listed if they may apply.
+    pub fn enforced_checks(&self) -> Vec<CheckKind> {
+        let quote_nonce = self.report_data_binding == ReportDataBinding::QuoteNonce;
+        let has_reference_values =
+            self.resolver.is_some() || self.reference_values.measurements().next().is_some();
+        let trust = match (&self.vcek_trust, &self.chain_quorum) {
+            (VcekTrust::PinnedVcek(_), _) => CheckKind::PinnedVcek,
+            (VcekTrust::Chain, Some(_)) => CheckKind::CertChainQuorum,
+            (VcekTrust::Chain, None) => CheckKind::VcekChain,
+        };
+        [
+            (CheckKind::ReportDataRequired, !self.allow_empty_report_data),
+            (CheckKind::StrictEvidenceFields, self.strict_evidence_fields),
+            (CheckKind::QuoteSignature, true),
+            (CheckKind::QuoteNonce, quote_nonce),
+            (CheckKind::PcrDigest, true),
+            (CheckKind::InitData, true),
+            (CheckKind::PolicyBundleExpiry, self.policy_bundle.is_some()),
+            (CheckKind::StrictVarDataHash, self.strict_var_data_hash),
+            (CheckKind::VarDataBinding, quote_nonce),
+            (trust, true),
+            (CheckKind::VcekTcb, true),
+            (CheckKind::Vmpl, true),
+            (CheckKind::VcekValidity, true),
+            (CheckKind::VcekRevocation, self.vcek_crl.is_some()),
+            (CheckKind::MeasuredLaunch, !self.allow_unmeasured_launch),
+            (CheckKind::MinTcb, self.min_tcb.is_some()),
+            (CheckKind::TcbInvariant, self.tcb_invariant),
+            (CheckKind::Microcode, self.microcode_policy.is_some()),
+            (CheckKind::MinAbi, self.min_abi.is_some()),
+            (
+                CheckKind::GuestPolicy,
+                self.guest_policy != GuestPolicyConstraints::default(),
+            ),
+            (CheckKind::AkPinning, self.ak_pins.is_some()),
+            (CheckKind::PcrPolicy, !self.expected_pcrs.is_empty()),
+            (CheckKind::SecureBoot, self.secure_boot_pcr7.is_some()),
+            (CheckKind::ReferenceValues, has_reference_values),
+        ]
+        .into_iter()
+        .filter_map(|(check, enforced)| enforced.then_some(check))
+        .collect()
+    }
+}
+
+/// The configuration for logging: the vendor certificates are shown by
+/// their fingerprint, the other keys and certificates are omitted.
+impl fmt::Debug for AzSnpVtpm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let vendor_certs: BTreeMap<String, String> = self
+            .vendor_certs
+            .iter()
+            .map(|(generation, certs)| {
+                let fingerprint = certs
+                    .fingerprint()
+                    .map_or_else(|_| "<invalid>".to_string(), hex::encode);
+                (generation.to_string(), fingerprint)
+            })
+            .collect();
+        let checks: Vec<String> = self
+            .enforced_checks()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        f.debug_struct("AzSnpVtpm")
+            .field("vendor_certs", &vendor_certs)
+            .field("enforced_checks", &checks)
+            .field("vmpl", &self.vmpl)
+            .field("init_data_pcr", &self.init_data_pcr)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::fixture_verifier;
+    use super::super::{
+        AbiVersion, AkChangePolicy, CrlSource, MicrocodePolicy, ReferenceValues, SnpGeneration, Tcb,
+    };
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_required_claims() {
+        let verifier = fixture_verifier().with_unmeasured_launch_allowed(true);
+        assert_eq!(
+            verifier.required_claims(),
+            BTreeSet::from([RequiredClaim::ReportData, RequiredClaim::Pcr(8)])
+        );
+
+        let verifier = verifier
+            .with_unmeasured_launch_allowed(false)
+            .with_microcode_policy(MicrocodePolicy::Minimum(115));
+        let claims: Vec<String> = verifier
+            .required_claims()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            claims,
+            ["report_data", "measurement", "reported_tcb", "pcr08"]
+        );
+
+        let verifier = verifier.with_expected_pcrs(HashMap::from([(0, [0; 32]), (4, [0; 32])]));
+        let claims: Vec<String> = verifier
+            .required_claims()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            claims,
+            [
+                "report_data",
+                "measurement",
+                "reported_tcb",
+                "pcr00",
+                "pcr04",
+                "pcr08"
+            ]
+        );
+
+        let verifier = verifier.with_secure_boot_required(vec![[0; 32]]);
+        assert!(verifier
+            .required_claims()
+            .contains(&RequiredClaim::Pcr(SECURE_BOOT_PCR)));
+    }
+
+    #[test]
+    fn test_enforced_checks() {
+        let verifier = fixture_verifier();
+        let checks: Vec<String> = verifier
+            .enforced_checks()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                "report_data_required",
+                "quote_signature",
+                "quote_nonce",
+                "pcr_digest",
+                "init_data",
+                "var_data_binding",
+                "vcek_chain",
+                "vcek_tcb",
+                "vmpl",
+                "vcek_validity",
+                "measured_launch",
+            ]
+        );
+
+        let verifier = verifier
+            .with_strict_evidence_fields(true)
+            .with_strict_var_data_hash(true)
+            .with_vcek_crl(CrlSource::Url("https://example.com/vcek.crl".into()), false)
+            .with_min_tcb(Tcb {
+                bootloader: 3,
+                tee: 0,
+                snp: 8,
+                microcode: 115,
+            })
+            .with_tcb_invariant_check(true)
+            .with_microcode_policy(MicrocodePolicy::Minimum(115))
+            .with_min_abi(AbiVersion {
+                major: 1,
+                minor: 51,
+            })
+            .with_guest_policy(GuestPolicyConstraints {
+                no_debug: true,
+                ..Default::default()
+            })
+            .with_ak_pinning(AkChangePolicy::Reject)
+            .with_expected_pcrs(HashMap::from([(0, [0; 32])]))
+            .with_reference_values(
+                ReferenceValues::new().allow(RequiredClaim::Measurement, vec![0; 48]),
+            );
+        let checks = verifier.enforced_checks();
+        assert_eq!(checks.len(), 22);
+        assert!(checks.contains(&CheckKind::VcekRevocation));
+        assert!(checks.contains(&CheckKind::ReferenceValues));
+        assert!(!checks.contains(&CheckKind::PolicyBundleExpiry));
+
+        let checks = verifier
+            .with_report_data_binding(ReportDataBinding::KeySha512)
+            .with_empty_report_data_allowed(true)
+            .with_unmeasured_launch_allowed(true)
+            .with_vcek_trust(VcekTrust::PinnedVcek(vec![]))
+            .enforced_checks();
+        assert!(checks.contains(&CheckKind::PinnedVcek));
+        for check in [
+            CheckKind::ReportDataRequired,
+            CheckKind::QuoteNonce,
+            CheckKind::VarDataBinding,
+            CheckKind::VcekChain,
+            CheckKind::MeasuredLaunch,
+        ] {
+            assert!(!checks.contains(&check), "{check} is enforced");
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let verifier = fixture_verifier();
+        let milan = verifier.vendor_certs[&SnpGeneration::Milan]
+            .fingerprint()
+            .unwrap();
+        let debug = format!("{verifier:?}");
+        assert!(debug.contains(&format!("\"Milan\": \"{}\"", hex::encode(milan))));
+        assert!(debug.contains("\"vcek_chain\""));
+        assert!(!debug.contains("BEGIN CERTIFICATE"));
+        assert!(!debug.contains("MII"));
+    }
+}
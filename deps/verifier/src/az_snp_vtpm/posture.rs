@@ -0,0 +1,125 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use super::Tcb;
+use crate::TeeEvidenceParsedClaim;
+use serde::Serialize;
+
+/// Weight of a guest policy forbidding debugging
+const DEBUG_DISABLED_WEIGHT: u32 = 8192;
+/// Weight of an init_data bound into its PCR
+const INIT_DATA_BOUND_WEIGHT: u32 = 4096;
+/// Weight of a guest policy forbidding a migration agent
+const MIGRATION_DISABLED_WEIGHT: u32 = 2048;
+/// Weight of an enabled TSME
+const TSME_ENABLED_WEIGHT: u32 = 1024;
+
+/// Security posture of a verified claim, ordered from least to most secure.
+///
+/// `total` is the sum of the weights of the satisfied properties: debug
+/// disabled (8192), init_data bound (4096), migration agent disabled (2048)
+/// and TSME enabled (1024), plus the sum of the reported TCB components as
+/// a measure of TCB freshness. Each property outweighs all the lighter ones
+/// together, including the TCB, which only breaks ties between otherwise
+/// equal hosts. Properties whose claims are missing count as unsatisfied.
+/// The claim carries no information on the signing of the guest image, so
+/// it is not scored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct PostureScore {
+    pub total: u32,
+    pub debug_disabled: bool,
+    pub init_data_bound: bool,
+    pub migration_disabled: bool,
+    pub tsme_enabled: bool,
+    pub tcb_freshness: u32,
+}
+
+/// A single bit field of the SNP report, claimed as "0" or "1"
+fn claim_flag(claim: &TeeEvidenceParsedClaim, name: &str) -> Option<bool> {
+    match claim[name].as_str()? {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+/// Score the security posture of a claim of `AzSnpVtpm`
+pub fn posture_score(claim: &TeeEvidenceParsedClaim) -> PostureScore {
+    let debug_disabled = claim_flag(claim, "policy_debug_allowed") == Some(false);
+    let migration_disabled = claim_flag(claim, "policy_migrate_ma") == Some(false);
+    let tsme_enabled = claim_flag(claim, "platform_tsme_enabled") == Some(true);
+    let init_data_bound = claim["init_data"]
+        .as_str()
+        .and_then(|pcr| hex::decode(pcr).ok())
+        .is_some_and(|pcr| pcr.iter().any(|byte| *byte != 0));
+    let tcb_freshness = serde_json::from_value::<Tcb>(claim["reported_tcb"].clone())
+        .map(|tcb| {
+            [tcb.bootloader, tcb.tee, tcb.snp, tcb.microcode]
+                .iter()
+                .map(|component| u32::from(*component))
+                .sum()
+        })
+        .unwrap_or(0);
+
+    let weights = [
+        (debug_disabled, DEBUG_DISABLED_WEIGHT),
+        (init_data_bound, INIT_DATA_BOUND_WEIGHT),
+        (migration_disabled, MIGRATION_DISABLED_WEIGHT),
+        (tsme_enabled, TSME_ENABLED_WEIGHT),
+    ];
+    let total = weights
+        .iter()
+        .filter(|(satisfied, _)| *satisfied)
+        .map(|(_, weight)| weight)
+        .sum::<u32>()
+        + tcb_freshness;
+
+    PostureScore {
+        total,
+        debug_disabled,
+        init_data_bound,
+        migration_disabled,
+        tsme_enabled,
+        tcb_freshness,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use serde_json::json;
+
+    #[test]
+    fn test_posture_score() {
+        let production = fixture_verifier()
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+        let score = posture_score(&production);
+        assert!(score.debug_disabled);
+        assert!(score.migration_disabled);
+        assert!(!score.tsme_enabled);
+        // reported_tcb of the fixture is 3.0.8.115
+        assert_eq!(score.tcb_freshness, 126);
+
+        let mut debug = production.clone();
+        debug["policy_debug_allowed"] = json!("1");
+        assert!(posture_score(&debug) < score);
+
+        // a debug host doesn't catch up with a fresher TCB
+        debug["reported_tcb"]["microcode"] = json!(255);
+        assert!(posture_score(&debug) < score);
+
+        let mut hosts = [posture_score(&debug), score];
+        hosts.sort();
+        assert_eq!(hosts[0], posture_score(&debug));
+    }
+}
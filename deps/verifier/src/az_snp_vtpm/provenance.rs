@@ -0,0 +1,305 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Structured provenance trace of a verification, for high-assurance audits.
+//!
+//! The trace records every input of the verdict (evidence, vendor
+//! certificates, policy and expectations), the intermediate values derived
+//! from them and the verdict itself. It is serialized as JSON with sorted
+//! keys, so its digest is stable and can be signed and archived for an
+//! independent replay.
+
+use super::{ak_pub_der, parse_vcek, report_data_json, AzSnpVtpm, Evidence, Tcb};
+use crate::az_vtpm::tpm::parse_quote_info;
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{anyhow, Result};
+use az_snp_vtpm::hcl::HclReport;
+use az_snp_vtpm::report::AttestationReport;
+use openssl::x509::X509;
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+/// Collects the values a verdict has been derived from
+#[derive(Default)]
+pub struct ProvenanceRecorder {
+    inputs: Map<String, Value>,
+    intermediates: Map<String, Value>,
+}
+
+/// A complete provenance trace
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProvenanceRecord {
+    pub inputs: Map<String, Value>,
+    pub intermediates: Map<String, Value>,
+    pub verdict: Value,
+}
+
+impl ProvenanceRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_input(&mut self, name: &str, value: Value) {
+        self.inputs.insert(name.into(), value);
+    }
+
+    pub fn record_intermediate(&mut self, name: &str, value: Value) {
+        self.intermediates.insert(name.into(), value);
+    }
+
+    /// Record an intermediate value, or the reason it couldn't be derived
+    fn record_derived(&mut self, name: &str, value: Result<Value>) {
+        let value = value.unwrap_or_else(|e| json!({ "error": format!("{e:#}") }));
+        self.record_intermediate(name, value);
+    }
+
+    pub fn finish(self, verdict: &Result<TeeEvidenceParsedClaim>) -> ProvenanceRecord {
+        let verdict = match verdict {
+            Ok(claim) => json!({ "accepted": true, "claim": claim }),
+            Err(e) => json!({ "accepted": false, "error": format!("{e:#}") }),
+        };
+        ProvenanceRecord {
+            inputs: self.inputs,
+            intermediates: self.intermediates,
+            verdict,
+        }
+    }
+}
+
+impl ProvenanceRecord {
+    /// The canonical encoding of the record, to be signed
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    /// sha256 of the canonical encoding of the record
+    pub fn digest(&self) -> Result<[u8; 32]> {
+        Ok(openssl::sha::sha256(&self.to_bytes()?))
+    }
+}
+
+fn optional_hex(value: Option<&[u8]>) -> Value {
+    value.map_or(Value::Null, |value| Value::String(hex::encode(value)))
+}
+
+fn cert_pem(cert: &X509) -> Result<Value> {
+    Ok(Value::String(String::from_utf8(cert.to_pem()?)?))
+}
+
+impl AzSnpVtpm {
+    /// Verify `evidence` like `evaluate`, recording every decision input and
+    /// intermediate value into `recorder`. The verdict is returned and also
+    /// recorded.
+    pub fn evaluate_with_provenance(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        mut recorder: ProvenanceRecorder,
+    ) -> (Result<TeeEvidenceParsedClaim>, ProvenanceRecord) {
+        self.record_provenance(
+            &mut recorder,
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+        );
+        let verdict = self.verify_evidence(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            None,
+        );
+        let record = recorder.finish(&verdict);
+        (verdict, record)
+    }
+
+    fn record_provenance(
+        &self,
+        recorder: &mut ProvenanceRecorder,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) {
+        recorder.record_input("evidence", Value::String(hex::encode(evidence)));
+        recorder.record_input(
+            "expected_report_data",
+            report_data_json(expected_report_data),
+        );
+        recorder.record_input(
+            "expected_init_data_hash",
+            optional_hex(match expected_init_data_hash {
+                InitDataHash::Value(value) => Some(value),
+                InitDataHash::NotProvided => None,
+            }),
+        );
+        recorder.record_input("policy", self.policy());
+        recorder.record_input(
+            "policy_hash",
+            Value::String(hex::encode(self.policy_hash())),
+        );
+        let vendor_certs = self
+            .vendor_certs
+            .iter()
+            .map(|(generation, certs)| {
+                Ok((
+                    generation.to_string(),
+                    json!({
+                        "ark": cert_pem(&certs.ark)?,
+                        "ask": cert_pem(&certs.ask)?,
+                        "asvk": cert_pem(&certs.asvk)?,
+                    }),
+                ))
+            })
+            .collect::<Result<serde_json::Map<_, _>>>()
+            .map(Value::Object)
+            .unwrap_or_else(|e| json!({ "error": format!("{e:#}") }));
+        recorder.record_input("vendor_certs", vendor_certs);
+
+        let evidence = match Evidence::from_slice(evidence) {
+            Ok(evidence) => evidence,
+            Err(e) => {
+                recorder.record_derived("evidence", Err(e));
+                return;
+            }
+        };
+        recorder.record_intermediate("vcek", Value::String(evidence.vcek.clone()));
+        recorder.record_derived(
+            "vcek_tcb",
+            parse_vcek(&evidence.vcek)
+                .and_then(|vcek| Ok(vcek.0.to_der()?))
+                .and_then(|der| Tcb::from_vcek_der(&der))
+                .and_then(|tcb| Ok(serde_json::to_value(tcb)?)),
+        );
+
+        let quote = &evidence.quote;
+        recorder.record_derived(
+            "quote_nonce",
+            quote
+                .nonce()
+                .map(hex::encode)
+                .map(Value::String)
+                .map_err(Into::into),
+        );
+        recorder.record_intermediate(
+            "pcrs",
+            Value::Array(
+                quote
+                    .pcrs_sha256()
+                    .map(|pcr| Value::String(hex::encode(pcr)))
+                    .collect(),
+            ),
+        );
+        recorder.record_derived(
+            "pcr_digest",
+            parse_quote_info(&quote.message())
+                .map(|info| Value::String(hex::encode(info.pcr_digest))),
+        );
+
+        let hcl_report = match self
+            .unwrap_report(evidence.report)
+            .and_then(|report| Ok(HclReport::new(report)?))
+        {
+            Ok(hcl_report) => hcl_report,
+            Err(e) => {
+                recorder.record_derived("hcl_report", Err(e));
+                return;
+            }
+        };
+        recorder.record_derived(
+            "ak_pub_sha256",
+            ak_pub_der(&hcl_report)
+                .map(|der| Value::String(hex::encode(openssl::sha::sha256(&der)))),
+        );
+        recorder.record_intermediate(
+            "var_data_sha256",
+            Value::String(hex::encode(hcl_report.var_data_sha256())),
+        );
+        recorder.record_derived(
+            "reported_tcb",
+            TryInto::<AttestationReport>::try_into(hcl_report)
+                .map_err(|e| anyhow!("{e}"))
+                .and_then(|report| Ok(serde_json::to_value(Tcb::from(&report.reported_tcb))?)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+
+    #[test]
+    fn test_provenance_record() {
+        let verifier = fixture_verifier();
+        let evidence = evidence_fixture(REPORT);
+        let (claim, record) = verifier.evaluate_with_provenance(
+            &evidence,
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            ProvenanceRecorder::new(),
+        );
+        let claim = claim.unwrap();
+
+        for input in [
+            "evidence",
+            "expected_report_data",
+            "expected_init_data_hash",
+            "policy",
+            "policy_hash",
+            "vendor_certs",
+        ] {
+            assert!(record.inputs.contains_key(input), "missing input {input}");
+        }
+        assert_eq!(record.inputs["evidence"], hex::encode(&evidence));
+        assert_eq!(
+            record.inputs["expected_report_data"],
+            hex::encode(REPORT_DATA)
+        );
+
+        for intermediate in [
+            "vcek",
+            "vcek_tcb",
+            "quote_nonce",
+            "pcrs",
+            "pcr_digest",
+            "ak_pub_sha256",
+            "var_data_sha256",
+            "reported_tcb",
+        ] {
+            let value = &record.intermediates[intermediate];
+            assert!(value.get("error").is_none(), "{intermediate}: {value}");
+        }
+        assert_eq!(record.intermediates["pcrs"].as_array().unwrap().len(), 24);
+        assert_eq!(
+            record.intermediates["quote_nonce"],
+            hex::encode(REPORT_DATA)
+        );
+
+        assert_eq!(record.verdict["accepted"], true);
+        assert_eq!(record.verdict["claim"], claim);
+
+        // the same verification yields the same record
+        let (_, replayed) = verifier.evaluate_with_provenance(
+            &evidence,
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            ProvenanceRecorder::new(),
+        );
+        assert_eq!(record.digest().unwrap(), replayed.digest().unwrap());
+    }
+
+    #[test]
+    fn test_provenance_record_rejection() {
+        let (claim, record) = fixture_verifier().evaluate_with_provenance(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(b"stale"),
+            &InitDataHash::NotProvided,
+            ProvenanceRecorder::new(),
+        );
+        claim.unwrap_err();
+        assert_eq!(record.verdict["accepted"], false);
+        assert!(record.verdict["error"].is_string());
+    }
+}
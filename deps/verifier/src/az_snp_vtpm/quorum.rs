@@ -0,0 +1,162 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Verification of the VCEK against a quorum of independent sources of the
+//! AMD cert chain, e.g. the embedded chain and a copy backed by a
+//! transparency log, so that a single poisoned cert bundle is detected.
+
+use super::{verify_snp_report, verify_vendor_certs_validity, AzSnpVtpm, CertError};
+use crate::snp::{load_milan_cert_chain, VendorCertificates};
+use anyhow::{anyhow, bail, Context, Result};
+use az_snp_vtpm::certs::Vcek;
+use az_snp_vtpm::report::AttestationReport;
+use log::warn;
+use serde_json::{json, Value};
+use std::time::SystemTime;
+
+/// A named source of the ASK, ARK and ASVK
+#[derive(Clone, Debug)]
+pub struct CertChainSource {
+    name: String,
+    certs: VendorCertificates,
+}
+
+impl CertChainSource {
+    /// Parse a PEM bundle of the ASK, ARK and ASVK, in this order
+    pub fn from_pem(name: &str, pem: &[u8]) -> Result<Self> {
+        let certs = VendorCertificates::from_pem(pem)
+            .with_context(|| format!("Malformed cert chain source {name}"))?;
+        Ok(Self {
+            name: name.to_string(),
+            certs,
+        })
+    }
+
+    /// The Milan chain embedded in the verifier
+    pub fn milan() -> Result<Self> {
+        let certs = load_milan_cert_chain()
+            .as_ref()
+            .map_err(|e| anyhow!("{e:#}"))?;
+        Ok(Self {
+            name: "milan".to_string(),
+            certs: certs.clone(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Sources of which at least `threshold` must validate the VCEK
+pub(crate) struct ChainQuorum {
+    sources: Vec<CertChainSource>,
+    threshold: usize,
+}
+
+impl ChainQuorum {
+    /// Verify the SNP report and VCEK against every source, whose certs must
+    /// be valid at `now`, returning the names of the sources which validate
+    /// them
+    pub(crate) fn verify(
+        &self,
+        snp_report: &AttestationReport,
+        vcek: &Vcek,
+        expected_vmpl: u32,
+        now: SystemTime,
+    ) -> Result<Vec<String>, CertError> {
+        let mut agreed = vec![];
+        for source in &self.sources {
+            let verified = verify_snp_report(snp_report, vcek, &source.certs, expected_vmpl)
+                .and_then(|()| verify_vendor_certs_validity(&source.certs, now));
+            match verified {
+                Ok(()) => agreed.push(source.name.clone()),
+                Err(e) => warn!("Cert chain source {} rejects the VCEK: {e:#}", source.name),
+            }
+        }
+        if agreed.len() < self.threshold {
+            return Err(CertError::ChainQuorumNotMet {
+                agreed,
+                required: self.threshold,
+            });
+        }
+        Ok(agreed)
+    }
+
+    pub(crate) fn to_json(&self) -> Value {
+        json!({
+            "sources": self.sources.iter().map(CertChainSource::name).collect::<Vec<_>>(),
+            "threshold": self.threshold,
+        })
+    }
+}
+
+impl AzSnpVtpm {
+    /// Require at least `threshold` of `sources` to validate the VCEK,
+    /// instead of the embedded Milan chain alone. The names of the agreeing
+    /// sources are added to the claim as `cert_chain_sources`.
+    pub fn with_cert_chain_quorum(
+        mut self,
+        sources: Vec<CertChainSource>,
+        threshold: usize,
+    ) -> Result<Self> {
+        if threshold == 0 || threshold > sources.len() {
+            bail!(
+                "Quorum of {threshold} out of {} cert chain sources is unsatisfiable",
+                sources.len()
+            );
+        }
+        self.chain_quorum = Some(ChainQuorum { sources, threshold });
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+
+    /// A chain of certificates unrelated to AMD
+    fn poisoned() -> CertChainSource {
+        let cert = include_bytes!("../../test_data/az-snp-vtpm/crl/issuer.pem");
+        CertChainSource::from_pem("poisoned", &[&cert[..], cert, cert].concat()).unwrap()
+    }
+
+    fn verify(threshold: usize) -> Result<crate::TeeEvidenceParsedClaim> {
+        let milan = CertChainSource::milan().unwrap();
+        let mirror = CertChainSource {
+            name: "transparency-log".to_string(),
+            ..milan.clone()
+        };
+        fixture_verifier()
+            .with_cert_chain_quorum(vec![milan, poisoned(), mirror], threshold)?
+            .verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+    }
+
+    #[test]
+    fn test_cert_chain_quorum() {
+        let claim = verify(2).unwrap();
+        assert_eq!(
+            claim["cert_chain_sources"],
+            json!(["milan", "transparency-log"])
+        );
+
+        let err = verify(3).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::ChainQuorumNotMet { agreed, required: 3 }) if agreed.len() == 2
+        ));
+
+        verify(0).unwrap_err();
+        verify(4).unwrap_err();
+        CertChainSource::from_pem("empty", b"").unwrap_err();
+    }
+}
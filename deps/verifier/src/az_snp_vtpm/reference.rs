@@ -0,0 +1,377 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Reference values for the launch measurement and the PCRs.
+//!
+//! Reference values are either held in memory (`ReferenceValues`) or
+//! resolved on demand, one measurement at a time, by a
+//! `ReferenceValueResolver`, e.g. backed by a remote store. Resolved values
+//! are cached for the lifetime of the verifier.
+
+use super::{AzSnpVtpm, CertError, RequiredClaim, PCR_COUNT};
+use crate::{ExpectedMeasurements, TeeEvidenceParsedClaim};
+use anyhow::{anyhow, Context, Result};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Resolves the acceptable values of a measurement on demand
+pub trait ReferenceValueResolver: Send + Sync {
+    /// The acceptable values of `measurement`, or `None` if it is not
+    /// constrained.
+    fn resolve(&self, measurement: &RequiredClaim) -> Result<Option<Vec<Vec<u8>>>>;
+}
+
+/// In-memory allowlist of reference values
+#[derive(Clone, Debug, Default)]
+pub struct ReferenceValues {
+    values: HashMap<RequiredClaim, Vec<Vec<u8>>>,
+}
+
+impl ReferenceValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Accept `value` for `measurement`, in addition to the values already
+    /// accepted.
+    pub fn allow(mut self, measurement: RequiredClaim, value: Vec<u8>) -> Self {
+        self.values.entry(measurement).or_default().push(value);
+        self
+    }
+
+    pub(crate) fn measurements(&self) -> impl Iterator<Item = &RequiredClaim> {
+        self.values.keys()
+    }
+
+    /// The reference values, hex encoded and sorted by measurement
+    pub(crate) fn to_json(&self) -> Value {
+        let values: BTreeMap<String, Vec<String>> = self
+            .values
+            .iter()
+            .map(|(measurement, values)| {
+                (
+                    measurement.to_string(),
+                    values.iter().map(hex::encode).collect(),
+                )
+            })
+            .collect();
+        json!(values)
+    }
+}
+
+impl From<&ExpectedMeasurements> for ReferenceValues {
+    fn from(expected: &ExpectedMeasurements) -> Self {
+        let mut values = HashMap::new();
+        let mut allow = |measurement, allowed: &Vec<Vec<u8>>| {
+            if !allowed.is_empty() {
+                values.insert(measurement, allowed.clone());
+            }
+        };
+        allow(RequiredClaim::Measurement, &expected.measurement);
+        allow(RequiredClaim::HostData, &expected.host_data);
+        for (index, allowed) in &expected.pcrs {
+            allow(RequiredClaim::Pcr(*index), allowed);
+        }
+        Self { values }
+    }
+}
+
+impl ReferenceValueResolver for ReferenceValues {
+    fn resolve(&self, measurement: &RequiredClaim) -> Result<Option<Vec<Vec<u8>>>> {
+        Ok(self.values.get(measurement).cloned())
+    }
+}
+
+/// Caches the values resolved by a `ReferenceValueResolver`
+pub(crate) struct CachingResolver {
+    resolver: Box<dyn ReferenceValueResolver>,
+    cache: Mutex<HashMap<RequiredClaim, Option<Vec<Vec<u8>>>>>,
+}
+
+impl CachingResolver {
+    pub(crate) fn new(resolver: Box<dyn ReferenceValueResolver>) -> Self {
+        Self {
+            resolver,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl ReferenceValueResolver for CachingResolver {
+    fn resolve(&self, measurement: &RequiredClaim) -> Result<Option<Vec<Vec<u8>>>> {
+        let mut cache = self
+            .cache
+            .lock()
+            .map_err(|_| anyhow!("Reference value cache is poisoned"))?;
+        if let Some(values) = cache.get(measurement) {
+            return Ok(values.clone());
+        }
+        let values = self.resolver.resolve(measurement)?;
+        cache.insert(*measurement, values.clone());
+        Ok(values)
+    }
+}
+
+impl AzSnpVtpm {
+    /// Require the launch measurement and PCRs to match `reference_values`.
+    /// Ignored if a resolver is set with `with_reference_value_resolver`.
+    pub fn with_reference_values(mut self, reference_values: ReferenceValues) -> Self {
+        self.reference_values = reference_values;
+        self
+    }
+
+    /// Resolve the reference values of the launch measurement and PCRs with
+    /// `resolver`, instead of the in-memory reference values. Resolved values
+    /// are cached.
+    pub fn with_reference_value_resolver(
+        mut self,
+        resolver: impl ReferenceValueResolver + 'static,
+    ) -> Self {
+        self.resolver = Some(CachingResolver::new(Box::new(resolver)));
+        self
+    }
+
+    /// Check the launch measurement and PCRs of `claim` against their
+    /// reference values.
+    pub(crate) fn verify_reference_values(&self, claim: &TeeEvidenceParsedClaim) -> Result<()> {
+        let resolver: &dyn ReferenceValueResolver = match &self.resolver {
+            Some(resolver) => resolver,
+            None => &self.reference_values,
+        };
+        verify_claim(resolver, claim)
+    }
+}
+
+/// Check the launch measurement and PCRs of `claim` with `resolver`. A
+/// constrained PCR must be in the quote.
+fn verify_claim(
+    resolver: &dyn ReferenceValueResolver,
+    claim: &TeeEvidenceParsedClaim,
+) -> Result<()> {
+    let measurement = claim["measurement"]
+        .as_str()
+        .context("Claim has no measurement")?;
+    let measurement = hex::decode(measurement).context("Malformed measurement claim")?;
+    verify_reference_value(resolver, RequiredClaim::Measurement, &measurement)?;
+
+    for index in 0..PCR_COUNT {
+        let pcr = RequiredClaim::Pcr(index);
+        match claim["tpm"][pcr.to_string()].as_str() {
+            Some(value) => verify_reference_value(resolver, pcr, &hex::decode(value)?)?,
+            // a constrained PCR must be in the quote
+            None if resolver.resolve(&pcr)?.is_some() => {
+                return Err(CertError::ReferenceValueMismatch(pcr).into())
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Check `claim` against the reference values passed through the
+/// `Verifier` trait, on top of the configured ones. A constrained PCR must
+/// be in the quote, even if it is beyond the PCRs a quote can have.
+pub(crate) fn verify_expected_measurements(
+    claim: &TeeEvidenceParsedClaim,
+    expected: &ExpectedMeasurements,
+) -> Result<()> {
+    let reference_values = ReferenceValues::from(expected);
+    verify_claim(&reference_values, claim)?;
+    for measurement in reference_values.measurements() {
+        if let RequiredClaim::Pcr(_) = measurement {
+            if claim["tpm"][measurement.to_string()].is_null() {
+                return Err(CertError::ReferenceValueMismatch(*measurement).into());
+            }
+        }
+    }
+    let host_data = claim["host_data"]
+        .as_str()
+        .context("Claim has no host_data")?;
+    verify_reference_value(
+        &reference_values,
+        RequiredClaim::HostData,
+        &hex::decode(host_data).context("Malformed host_data claim")?,
+    )?;
+    Ok(())
+}
+
+fn verify_reference_value(
+    resolver: &dyn ReferenceValueResolver,
+    measurement: RequiredClaim,
+    value: &[u8],
+) -> Result<(), CertError> {
+    let Some(reference_values) = resolver.resolve(&measurement)? else {
+        return Ok(());
+    };
+    if !reference_values.iter().any(|reference| reference == value) {
+        return Err(CertError::ReferenceValueMismatch(measurement));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_verifier, sparse_quote, REPORT, REPORT_DATA,
+    };
+    use super::super::INITDATA_PCR;
+    use super::*;
+    use crate::az_vtpm::vtpm::extend_claim;
+    use crate::{InitDataHash, ReportData, Verifier};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Resolves the fixture's PCR8 (all zero) and counts the resolutions
+    struct PcrResolver {
+        value: Vec<u8>,
+        resolutions: Arc<AtomicUsize>,
+    }
+
+    impl ReferenceValueResolver for PcrResolver {
+        fn resolve(&self, measurement: &RequiredClaim) -> Result<Option<Vec<Vec<u8>>>> {
+            self.resolutions.fetch_add(1, Ordering::SeqCst);
+            match measurement {
+                RequiredClaim::Pcr(8) => Ok(Some(vec![self.value.clone()])),
+                _ => Ok(None),
+            }
+        }
+    }
+
+    fn verify(verifier: &AzSnpVtpm) -> Result<TeeEvidenceParsedClaim> {
+        verifier.verify_evidence(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_reference_value_resolver() {
+        let resolutions = Arc::new(AtomicUsize::new(0));
+        let verifier = fixture_verifier().with_reference_value_resolver(PcrResolver {
+            value: vec![0u8; 32],
+            resolutions: resolutions.clone(),
+        });
+        verify(&verifier).unwrap();
+        let resolved = resolutions.load(Ordering::SeqCst);
+        assert_eq!(resolved, 1 + PCR_COUNT);
+
+        // resolved values are cached
+        verify(&verifier).unwrap();
+        assert_eq!(resolutions.load(Ordering::SeqCst), resolved);
+    }
+
+    #[test]
+    fn test_reference_value_resolver_miss() {
+        let verifier = fixture_verifier().with_reference_value_resolver(PcrResolver {
+            value: vec![1u8; 32],
+            resolutions: Arc::new(AtomicUsize::new(0)),
+        });
+        let err = verify(&verifier).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::ReferenceValueMismatch(RequiredClaim::Pcr(8)))
+        ));
+    }
+
+    #[test]
+    fn test_reference_value_missing_pcr() {
+        let mut claim = json!({ "measurement": hex::encode([0; 48]) });
+        extend_claim(&mut claim, &sparse_quote(&[0, 4, 7, 8]), INITDATA_PCR).unwrap();
+        let reference_values = ReferenceValues::new().allow(RequiredClaim::Pcr(4), vec![4; 32]);
+        verify_claim(&reference_values, &claim).unwrap();
+
+        // PCR5 isn't in the quote
+        let reference_values = reference_values.allow(RequiredClaim::Pcr(5), vec![5; 32]);
+        let err = verify_claim(&reference_values, &claim).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::ReferenceValueMismatch(RequiredClaim::Pcr(5)))
+        ));
+    }
+
+    #[test]
+    fn test_reference_values() {
+        let verifier = fixture_verifier().with_reference_values(
+            ReferenceValues::new()
+                .allow(RequiredClaim::Pcr(8), vec![1u8; 32])
+                .allow(RequiredClaim::Pcr(8), vec![0u8; 32]),
+        );
+        verify(&verifier).unwrap();
+
+        let verifier = fixture_verifier().with_reference_values(
+            ReferenceValues::new().allow(RequiredClaim::Measurement, vec![]),
+        );
+        verify(&verifier).unwrap_err();
+    }
+
+    async fn evaluate(expected: &ExpectedMeasurements) -> Result<TeeEvidenceParsedClaim> {
+        fixture_verifier()
+            .evaluate_with_expected(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                expected,
+            )
+            .await
+    }
+
+    async fn mismatch(expected: ExpectedMeasurements) -> RequiredClaim {
+        let err = evaluate(&expected).await.unwrap_err();
+        match err.downcast_ref::<CertError>() {
+            Some(CertError::ReferenceValueMismatch(measurement)) => *measurement,
+            _ => panic!("unexpected error {err:#}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_expected_measurements() {
+        let claim = evaluate(&ExpectedMeasurements::default()).await.unwrap();
+        let measurement = hex::decode(claim["measurement"].as_str().unwrap()).unwrap();
+        let host_data = hex::decode(claim["host_data"].as_str().unwrap()).unwrap();
+
+        let expected = ExpectedMeasurements {
+            measurement: vec![vec![1; 48], measurement],
+            pcrs: BTreeMap::from([(8, vec![vec![0; 32]])]),
+            host_data: vec![host_data],
+        };
+        evaluate(&expected).await.unwrap();
+
+        assert_eq!(
+            mismatch(ExpectedMeasurements {
+                measurement: vec![vec![1; 48]],
+                ..expected.clone()
+            })
+            .await,
+            RequiredClaim::Measurement
+        );
+        assert_eq!(
+            mismatch(ExpectedMeasurements {
+                pcrs: BTreeMap::from([(8, vec![vec![1; 32]])]),
+                ..expected.clone()
+            })
+            .await,
+            RequiredClaim::Pcr(8)
+        );
+        assert_eq!(
+            mismatch(ExpectedMeasurements {
+                pcrs: BTreeMap::from([(30, vec![vec![0; 32]])]),
+                ..expected.clone()
+            })
+            .await,
+            RequiredClaim::Pcr(30)
+        );
+        assert_eq!(
+            mismatch(ExpectedMeasurements {
+                host_data: vec![vec![1; 32]],
+                ..expected
+            })
+            .await,
+            RequiredClaim::HostData
+        );
+    }
+}
@@ -0,0 +1,87 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Startup self-test of the verification pipeline against a known-good
+//! evidence embedded in the binary.
+
+use super::{AzSnpVtpm, CertError, Evidence, FixedClock};
+use crate::{InitDataHash, ReportData};
+use anyhow::{ensure, Result};
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+const REPORT: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/hcl-report.bin");
+const QUOTE: &[u8] = include_bytes!("../../test_data/az-snp-vtpm/quote.bin");
+const VCEK: &str = include_str!("../../test_data/az-snp-vtpm/vcek.pem");
+const NONCE: &[u8] = b"challenge";
+/// A time within the validity of the embedded VCEK, 2023-11-14, so that
+/// the self-test doesn't depend on the system clock
+const VERIFIED_AT: Duration = Duration::from_secs(1_700_000_000);
+
+impl AzSnpVtpm {
+    /// Same as `new`, also verifying an embedded known-good evidence, so
+    /// that a service with a broken cert chain or verification pipeline
+    /// fails at startup rather than at its first request.
+    pub fn new_validated() -> Result<Self, CertError> {
+        let clock = Arc::new(FixedClock::new(UNIX_EPOCH + VERIFIED_AT));
+        Self::new()?
+            .with_clock(clock)
+            .self_test()
+            .map_err(CertError::SelfTest)?;
+        Self::new()
+    }
+
+    /// Verify the embedded evidence
+    fn self_test(&self) -> Result<()> {
+        let evidence = Evidence {
+            quote: bincode::deserialize(QUOTE)?,
+            report: REPORT.to_vec(),
+            vcek: VCEK.to_string(),
+            pcrs_sha384: None,
+            event_log: None,
+        };
+        let claim = self.verify_parsed_evidence(
+            evidence,
+            &ReportData::Value(NONCE),
+            &InitDataHash::NotProvided,
+            None,
+            None,
+        )?;
+        ensure!(
+            claim["tpm"]
+                .as_object()
+                .is_some_and(|pcrs| !pcrs.is_empty()),
+            "The claim of the embedded evidence has no PCRs"
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_validated() {
+        AzSnpVtpm::new_validated().unwrap();
+    }
+
+    #[test]
+    fn test_self_test_failure() {
+        // the embedded VCEK isn't valid yet at the epoch
+        let clock = Arc::new(FixedClock::new(UNIX_EPOCH));
+        let err = AzSnpVtpm::new()
+            .unwrap()
+            .with_clock(clock)
+            .self_test()
+            .unwrap_err();
+        let err = CertError::SelfTest(err);
+        assert_eq!(
+            err.to_string(),
+            "Self-test verification of the embedded evidence failed"
+        );
+        assert!(std::error::Error::source(&err).is_some());
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Two-phase attestation: the HCL report is verified once when it is
+//! ingested, later quotes of the same vTPM are verified against it.
+
+use super::clock::is_fresh;
+use super::{ak_pub_der, verify_quote_with_ak, AzSnpVtpm, CertError, ReportDataBinding};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{anyhow, bail, Result};
+use az_snp_vtpm::hcl::HclReport;
+use az_snp_vtpm::vtpm::Quote;
+use openssl::sha::sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Id of an attestation session, the sha256 fingerprint of the AKpub (DER)
+pub type SessionId = [u8; 32];
+
+struct Session {
+    ak_pub: Vec<u8>,
+    ingested_at: SystemTime,
+    claim: TeeEvidenceParsedClaim,
+}
+
+/// Verifier splitting the verification of an evidence in two phases.
+///
+/// `ingest_report` verifies an HCL report and its VCEK like
+/// `AzSnpVtpm::evaluate` and stores the SNP report claims under a session
+/// id. `verify_quote` then verifies vTPM quotes against the AK of the
+/// session, without resubmitting the report. Sessions expire `ttl` after
+/// their report has been ingested.
+pub struct SessionVerifier {
+    verifier: AzSnpVtpm,
+    ttl: Duration,
+    sessions: Mutex<HashMap<SessionId, Session>>,
+}
+
+impl SessionVerifier {
+    pub fn new(verifier: AzSnpVtpm, ttl: Duration) -> Self {
+        Self {
+            verifier,
+            ttl,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Verify the HCL `report` and the `vcek` (PEM) and start a session for
+    /// its AK. Ingesting a report again restarts its session. The verifier
+    /// must have the quote nonce binding, only it binds the AK to the report.
+    pub fn ingest_report(&self, report: Vec<u8>, vcek: &str) -> Result<SessionId> {
+        if self.verifier.report_data_binding != ReportDataBinding::QuoteNonce {
+            bail!("Attestation sessions need the quote nonce report_data binding");
+        }
+        let hcl_report = HclReport::new(self.verifier.unwrap_report(report)?)?;
+        let ak_pub = ak_pub_der(&hcl_report)?;
+        let session_id = sha256(&ak_pub);
+        let claim =
+            self.verifier
+                .verify_report(hcl_report, vcek, &ReportData::NotProvided, None)?;
+
+        let mut sessions = self.lock_sessions()?;
+        let now = self.verifier.now();
+        sessions.retain(|_, session| is_fresh(now, session.ingested_at, self.ttl));
+        sessions.insert(
+            session_id,
+            Session {
+                ak_pub,
+                ingested_at: now,
+                claim,
+            },
+        );
+        Ok(session_id)
+    }
+
+    /// Verify `quote` against the AK of the report ingested for
+    /// `session_id`, returning the claims of the report extended with the
+    /// ones of the quote.
+    pub fn verify_quote(
+        &self,
+        session_id: &SessionId,
+        quote: &Quote,
+        expected_report_data: &[u8],
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        let (ak_pub, mut claim) = self.session(session_id)?;
+        let expected_report_data = ReportData::Value(expected_report_data);
+        verify_quote_with_ak(
+            quote,
+            &ak_pub,
+            self.verifier.expected_nonce(&expected_report_data),
+            expected_init_data_hash,
+            self.verifier.init_data_pcr,
+            &self.verifier.init_data_extend,
+        )?;
+        self.verifier
+            .bind_claim(&mut claim, quote, &expected_report_data)?;
+
+        Ok(claim)
+    }
+
+    /// AK and claims of the session, unless it is unknown or has expired
+    fn session(&self, session_id: &SessionId) -> Result<(Vec<u8>, TeeEvidenceParsedClaim)> {
+        let mut sessions = self.lock_sessions()?;
+        match sessions.get(session_id) {
+            Some(session) if is_fresh(self.verifier.now(), session.ingested_at, self.ttl) => {
+                Ok((session.ak_pub.clone(), session.claim.clone()))
+            }
+            Some(_) => {
+                sessions.remove(session_id);
+                Err(CertError::UnknownSession.into())
+            }
+            None => Err(CertError::UnknownSession.into()),
+        }
+    }
+
+    fn lock_sessions(&self) -> Result<std::sync::MutexGuard<'_, HashMap<SessionId, Session>>> {
+        self.sessions
+            .lock()
+            .map_err(|_| anyhow!("Attestation session cache is poisoned"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{fixture_verifier, QUOTE, REPORT, REPORT_DATA, VCEK};
+    use super::super::FixedClock;
+    use super::*;
+    use std::sync::Arc;
+
+    fn quote() -> Quote {
+        bincode::deserialize(QUOTE).unwrap()
+    }
+
+    #[test]
+    fn test_two_phase_verification() {
+        let verifier = SessionVerifier::new(fixture_verifier(), Duration::from_secs(60));
+        let session_id = verifier.ingest_report(REPORT.to_vec(), VCEK).unwrap();
+
+        let claim = verifier
+            .verify_quote(
+                &session_id,
+                &quote(),
+                REPORT_DATA,
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        assert_eq!(claim["report_data"], hex::encode(REPORT_DATA));
+        assert!(claim["tpm"]["pcr08"].is_string());
+
+        // the quote is still checked against the expected nonce
+        verifier
+            .verify_quote(&session_id, &quote(), b"stale", &InitDataHash::NotProvided)
+            .unwrap_err();
+
+        let err = verifier
+            .verify_quote(&[0; 32], &quote(), REPORT_DATA, &InitDataHash::NotProvided)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::UnknownSession)
+        ));
+    }
+
+    #[test]
+    fn test_ingest_tampered_report() {
+        let verifier = SessionVerifier::new(fixture_verifier(), Duration::from_secs(60));
+        let mut wrong_report = REPORT.to_vec();
+        wrong_report[0x01a6] = 0;
+        verifier.ingest_report(wrong_report, VCEK).unwrap_err();
+    }
+
+    #[test]
+    fn test_ingest_with_key_binding() {
+        let verifier = SessionVerifier::new(
+            fixture_verifier().with_report_data_binding(ReportDataBinding::KeySha512),
+            Duration::from_secs(60),
+        );
+        let err = verifier.ingest_report(REPORT.to_vec(), VCEK).unwrap_err();
+        assert!(err.to_string().contains("quote nonce"));
+    }
+
+    #[test]
+    fn test_session_expiry() {
+        let clock = Arc::new(FixedClock::new(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000),
+        ));
+        let verifier = SessionVerifier::new(
+            AzSnpVtpm::new().unwrap().with_clock(clock.clone()),
+            Duration::from_secs(60),
+        );
+        let session_id = verifier.ingest_report(REPORT.to_vec(), VCEK).unwrap();
+
+        clock.advance(Duration::from_secs(59));
+        verifier
+            .verify_quote(
+                &session_id,
+                &quote(),
+                REPORT_DATA,
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+
+        clock.advance(Duration::from_secs(1));
+        let err = verifier
+            .verify_quote(
+                &session_id,
+                &quote(),
+                REPORT_DATA,
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CertError>(),
+            Some(CertError::UnknownSession)
+        ));
+    }
+}
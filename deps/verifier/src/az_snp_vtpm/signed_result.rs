@@ -0,0 +1,221 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Verification results as JWTs signed by the verifier, for relying parties
+//! which only trust the verifier's public key.
+
+use super::{report_data_json, AzSnpVtpm};
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{bail, Context, Result};
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use openssl::nid::Nid;
+use openssl::pkey::{Id, PKey};
+use serde_json::{json, Map, Value};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Validity of a signed result
+const RESULT_VALIDITY: Duration = Duration::from_secs(300);
+
+/// Claims of a verification result copied into the signed result
+const ATTESTATION_CLAIMS: &[&str] = &[
+    "measurement",
+    "report_data",
+    "init_data",
+    "reported_tcb",
+    "vcek_tcb",
+    "policy_debug_allowed",
+    "policy_migrate_ma",
+];
+
+/// Key and issuer of the signed results
+pub(crate) struct ResultSigner {
+    algorithm: Algorithm,
+    key: EncodingKey,
+    issuer: String,
+}
+
+impl ResultSigner {
+    /// Load a P-256 (ES256) or Ed25519 (EdDSA) private key in PEM
+    fn from_pem(pem: &[u8], issuer: &str) -> Result<Self> {
+        let key = PKey::private_key_from_pem(pem).context("Failed to parse result signing key")?;
+        let der = key.private_key_to_pkcs8()?;
+        let (algorithm, key) = match key.id() {
+            Id::EC if key.ec_key()?.group().curve_name() == Some(Nid::X9_62_PRIME256V1) => {
+                (Algorithm::ES256, EncodingKey::from_ec_der(&der))
+            }
+            Id::ED25519 => (Algorithm::EdDSA, EncodingKey::from_ed_der(&der)),
+            _ => bail!("Result signing key must be a P-256 or Ed25519 key"),
+        };
+        Ok(Self {
+            algorithm,
+            key,
+            issuer: issuer.to_string(),
+        })
+    }
+}
+
+impl AzSnpVtpm {
+    /// Sign results of `evaluate_signed` with the private key `pem`, either
+    /// P-256 (ES256) or Ed25519 (EdDSA), as `issuer`.
+    pub fn with_result_signing_key(mut self, pem: &[u8], issuer: &str) -> Result<Self> {
+        self.result_signer = Some(ResultSigner::from_pem(pem, issuer)?);
+        Ok(self)
+    }
+
+    /// Verify `evidence` like `evaluate` and return the result as a signed
+    /// JWT. Its claims are the issuer (`iss`), issued-at (`iat`), expiry
+    /// (`exp`, 5 minutes later), the hex encoded expected report_data
+    /// (`nonce`), the `verdict` (`accept` or `reject`, with the `error`) and,
+    /// for accepted evidence, the key attestation claims.
+    pub fn evaluate_signed(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> Result<String> {
+        let Some(signer) = &self.result_signer else {
+            bail!("No result signing key configured");
+        };
+
+        let result = self.verify_evidence(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            None,
+        );
+
+        let iat = self
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .context("Time of verification is before the epoch")?;
+        let mut claims = json!({
+            "iss": signer.issuer,
+            "iat": iat.as_secs(),
+            "exp": (iat + RESULT_VALIDITY).as_secs(),
+            "nonce": report_data_json(expected_report_data),
+        });
+        let map = claims.as_object_mut().context("Malformed result claims")?;
+        match result {
+            Ok(claim) => {
+                map.insert("verdict".into(), "accept".into());
+                map.extend(attestation_claims(&claim));
+            }
+            Err(e) => {
+                map.insert("verdict".into(), "reject".into());
+                map.insert("error".into(), format!("{e:#}").into());
+            }
+        }
+
+        encode(&Header::new(signer.algorithm), &claims, &signer.key)
+            .context("Failed to sign the verification result")
+    }
+}
+
+fn attestation_claims(claim: &TeeEvidenceParsedClaim) -> Map<String, Value> {
+    ATTESTATION_CLAIMS
+        .iter()
+        .filter_map(|name| Some((name.to_string(), claim.get(*name)?.clone())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_verifier, FIXTURE_NOW, REPORT, REPORT_DATA,
+    };
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{decode, DecodingKey, Validation};
+    use openssl::bn::{BigNum, BigNumContext};
+    use openssl::ec::{EcGroup, EcKey};
+
+    fn signing_key() -> (Vec<u8>, DecodingKey) {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::generate(&group).unwrap();
+        let (mut x, mut y) = (BigNum::new().unwrap(), BigNum::new().unwrap());
+        key.public_key()
+            .affine_coordinates_gfp(&group, &mut x, &mut y, &mut BigNumContext::new().unwrap())
+            .unwrap();
+        let decoding_key = DecodingKey::from_ec_components(
+            &URL_SAFE_NO_PAD.encode(x.to_vec_padded(32).unwrap()),
+            &URL_SAFE_NO_PAD.encode(y.to_vec_padded(32).unwrap()),
+        )
+        .unwrap();
+        let pem = PKey::from_ec_key(key)
+            .unwrap()
+            .private_key_to_pem_pkcs8()
+            .unwrap();
+        (pem, decoding_key)
+    }
+
+    #[test]
+    fn test_evaluate_signed() {
+        let (pem, decoding_key) = signing_key();
+        let verifier = fixture_verifier()
+            .with_result_signing_key(&pem, "https://verifier.example")
+            .unwrap();
+        let mut validation = Validation::new(Algorithm::ES256);
+        validation.set_issuer(&["https://verifier.example"]);
+        // the token is issued at the time of the fixture clock
+        validation.validate_exp = false;
+
+        let token = verifier
+            .evaluate_signed(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        let claims = decode::<Value>(&token, &decoding_key, &validation)
+            .unwrap()
+            .claims;
+        assert_eq!(claims["verdict"], "accept");
+        assert_eq!(claims["nonce"], hex::encode(REPORT_DATA));
+        assert_eq!(claims["report_data"], hex::encode(REPORT_DATA));
+        assert!(claims["measurement"].is_string());
+        assert!(claims["reported_tcb"].is_object());
+        assert_eq!(claims["iat"], FIXTURE_NOW);
+        assert_eq!(claims["exp"], FIXTURE_NOW + RESULT_VALIDITY.as_secs());
+
+        let token = verifier
+            .evaluate_signed(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(b"stale"),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap();
+        let claims = decode::<Value>(&token, &decoding_key, &validation)
+            .unwrap()
+            .claims;
+        assert_eq!(claims["verdict"], "reject");
+        assert!(claims["error"].is_string());
+        assert!(claims.get("measurement").is_none());
+
+        // a token signed by another key is rejected
+        let (_, other_key) = signing_key();
+        decode::<Value>(&token, &other_key, &validation).unwrap_err();
+    }
+
+    #[test]
+    fn test_result_signing_key() {
+        let rsa = PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        fixture_verifier()
+            .with_result_signing_key(
+                &rsa.private_key_to_pem_pkcs8().unwrap(),
+                "https://verifier.example",
+            )
+            .err()
+            .unwrap();
+
+        let verifier = fixture_verifier();
+        verifier
+            .evaluate_signed(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+            )
+            .unwrap_err();
+    }
+}
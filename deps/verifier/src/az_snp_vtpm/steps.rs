@@ -0,0 +1,195 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Timing of the verification steps. With the `tracing` feature, each step
+//! is a `tracing` event of the `az_snp_vtpm.verify` span, which has the
+//! `evidence_kind` and `outcome` fields, otherwise it is logged. The steps
+//! are also recorded in the transcript of `evaluate_with_report`, and with
+//! the `metrics` feature, in the step duration histogram.
+
+use super::transcript::{self, StepStatus};
+use std::fmt::Display;
+use std::time::Instant;
+
+#[cfg(feature = "tracing")]
+const EVIDENCE_KIND: &str = "az-snp-vtpm";
+
+/// Run the verification step `name`, recording its outcome and duration
+pub(crate) fn step<T, E: Display>(
+    name: &'static str,
+    run: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let start = Instant::now();
+    let result = run();
+    let elapsed = start.elapsed();
+    #[cfg(feature = "metrics")]
+    super::metrics::record_step(name, elapsed, result.is_ok());
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        step = name,
+        elapsed_us = elapsed.as_micros() as u64,
+        passed = result.is_ok(),
+        "verification step"
+    );
+    #[cfg(not(feature = "tracing"))]
+    log::debug!(
+        "Verification step {name} {} in {elapsed:?}",
+        if result.is_ok() { "passed" } else { "failed" }
+    );
+    match &result {
+        Ok(_) => transcript::record(name, StepStatus::Pass, None),
+        Err(e) => transcript::record(name, StepStatus::Fail, Some(format!("{e:#}"))),
+    }
+    result
+}
+
+/// Run the verification `verify` in the `az_snp_vtpm.verify` span, with
+/// its outcome
+#[cfg(feature = "tracing")]
+pub(crate) fn in_verification<T, E>(verify: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let span = tracing::info_span!(
+        "az_snp_vtpm.verify",
+        evidence_kind = EVIDENCE_KIND,
+        outcome = tracing::field::Empty,
+    );
+    let result = span.in_scope(verify);
+    span.record(
+        "outcome",
+        if result.is_ok() { "success" } else { "failure" },
+    );
+    result
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use crate::{InitDataHash, ReportData};
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Subscriber};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Registry;
+
+    type Fields = BTreeMap<String, String>;
+
+    /// The closed spans and the events, with the name of their span
+    #[derive(Default)]
+    struct Captured {
+        spans: Vec<(&'static str, Fields)>,
+        events: Vec<(Option<&'static str>, Fields)>,
+    }
+
+    /// A layer capturing the spans and events
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Captured>>);
+
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().into(), value.into());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().into(), format!("{value:?}"));
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Capture {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = Fields::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).unwrap();
+            let mut extensions = span.extensions_mut();
+            values.record(&mut FieldVisitor(extensions.get_mut().unwrap()));
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let mut fields = Fields::new();
+            event.record(&mut FieldVisitor(&mut fields));
+            let span = ctx.event_span(event).map(|span| span.name());
+            self.0.lock().unwrap().events.push((span, fields));
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            let span = ctx.span(&id).unwrap();
+            let fields = span.extensions().get::<Fields>().cloned().unwrap();
+            self.0.lock().unwrap().spans.push((span.name(), fields));
+        }
+    }
+
+    #[test]
+    fn test_verification_span() {
+        let capture = Capture::default();
+        let subscriber = Registry::default().with(capture.clone());
+        let verify = |report_data: &[u8]| {
+            fixture_verifier().verify_evidence(
+                &evidence_fixture(REPORT),
+                &ReportData::Value(report_data),
+                &InitDataHash::NotProvided,
+                None,
+            )
+        };
+        tracing::subscriber::with_default(subscriber, || {
+            verify(REPORT_DATA).unwrap();
+            verify(b"stale").unwrap_err();
+        });
+
+        let captured = capture.0.lock().unwrap();
+        let spans: Vec<_> = captured
+            .spans
+            .iter()
+            .filter(|(name, _)| *name == "az_snp_vtpm.verify")
+            .map(|(_, fields)| (fields["evidence_kind"].as_str(), fields["outcome"].as_str()))
+            .collect();
+        assert_eq!(
+            spans,
+            [("az-snp-vtpm", "success"), ("az-snp-vtpm", "failure")]
+        );
+
+        let events: Vec<&Fields> = captured
+            .events
+            .iter()
+            .filter(|(_, fields)| {
+                fields
+                    .get("message")
+                    .is_some_and(|m| m == "verification step")
+            })
+            .map(|(span, fields)| {
+                assert_eq!(*span, Some("az_snp_vtpm.verify"));
+                fields
+            })
+            .collect();
+        let steps: Vec<(&str, &str)> = events
+            .iter()
+            .map(|fields| (fields["step"].as_str(), fields["passed"].as_str()))
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                ("vmpl", "true"),
+                ("signature", "true"),
+                ("nonce", "true"),
+                ("pcr", "true"),
+                ("report_data", "true"),
+                ("snp", "true"),
+                ("vmpl", "true"),
+                ("signature", "true"),
+                ("nonce", "false"),
+            ]
+        );
+        assert!(events
+            .iter()
+            .all(|fields| fields.contains_key("elapsed_us")));
+    }
+}
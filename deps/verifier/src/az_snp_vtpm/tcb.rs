@@ -0,0 +1,238 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+use crate::snp::{get_oid_int, LOADER_SPL_OID, SNP_SPL_OID, TEE_SPL_OID, UCODE_SPL_OID};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use sev::firmware::host::TcbVersion;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+use x509_parser::prelude::*;
+
+/// Bits of the raw TCB encoding which hold no component
+const RAW_RESERVED_MASK: u64 = 0x0000_ffff_ffff_0000;
+
+/// The security-relevant components of an SNP TCB version
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tcb {
+    pub bootloader: u8,
+    pub tee: u8,
+    pub snp: u8,
+    pub microcode: u8,
+}
+
+impl From<&TcbVersion> for Tcb {
+    fn from(tcb: &TcbVersion) -> Self {
+        Self {
+            bootloader: tcb.bootloader,
+            tee: tcb.tee,
+            snp: tcb.snp,
+            microcode: tcb.microcode,
+        }
+    }
+}
+
+impl Tcb {
+    /// Read the TCB a VCEK has been issued for from its AMD SPL extensions
+    pub fn from_vcek_der(der: &[u8]) -> Result<Self> {
+        let (_, cert) = X509Certificate::from_der(der).context("Failed to parse VCEK")?;
+        let tbs = &cert.tbs_certificate;
+        Ok(Self {
+            bootloader: get_oid_int(tbs, LOADER_SPL_OID)?,
+            tee: get_oid_int(tbs, TEE_SPL_OID)?,
+            snp: get_oid_int(tbs, SNP_SPL_OID)?,
+            microcode: get_oid_int(tbs, UCODE_SPL_OID)?,
+        })
+    }
+
+    /// Decode AMD's packed 64-bit TCB_VERSION: bootloader in bits 0-7, tee
+    /// in bits 8-15, snp in bits 48-55 and microcode in bits 56-63. The
+    /// reserved bits must be zero.
+    pub fn from_raw(raw: u64) -> Result<Self> {
+        if raw & RAW_RESERVED_MASK != 0 {
+            bail!("Reserved bits of raw TCB 0x{raw:016x} are set");
+        }
+        let bytes = raw.to_le_bytes();
+        Ok(Self {
+            bootloader: bytes[0],
+            tee: bytes[1],
+            snp: bytes[6],
+            microcode: bytes[7],
+        })
+    }
+
+    /// Encode the TCB in AMD's packed 64-bit TCB_VERSION form
+    pub fn to_raw(&self) -> u64 {
+        u64::from_le_bytes([
+            self.bootloader,
+            self.tee,
+            0,
+            0,
+            0,
+            0,
+            self.snp,
+            self.microcode,
+        ])
+    }
+
+    /// Whether every component is at least the one of `floor`
+    pub fn meets(&self, floor: &Tcb) -> bool {
+        self.component_below(floor).is_none()
+    }
+
+    /// The name of the first component below the one of `floor`, if any
+    pub fn component_below(&self, floor: &Tcb) -> Option<&'static str> {
+        self.zip(floor)
+            .into_iter()
+            .find(|(_, svn, min)| svn < min)
+            .map(|(component, _, _)| component)
+    }
+
+    /// The first component differing from the one of `other`, with the SVN
+    /// of `self` and of `other`, if any
+    pub fn component_mismatch(&self, other: &Tcb) -> Option<(&'static str, u8, u8)> {
+        self.zip(other).into_iter().find(|(_, a, b)| a != b)
+    }
+
+    fn zip(&self, other: &Tcb) -> [(&'static str, u8, u8); 4] {
+        [
+            ("bootloader", self.bootloader, other.bootloader),
+            ("tee", self.tee, other.tee),
+            ("snp", self.snp, other.snp),
+            ("microcode", self.microcode, other.microcode),
+        ]
+    }
+}
+
+/// Constraint on the microcode SVN of the reported TCB, which is rolled out
+/// independently of the other TCB components
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MicrocodePolicy {
+    /// Accept any microcode SVN at least this one
+    Minimum(u8),
+    /// Accept only these microcode SVNs
+    Allowed(BTreeSet<u8>),
+}
+
+impl MicrocodePolicy {
+    pub fn allows(&self, microcode: u8) -> bool {
+        match self {
+            Self::Minimum(minimum) => microcode >= *minimum,
+            Self::Allowed(allowed) => allowed.contains(&microcode),
+        }
+    }
+}
+
+/// Minimum ABI version of the firmware required by an SNP guest policy,
+/// ordered by major, then minor version
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AbiVersion {
+    pub major: u8,
+    pub minor: u8,
+}
+
+impl fmt::Display for AbiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Constraints on the SNP guest policy, none by default
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GuestPolicyConstraints {
+    /// Reject guests whose policy allows debugging (DEBUG)
+    pub no_debug: bool,
+    /// Reject guests whose policy allows a migration agent (MIGRATE_MA)
+    pub no_migration: bool,
+    /// Minimum firmware ABI version the guest policy must require
+    pub min_abi: Option<AbiVersion>,
+}
+
+/// Parse a raw TCB as printed by AMD tooling, in hex with a `0x` prefix or
+/// in decimal.
+impl FromStr for Tcb {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let raw = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => u64::from_str_radix(hex, 16),
+            None => s.parse(),
+        }
+        .with_context(|| format!("Malformed raw TCB {s}"))?;
+        Self::from_raw(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE_TCB: Tcb = Tcb {
+        bootloader: 3,
+        tee: 0,
+        snp: 8,
+        microcode: 115,
+    };
+
+    #[test]
+    fn test_raw_round_trip() {
+        assert_eq!(Tcb::from_raw(0x7308_0000_0000_0003).unwrap(), FIXTURE_TCB);
+        assert_eq!(FIXTURE_TCB.to_raw(), 0x7308_0000_0000_0003);
+
+        let tcb = Tcb {
+            bootloader: 1,
+            tee: 2,
+            snp: 3,
+            microcode: 4,
+        };
+        assert_eq!(Tcb::from_raw(tcb.to_raw()).unwrap(), tcb);
+        assert_eq!(
+            Tcb::from_raw(!RAW_RESERVED_MASK).unwrap().to_raw(),
+            !RAW_RESERVED_MASK
+        );
+
+        Tcb::from_raw(0x7308_0000_0001_0003).unwrap_err();
+    }
+
+    #[test]
+    fn test_component_below() {
+        assert_eq!(FIXTURE_TCB.component_below(&FIXTURE_TCB), None);
+        let floor = Tcb {
+            microcode: 116,
+            ..FIXTURE_TCB
+        };
+        assert_eq!(FIXTURE_TCB.component_below(&floor), Some("microcode"));
+        assert!(floor.meets(&FIXTURE_TCB));
+        let floor = Tcb {
+            tee: 1,
+            snp: 9,
+            ..FIXTURE_TCB
+        };
+        assert_eq!(FIXTURE_TCB.component_below(&floor), Some("tee"));
+    }
+
+    #[test]
+    fn test_component_mismatch() {
+        assert_eq!(FIXTURE_TCB.component_mismatch(&FIXTURE_TCB), None);
+        let other = Tcb {
+            snp: FIXTURE_TCB.snp + 1,
+            ..FIXTURE_TCB
+        };
+        assert_eq!(
+            FIXTURE_TCB.component_mismatch(&other),
+            Some(("snp", FIXTURE_TCB.snp, other.snp))
+        );
+    }
+
+    #[test]
+    fn test_parse_raw() {
+        assert_eq!("0x7308000000000003".parse::<Tcb>().unwrap(), FIXTURE_TCB);
+        assert_eq!("8288875114175397891".parse::<Tcb>().unwrap(), FIXTURE_TCB);
+        "0xg".parse::<Tcb>().unwrap_err();
+    }
+}
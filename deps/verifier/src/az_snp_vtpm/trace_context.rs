@@ -0,0 +1,162 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Continuation of the attester's trace in the verification span.
+//!
+//! The evidence envelope may carry a W3C `traceparent` next to the quote,
+//! report and VCEK. Its span is the parent of the verification span, so
+//! that the attestation and its verification are part of the same trace.
+//! Without a valid `traceparent`, the verification span is a child of the
+//! current context.
+
+use anyhow::{bail, Context as _, Result};
+use log::warn;
+use opentelemetry::global;
+use opentelemetry::trace::{
+    Span, SpanContext, SpanId, Status, TraceContextExt, TraceFlags, TraceId, TraceState, Tracer,
+};
+use opentelemetry::Context;
+use serde::Deserialize;
+
+/// Name of the verification span
+const SPAN_NAME: &str = "az-snp-vtpm.verify";
+
+/// The trace context of an evidence envelope, ignoring the evidence
+#[derive(Deserialize)]
+struct TraceEnvelope {
+    traceparent: Option<String>,
+}
+
+/// Parse a version 00 W3C traceparent:
+/// `00-<32 hex trace-id>-<16 hex parent-id>-<2 hex trace-flags>`
+fn parse_traceparent(traceparent: &str) -> Result<SpanContext> {
+    let parts: Vec<&str> = traceparent.trim().split('-').collect();
+    let [version, trace_id, span_id, flags] = parts[..] else {
+        bail!("Malformed traceparent {traceparent}");
+    };
+    if version != "00" {
+        bail!("Unsupported traceparent version {version}");
+    }
+    let is_hex = |field: &str, len| {
+        field.len() == len
+            && field
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    };
+    if !is_hex(trace_id, 32) || !is_hex(span_id, 16) || !is_hex(flags, 2) {
+        bail!("Malformed traceparent {traceparent}");
+    }
+
+    let trace_id = TraceId::from_hex(trace_id).context("Malformed traceparent trace-id")?;
+    let span_id = SpanId::from_hex(span_id).context("Malformed traceparent parent-id")?;
+    let flags = u8::from_str_radix(flags, 16).context("Malformed traceparent trace-flags")?;
+    if trace_id == TraceId::INVALID || span_id == SpanId::INVALID {
+        bail!("traceparent has an all-zero trace-id or parent-id");
+    }
+    Ok(SpanContext::new(
+        trace_id,
+        span_id,
+        TraceFlags::new(flags) & TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    ))
+}
+
+/// The parent context of the verification of `evidence`
+fn parent_context(evidence: &[u8]) -> Context {
+    let Ok(TraceEnvelope {
+        traceparent: Some(traceparent),
+    }) = serde_json::from_slice(evidence)
+    else {
+        return Context::current();
+    };
+    match parse_traceparent(&traceparent) {
+        Ok(span_context) => Context::current().with_remote_span_context(span_context),
+        Err(e) => {
+            warn!("Ignoring the traceparent of the evidence: {e:#}");
+            Context::current()
+        }
+    }
+}
+
+/// Run `verify` in a verification span continuing the trace propagated in
+/// `evidence`, if any
+pub(crate) fn in_verification_span<T>(
+    evidence: &[u8],
+    verify: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    let tracer = global::tracer("verifier");
+    let mut span = tracer.start_with_context(SPAN_NAME, &parent_context(evidence));
+    let result = verify();
+    if let Err(e) = &result {
+        span.set_status(Status::error(format!("{e:#}")));
+    }
+    span.end();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+    use crate::{InitDataHash, ReportData};
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use serde_json::Value;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let span_context = parse_traceparent(TRACEPARENT).unwrap();
+        assert_eq!(
+            span_context.trace_id(),
+            TraceId::from_hex("4bf92f3577b34da6a3ce929d0e0e4736").unwrap()
+        );
+        assert_eq!(
+            span_context.span_id(),
+            SpanId::from_hex("00f067aa0ba902b7").unwrap()
+        );
+        assert!(span_context.is_sampled());
+        assert!(span_context.is_remote());
+
+        for traceparent in [
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            parse_traceparent(traceparent).unwrap_err();
+        }
+    }
+
+    #[test]
+    fn test_verification_span_parent() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        global::set_tracer_provider(provider.clone());
+
+        let mut evidence: Value = serde_json::from_slice(&evidence_fixture(REPORT)).unwrap();
+        evidence["traceparent"] = TRACEPARENT.into();
+        fixture_verifier()
+            .verify_evidence(
+                &serde_json::to_vec(&evidence).unwrap(),
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                None,
+            )
+            .unwrap();
+
+        provider.force_flush();
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans.iter().find(|span| span.name == SPAN_NAME).unwrap();
+        let parent = parse_traceparent(TRACEPARENT).unwrap();
+        assert_eq!(span.span_context.trace_id(), parent.trace_id());
+        assert_eq!(span.parent_span_id, parent.span_id());
+    }
+}
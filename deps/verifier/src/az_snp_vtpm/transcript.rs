@@ -0,0 +1,169 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Transcript of the checks of a verification, for audit records.
+//!
+//! The steps record their outcome in the transcript of the current thread
+//! while `evaluate_with_report` runs. Steps which didn't run, because an
+//! earlier one failed, are reported as skipped.
+
+use super::AzSnpVtpm;
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::Result;
+use serde::Serialize;
+use std::cell::RefCell;
+
+/// The steps of a transcript, in the order of the report
+const STEPS: [&str; 7] = [
+    "signature",
+    "nonce",
+    "pcr",
+    "report_data",
+    "snp",
+    "vmpl",
+    "init_data",
+];
+
+thread_local! {
+    static TRANSCRIPT: RefCell<Option<Vec<VerificationStep>>> = const { RefCell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepStatus {
+    Pass,
+    Skip,
+    Fail,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VerificationStep {
+    pub name: &'static str,
+    pub status: StepStatus,
+    /// Why the step failed or has been skipped
+    pub detail: Option<String>,
+}
+
+/// The outcome of each check of a verification
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct VerificationReport {
+    pub steps: Vec<VerificationStep>,
+}
+
+impl VerificationReport {
+    /// The step `name`, if it is a step of the transcript
+    pub fn step(&self, name: &str) -> Option<&VerificationStep> {
+        self.steps.iter().find(|step| step.name == name)
+    }
+}
+
+/// Record the outcome of the step `name` in the transcript, if any
+pub(crate) fn record(name: &'static str, status: StepStatus, detail: Option<String>) {
+    TRANSCRIPT.with(|transcript| {
+        if let Some(steps) = transcript.borrow_mut().as_mut() {
+            steps.push(VerificationStep {
+                name,
+                status,
+                detail,
+            });
+        }
+    });
+}
+
+/// Record the step `name` as skipped because of `reason`
+pub(crate) fn skip(name: &'static str, reason: &str) {
+    record(name, StepStatus::Skip, Some(reason.to_string()));
+}
+
+impl AzSnpVtpm {
+    /// Same as `evaluate_blocking`, also returning the transcript of the
+    /// checks, whether the verification succeeded or not.
+    pub fn evaluate_with_report(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> (Result<TeeEvidenceParsedClaim>, VerificationReport) {
+        let previous = TRANSCRIPT.with(|transcript| transcript.replace(Some(vec![])));
+        let result =
+            self.evaluate_blocking(evidence, expected_report_data, expected_init_data_hash);
+        let recorded = TRANSCRIPT
+            .with(|transcript| transcript.replace(previous))
+            .unwrap_or_default();
+
+        let steps = STEPS
+            .iter()
+            .map(|name| {
+                recorded
+                    .iter()
+                    .find(|step| step.name == *name)
+                    .cloned()
+                    .unwrap_or(VerificationStep {
+                        name,
+                        status: StepStatus::Skip,
+                        detail: Some("not run".to_string()),
+                    })
+            })
+            .collect();
+        (result, VerificationReport { steps })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{evidence_fixture, fixture_verifier, REPORT, REPORT_DATA};
+    use super::*;
+
+    fn statuses(report: &VerificationReport) -> Vec<(&str, StepStatus)> {
+        report
+            .steps
+            .iter()
+            .map(|step| (step.name, step.status))
+            .collect()
+    }
+
+    #[test]
+    fn test_verification_report() {
+        let verifier = fixture_verifier();
+        let (result, report) = verifier.evaluate_with_report(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+        );
+        result.unwrap();
+        assert_eq!(
+            statuses(&report),
+            [
+                ("signature", StepStatus::Pass),
+                ("nonce", StepStatus::Pass),
+                ("pcr", StepStatus::Pass),
+                ("report_data", StepStatus::Pass),
+                ("snp", StepStatus::Pass),
+                ("vmpl", StepStatus::Pass),
+                ("init_data", StepStatus::Skip),
+            ]
+        );
+        assert_eq!(
+            report.step("init_data").unwrap().detail.as_deref(),
+            Some("no expected init_data hash")
+        );
+
+        // the steps after the failing nonce check don't run
+        let (result, report) = verifier.evaluate_with_report(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(b"another nonce"),
+            &InitDataHash::NotProvided,
+        );
+        result.unwrap_err();
+        assert_eq!(report.step("nonce").unwrap().status, StepStatus::Fail);
+        let snp = report.step("snp").unwrap();
+        assert_eq!(snp.status, StepStatus::Skip);
+        assert_eq!(snp.detail.as_deref(), Some("not run"));
+
+        // nothing is recorded outside of evaluate_with_report
+        record("signature", StepStatus::Pass, None);
+        TRANSCRIPT.with(|transcript| assert!(transcript.borrow().is_none()));
+    }
+}
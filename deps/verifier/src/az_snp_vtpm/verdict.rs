@@ -0,0 +1,253 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Compact, cacheable verdicts for gateways which see identical evidence
+//! resubmitted within a short window.
+//!
+//! A verdict is keyed by a digest over the evidence, the expected
+//! report_data and init_data and the verifier's policy, so it is only ever
+//! reused for the same challenge under the same policy. The caller picks
+//! the lifetime of a verdict and must not make it outlive the validity of
+//! the nonce it has been issued for: once the nonce has expired, the
+//! evidence is stale and must be rejected, not served from the cache.
+
+use super::clock::{Clock, SystemClock};
+use super::AzSnpVtpm;
+use crate::{InitDataHash, ReportData, TeeEvidenceParsedClaim};
+use anyhow::{anyhow, Result};
+use log::debug;
+use openssl::sha::Sha256;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+/// Outcome of the verification of an evidence
+#[derive(Clone, Debug, PartialEq)]
+pub enum Verdict {
+    Accept(TeeEvidenceParsedClaim),
+    Reject(String),
+}
+
+impl AzSnpVtpm {
+    /// Cache key of the verdict on `evidence` for the given expectations
+    pub fn verdict_digest(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+    ) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hash_field(&mut hasher, Some(evidence));
+        match expected_report_data {
+            ReportData::Value(value) => hash_field(&mut hasher, Some(value)),
+            ReportData::Segments(segments) => {
+                hasher.update(&[2]);
+                hasher.update(&(segments.len() as u64).to_be_bytes());
+                for (offset, value) in segments {
+                    hasher.update(&(*offset as u64).to_be_bytes());
+                    hash_field(&mut hasher, Some(value));
+                }
+            }
+            ReportData::NotProvided => hash_field(&mut hasher, None),
+        }
+        hash_field(
+            &mut hasher,
+            match expected_init_data_hash {
+                InitDataHash::Value(value) => Some(value),
+                InitDataHash::NotProvided => None,
+            },
+        );
+        hasher.update(&self.policy_hash());
+        hasher.finish()
+    }
+
+    /// Verify `evidence` like `evaluate`, returning the cache key of the
+    /// verdict, the verdict and the time it expires, `ttl` from now.
+    /// `ttl` must not exceed the remaining validity of the nonce.
+    pub fn evaluate_cacheable(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        ttl: Duration,
+    ) -> ([u8; 32], Verdict, SystemTime) {
+        let digest = self.verdict_digest(evidence, expected_report_data, expected_init_data_hash);
+        let verdict = match self.verify_evidence(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            None,
+        ) {
+            Ok(claim) => Verdict::Accept(claim),
+            Err(e) => Verdict::Reject(format!("{e:#}")),
+        };
+        (digest, verdict, self.now() + ttl)
+    }
+}
+
+/// Verdicts and their expiry by digest
+type Verdicts = HashMap<[u8; 32], (Verdict, SystemTime)>;
+
+/// In-memory cache of verdicts, keyed by `AzSnpVtpm::verdict_digest`
+pub struct VerdictCache {
+    verdicts: Mutex<Verdicts>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Default for VerdictCache {
+    fn default() -> Self {
+        Self {
+            verdicts: Mutex::new(HashMap::new()),
+            clock: Arc::new(SystemClock),
+        }
+    }
+}
+
+impl VerdictCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `clock` instead of the system clock to expire verdicts
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The verdict cached for `digest`, unless it has expired
+    pub fn get(&self, digest: &[u8; 32]) -> Result<Option<Verdict>> {
+        let mut verdicts = self.lock_verdicts()?;
+        match verdicts.get(digest) {
+            Some((verdict, expiry)) if self.clock.now() < *expiry => Ok(Some(verdict.clone())),
+            Some(_) => {
+                verdicts.remove(digest);
+                Ok(None)
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn insert(&self, digest: [u8; 32], verdict: Verdict, expiry: SystemTime) -> Result<()> {
+        let mut verdicts = self.lock_verdicts()?;
+        let now = self.clock.now();
+        verdicts.retain(|_, (_, expiry)| now < *expiry);
+        verdicts.insert(digest, (verdict, expiry));
+        Ok(())
+    }
+
+    /// Return the cached verdict on `evidence`, or verify it with `verifier`
+    /// and cache the verdict for `ttl`.
+    pub fn evaluate(
+        &self,
+        verifier: &AzSnpVtpm,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        ttl: Duration,
+    ) -> Result<Verdict> {
+        let digest =
+            verifier.verdict_digest(evidence, expected_report_data, expected_init_data_hash);
+        if let Some(verdict) = self.get(&digest)? {
+            debug!("Reusing cached verdict");
+            return Ok(verdict);
+        }
+
+        let (digest, verdict, expiry) = verifier.evaluate_cacheable(
+            evidence,
+            expected_report_data,
+            expected_init_data_hash,
+            ttl,
+        );
+        self.insert(digest, verdict.clone(), expiry)?;
+        Ok(verdict)
+    }
+
+    fn lock_verdicts(&self) -> Result<std::sync::MutexGuard<'_, Verdicts>> {
+        self.verdicts
+            .lock()
+            .map_err(|_| anyhow!("Verdict cache is poisoned"))
+    }
+}
+
+/// Hash an optional field unambiguously: a presence byte, then the length
+/// and the value.
+fn hash_field(hasher: &mut Sha256, value: Option<&[u8]>) {
+    match value {
+        Some(value) => {
+            hasher.update(&[1]);
+            hasher.update(&(value.len() as u64).to_be_bytes());
+            hasher.update(value);
+        }
+        None => hasher.update(&[0]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        evidence_fixture, fixture_clock, fixture_verifier, REPORT, REPORT_DATA,
+    };
+    use super::super::FixedClock;
+    use super::*;
+
+    #[test]
+    fn test_cached_verdict() {
+        let verifier = fixture_verifier();
+        let cache = VerdictCache::new().with_clock(fixture_clock());
+        let evidence = evidence_fixture(REPORT);
+        let ttl = Duration::from_secs(60);
+
+        let verdict = cache
+            .evaluate(
+                &verifier,
+                &evidence,
+                &ReportData::Value(REPORT_DATA),
+                &InitDataHash::NotProvided,
+                ttl,
+            )
+            .unwrap();
+        assert!(matches!(verdict, Verdict::Accept(_)));
+
+        let digest = verifier.verdict_digest(
+            &evidence,
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+        );
+        assert_eq!(cache.get(&digest).unwrap(), Some(verdict));
+
+        // another nonce doesn't hit the cached verdict
+        let verdict = cache
+            .evaluate(
+                &verifier,
+                &evidence,
+                &ReportData::Value(b"stale"),
+                &InitDataHash::NotProvided,
+                ttl,
+            )
+            .unwrap();
+        assert!(matches!(verdict, Verdict::Reject(_)));
+    }
+
+    #[test]
+    fn test_cached_verdict_expiry() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_800_000_000);
+        let clock = Arc::new(FixedClock::new(start));
+        let verifier = AzSnpVtpm::new().unwrap().with_clock(clock.clone());
+        let cache = VerdictCache::new().with_clock(clock.clone());
+        let (digest, verdict, expiry) = verifier.evaluate_cacheable(
+            &evidence_fixture(REPORT),
+            &ReportData::Value(REPORT_DATA),
+            &InitDataHash::NotProvided,
+            Duration::from_secs(60),
+        );
+        assert_eq!(expiry, start + Duration::from_secs(60));
+        cache.insert(digest, verdict.clone(), expiry).unwrap();
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(cache.get(&digest).unwrap(), Some(verdict));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(cache.get(&digest).unwrap(), None);
+    }
+}
@@ -3,10 +3,14 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use super::az_snp_vtpm::{extend_claim, verify_init_data};
 use super::tdx::claims::generate_parsed_claim;
 use super::tdx::quote::{parse_tdx_quote, Quote as TdQuote};
 use super::{TeeEvidenceParsedClaim, Verifier};
+use crate::az_vtpm::vtpm::{
+    extend_claim, sha256_pcrs_by_index, verify_nonce, verify_nonce_segments, verify_pcrs,
+    verify_signature,
+};
+use crate::az_vtpm::{verify_init_data, InitDataExtend, INITDATA_PCR};
 use crate::intel_dcap::{ecdsa_quote_verification, extend_using_custom_claims};
 use crate::{InitDataHash, ReportData};
 use anyhow::{bail, Context, Result};
@@ -14,7 +18,6 @@ use async_trait::async_trait;
 use az_tdx_vtpm::hcl::HclReport;
 use az_tdx_vtpm::vtpm::Quote as TpmQuote;
 use log::debug;
-use openssl::pkey::PKey;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
@@ -29,6 +32,10 @@ pub struct AzTdxVtpm;
 
 #[async_trait]
 impl Verifier for AzTdxVtpm {
+    fn evidence_kind(&self) -> &'static str {
+        "az-tdx-vtpm"
+    }
+
     /// The following verification steps are performed:
     /// 1. TPM Quote has been signed by AK included in the HCL variable data
     /// 2. Attestation nonce matches TPM Quote nonce
@@ -42,30 +49,40 @@ impl Verifier for AzTdxVtpm {
         expected_report_data: &ReportData,
         expected_init_data_hash: &InitDataHash,
     ) -> Result<TeeEvidenceParsedClaim> {
-        let ReportData::Value(expected_report_data) = expected_report_data else {
-            bail!("unexpected empty report data");
-        };
-
         let evidence = serde_json::from_slice::<Evidence>(evidence)
             .context("Failed to deserialize Azure vTPM TDX evidence")?;
 
         let hcl_report = HclReport::new(evidence.hcl_report)?;
-        verify_tpm_signature(&evidence.tpm_quote, &hcl_report)?;
+        verify_signature(&evidence.tpm_quote, &hcl_report)?;
 
-        verify_tpm_nonce(&evidence.tpm_quote, expected_report_data)?;
+        match expected_report_data {
+            ReportData::Value(expected_report_data) => {
+                verify_nonce(&evidence.tpm_quote, expected_report_data)?
+            }
+            ReportData::Segments(segments) => verify_nonce_segments(&evidence.tpm_quote, segments)?,
+            ReportData::NotProvided => bail!("unexpected empty report data"),
+        }
 
-        verify_pcrs(&evidence.tpm_quote)?;
+        verify_pcrs(&evidence.tpm_quote, None)?;
 
         let custom_claims = ecdsa_quote_verification(&evidence.td_quote).await?;
         let td_quote = parse_tdx_quote(&evidence.td_quote)?;
 
         verify_hcl_var_data(&hcl_report, &td_quote)?;
 
-        let pcrs: Vec<&[u8; 32]> = evidence.tpm_quote.pcrs_sha256().collect();
-        verify_init_data(expected_init_data_hash, &pcrs)?;
+        let pcrs = sha256_pcrs_by_index(&evidence.tpm_quote)?
+            .into_iter()
+            .map(|(index, pcr)| (index, pcr.as_slice()))
+            .collect();
+        verify_init_data(
+            expected_init_data_hash,
+            &pcrs,
+            INITDATA_PCR,
+            &InitDataExtend::default(),
+        )?;
 
         let mut claim = generate_parsed_claim(td_quote, None, None)?;
-        extend_claim(&mut claim, &evidence.tpm_quote)?;
+        extend_claim(&mut claim, &evidence.tpm_quote, INITDATA_PCR)?;
         extend_using_custom_claims(&mut claim, custom_claims)?;
 
         Ok(claim)
@@ -74,44 +91,17 @@ impl Verifier for AzTdxVtpm {
 
 fn verify_hcl_var_data(hcl_report: &HclReport, td_quote: &TdQuote) -> Result<()> {
     let var_data_hash = hcl_report.var_data_sha256();
-    if var_data_hash != td_quote.report_data()[..32] {
+    if td_quote.report_data().get(..32) != Some(var_data_hash.as_slice()) {
         bail!("TDX Quote report data mismatch");
     }
     debug!("Report data verification completed successfully.");
     Ok(())
 }
 
-fn verify_tpm_signature(quote: &TpmQuote, hcl_report: &HclReport) -> Result<()> {
-    let ak_pub = hcl_report.ak_pub().context("Failed to get AKpub")?;
-    let der = ak_pub.key.try_to_der()?;
-    let ak_pub = PKey::public_key_from_der(&der).context("Failed to parse AKpub")?;
-
-    quote
-        .verify_signature(&ak_pub)
-        .context("Failed to verify vTPM quote")?;
-    Ok(())
-}
-
-fn verify_pcrs(quote: &TpmQuote) -> Result<()> {
-    quote
-        .verify_pcrs()
-        .context("Digest of PCRs does not match digest in Quote")?;
-    debug!("PCR verification completed successfully");
-    Ok(())
-}
-
-fn verify_tpm_nonce(quote: &TpmQuote, report_data: &[u8]) -> Result<()> {
-    let nonce = quote.nonce()?;
-    if nonce != report_data[..] {
-        bail!("TPM quote nonce doesn't match expected report_data");
-    }
-    debug!("TPM report_data verification completed successfully");
-    Ok(())
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::az_vtpm::VtpmError;
     use az_tdx_vtpm::vtpm::Quote;
     use az_tdx_vtpm::vtpm::VerifyError;
 
@@ -144,7 +134,7 @@ mod tests {
     fn test_verify_tpm_signature() {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
         let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
-        verify_tpm_signature(&quote, &hcl_report).unwrap();
+        verify_signature(&quote, &hcl_report).unwrap();
     }
 
     #[test]
@@ -155,7 +145,7 @@ mod tests {
 
         let hcl_report = HclReport::new(REPORT.to_vec()).unwrap();
         assert_eq!(
-            verify_tpm_signature(&wrong_quote, &hcl_report)
+            verify_signature(&wrong_quote, &hcl_report)
                 .unwrap_err()
                 .downcast_ref::<VerifyError>()
                 .unwrap()
@@ -168,20 +158,20 @@ mod tests {
     fn test_verify_tpm_nonce() {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
         let nonce = "challenge".as_bytes();
-        verify_tpm_nonce(&quote, nonce).unwrap();
+        verify_nonce(&quote, nonce).unwrap();
     }
 
     #[test]
     fn test_verify_tpm_nonce_failure() {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
         let wrong_nonce = "wrong".as_bytes();
-        verify_tpm_nonce(&quote, wrong_nonce).unwrap_err();
+        verify_nonce(&quote, wrong_nonce).unwrap_err();
     }
 
     #[test]
     fn test_verify_pcrs() {
         let quote: Quote = bincode::deserialize(QUOTE).unwrap();
-        verify_pcrs(&quote).unwrap();
+        verify_pcrs(&quote, None).unwrap();
     }
 
     #[test]
@@ -190,13 +180,25 @@ mod tests {
         quote[0x0169] = 0;
         let wrong_quote: Quote = bincode::deserialize(&quote).unwrap();
 
+        let Err(VtpmError::Anyhow(err)) = verify_pcrs(&wrong_quote, None) else {
+            panic!("PCR mismatch isn't an untyped error");
+        };
         assert_eq!(
-            verify_pcrs(&wrong_quote)
-                .unwrap_err()
-                .downcast_ref::<VerifyError>()
-                .unwrap()
-                .to_string(),
+            err.downcast_ref::<VerifyError>().unwrap().to_string(),
             VerifyError::PcrMismatch.to_string()
         );
     }
+    #[test]
+    fn test_extend_claim() {
+        let td_quote = parse_tdx_quote(TD_QUOTE).unwrap();
+        let mut claim = generate_parsed_claim(td_quote, None, None).unwrap();
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        extend_claim(&mut claim, &quote, INITDATA_PCR).unwrap();
+
+        assert!(claim["quote"]["body"]["mr_td"].is_string());
+        assert!(claim["quote"]["body"]["rtmr_0"].is_string());
+        assert_eq!(claim["tpm"].as_object().unwrap().len(), 24);
+        assert_eq!(claim["init_data"], claim["tpm"]["pcr08"]);
+        assert_eq!(claim["report_data"], hex::encode("challenge"));
+    }
 }
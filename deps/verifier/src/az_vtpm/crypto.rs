@@ -0,0 +1,155 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The digests and the AKpub signature checks of the vTPM quote, by the
+//! crypto backend selected at build time: OpenSSL by default, or ring with
+//! the `ring` feature. The SNP report and the AMD cert chain are always
+//! verified with OpenSSL.
+
+use anyhow::{Context, Result};
+use az_cvm_vtpm::vtpm::VerifyError;
+
+pub(crate) trait CryptoBackend {
+    fn sha256(data: &[u8]) -> [u8; 32];
+
+    fn sha384(data: &[u8]) -> [u8; 48];
+
+    /// Verify the RSASSA-PKCS1-v1_5 SHA-256 `signature` of `message` by the
+    /// DER encoded SubjectPublicKeyInfo `public_key`. A wrong signature is a
+    /// `VerifyError::SignatureMismatch`.
+    fn verify_rsa_sha256(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()>;
+}
+
+// with ring, only the tests compare it to the ring backend
+#[cfg_attr(feature = "ring", allow(dead_code))]
+pub(crate) struct OpensslBackend;
+
+impl CryptoBackend for OpensslBackend {
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        openssl::sha::sha256(data)
+    }
+
+    fn sha384(data: &[u8]) -> [u8; 48] {
+        openssl::sha::sha384(data)
+    }
+
+    fn verify_rsa_sha256(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+        use openssl::hash::MessageDigest;
+        use openssl::pkey::PKey;
+        use openssl::rsa::Padding;
+        use openssl::sign::Verifier;
+
+        let key = PKey::public_key_from_der(public_key).context("Failed to parse AKpub")?;
+        let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+        verifier.set_rsa_padding(Padding::PKCS1)?;
+        verifier.update(message)?;
+        // a malformed signature is as wrong as a mismatching one
+        if !verifier.verify(signature).unwrap_or(false) {
+            return Err(VerifyError::SignatureMismatch.into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "ring")]
+pub(crate) struct RingBackend;
+
+#[cfg(feature = "ring")]
+impl CryptoBackend for RingBackend {
+    fn sha256(data: &[u8]) -> [u8; 32] {
+        let digest = ring::digest::digest(&ring::digest::SHA256, data);
+        digest
+            .as_ref()
+            .try_into()
+            .expect("SHA-256 digest is 32 bytes")
+    }
+
+    fn sha384(data: &[u8]) -> [u8; 48] {
+        let digest = ring::digest::digest(&ring::digest::SHA384, data);
+        digest
+            .as_ref()
+            .try_into()
+            .expect("SHA-384 digest is 48 bytes")
+    }
+
+    fn verify_rsa_sha256(public_key: &[u8], message: &[u8], signature: &[u8]) -> Result<()> {
+        use ring::signature::{UnparsedPublicKey, RSA_PKCS1_2048_8192_SHA256};
+        use x509_parser::prelude::{FromDer, SubjectPublicKeyInfo};
+
+        // ring takes the RSAPublicKey wrapped in the SubjectPublicKeyInfo
+        let (_, spki) =
+            SubjectPublicKeyInfo::from_der(public_key).context("Failed to parse AKpub")?;
+        UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, &spki.subject_public_key.data)
+            .verify(message, signature)
+            .map_err(|_| VerifyError::SignatureMismatch.into())
+    }
+}
+
+/// The backend of the build
+#[cfg(not(feature = "ring"))]
+pub(crate) type Crypto = OpensslBackend;
+#[cfg(feature = "ring")]
+pub(crate) type Crypto = RingBackend;
+
+#[cfg(test)]
+mod tests {
+    use super::super::vtpm::{ak_pub_der, quote_signature};
+    use super::*;
+    use az_cvm_vtpm::hcl::HclReport;
+    use az_cvm_vtpm::vtpm::Quote;
+
+    const REPORT: &[u8; 2600] = include_bytes!("../../test_data/az-snp-vtpm/hcl-report.bin");
+    const QUOTE: &[u8; 1170] = include_bytes!("../../test_data/az-snp-vtpm/quote.bin");
+
+    /// The results of the checks of the fixture with backend `B`
+    fn check<B: CryptoBackend>() -> (Vec<u8>, Vec<u8>, Vec<Option<String>>) {
+        let quote: Quote = bincode::deserialize(QUOTE).unwrap();
+        let ak_pub = ak_pub_der(&HclReport::new(REPORT.to_vec()).unwrap()).unwrap();
+        let message = quote.message();
+        let signature = quote_signature(&quote).unwrap();
+        let mut tampered = message.clone();
+        tampered[0] ^= 1;
+
+        let verify = |message: &[u8], signature: &[u8], key: &[u8]| {
+            B::verify_rsa_sha256(key, message, signature)
+                .err()
+                .map(|e| match e.downcast_ref::<VerifyError>() {
+                    Some(VerifyError::SignatureMismatch) => "mismatch".to_string(),
+                    _ => "error".to_string(),
+                })
+        };
+        let verdicts = vec![
+            verify(&message, &signature, &ak_pub),
+            verify(&tampered, &signature, &ak_pub),
+            verify(&message, &signature[1..], &ak_pub),
+            verify(&message, &signature, b"not a key"),
+        ];
+        (
+            B::sha256(&message).to_vec(),
+            B::sha384(&message).to_vec(),
+            verdicts,
+        )
+    }
+
+    #[test]
+    fn test_openssl_backend() {
+        let (_, _, verdicts) = check::<OpensslBackend>();
+        assert_eq!(
+            verdicts,
+            [
+                None,
+                Some("mismatch".into()),
+                Some("mismatch".into()),
+                Some("error".into())
+            ]
+        );
+    }
+
+    #[cfg(feature = "ring")]
+    #[test]
+    fn test_backends_agree() {
+        assert_eq!(check::<OpensslBackend>(), check::<RingBackend>());
+    }
+}
@@ -0,0 +1,166 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! The vTPM quote and init_data checks shared by the Azure SNP and TDX vTPM
+//! verifiers.
+
+use self::tpm::PcrBank;
+use crate::{InitDataHash, TeeEvidenceParsedClaim};
+use anyhow::{anyhow, bail, Context, Result};
+use az_cvm_vtpm::vtpm::QuoteError;
+use log::debug;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+pub(crate) mod crypto;
+pub(crate) mod tpm;
+pub(crate) mod vtpm;
+
+/// Default PCR the init_data is bound to
+pub(crate) const INITDATA_PCR: usize = 8;
+
+#[derive(Error, Debug)]
+pub(crate) enum VtpmError {
+    #[error("TPM quote nonce doesn't match expected report_data")]
+    NonceMismatch,
+    #[error("TPM quote nonce is {actual} bytes, expected report_data is {expected} bytes")]
+    NonceLength { expected: usize, actual: usize },
+    #[error("TPM quote nonce doesn't match the report_data segment at offset {offset}")]
+    NonceSegmentMismatch { offset: usize },
+    #[error("Recomputed PCR digest does not match the digest in Quote")]
+    PcrDigestMismatch,
+    #[error("Quote carries {found} {bank} PCRs, but selects {selected}")]
+    PcrSelectionMismatch {
+        bank: &'static str,
+        selected: usize,
+        found: usize,
+    },
+    #[error("Expected init_data digest is different from the content of PCR{0}")]
+    InitDataMismatch(usize),
+    #[error(transparent)]
+    Quote(#[from] QuoteError),
+    #[error(transparent)]
+    Anyhow(#[from] anyhow::Error),
+}
+
+/// How the guest extends its init_data hash into the init_data PCR, which
+/// determines the PCR value binding an expected init_data hash
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitDataExtend {
+    /// A single extend of the hash into a zeroed PCR:
+    /// `H(0x00 * len || init_data_hash)`
+    #[default]
+    Zeroed,
+    /// A single extend of the hash measured as event data, which the TPM
+    /// hashes as with TPM2_PCR_Event: `H(0x00 * len || H(init_data_hash))`
+    Event,
+    /// A single extend of the hash into a PCR holding `initial`, e.g. the
+    /// value left by the firmware: `H(initial || init_data_hash)`
+    Initial(Vec<u8>),
+}
+
+impl InitDataExtend {
+    /// The value of a PCR of `bank` binding `init_data_hash`
+    pub(crate) fn expected_pcr(&self, bank: PcrBank, init_data_hash: &[u8]) -> Result<Vec<u8>> {
+        let measured = match self {
+            Self::Event => transient(bank.digest(init_data_hash)),
+            Self::Zeroed | Self::Initial(_) => transient(init_data_hash.to_vec()),
+        };
+        let initial = match self {
+            Self::Initial(initial) if initial.len() != bank.digest_len() => bail!(
+                "Initial init_data PCR value of {} bytes isn't a {} PCR",
+                initial.len(),
+                bank.name()
+            ),
+            Self::Initial(initial) => initial.clone(),
+            Self::Zeroed | Self::Event => vec![0u8; bank.digest_len()],
+        };
+        let input = transient([initial.as_slice(), measured.as_slice()].concat());
+        Ok(bank.digest(&input))
+    }
+}
+
+pub(crate) fn claim_map(
+    claim: &mut TeeEvidenceParsedClaim,
+) -> Result<&mut serde_json::Map<String, Value>> {
+    let Value::Object(map) = claim else {
+        bail!("failed to extend the claim, not an object");
+    };
+    Ok(map)
+}
+
+/// A transient buffer, scrubbed on drop with the `zeroize` feature
+#[cfg(feature = "zeroize")]
+fn transient<T: zeroize::Zeroize>(value: T) -> zeroize::Zeroizing<T> {
+    zeroize::Zeroizing::new(value)
+}
+
+#[cfg(not(feature = "zeroize"))]
+fn transient<T>(value: T) -> T {
+    value
+}
+
+/// PCR[init_data_pcr] is expected to be a single extend of the init_data
+/// hash following `init_data_extend`, by default into a zeroed PCR:
+/// `H(0x00 * len || init_data_hash)`. The hash algorithm H is chosen from
+/// the length of the init_data hash and has to match the bank of the PCR
+/// value.
+pub(crate) fn verify_init_data(
+    expected: &InitDataHash,
+    pcrs: &BTreeMap<usize, &[u8]>,
+    init_data_pcr: usize,
+    init_data_extend: &InitDataExtend,
+) -> Result<(), VtpmError> {
+    let InitDataHash::Value(expected_init_data_hash) = expected else {
+        debug!("No expected value, skipping init_data verification");
+        return Ok(());
+    };
+
+    debug!("Check the binding of PCR{init_data_pcr}");
+
+    let bank = PcrBank::from_digest_len(expected_init_data_hash.len())
+        .context("Unsupported init_data hash")?;
+    let Some(pcr) = pcrs.get(&init_data_pcr) else {
+        return Err(anyhow!("Quote doesn't select the init_data PCR{init_data_pcr}").into());
+    };
+    if pcr.len() != bank.digest_len() {
+        return Err(anyhow!(
+            "A {} init_data hash can't be bound to PCR{init_data_pcr} of {} bytes",
+            bank.name(),
+            pcr.len()
+        )
+        .into());
+    }
+
+    let digest = transient(init_data_extend.expected_pcr(bank, expected_init_data_hash)?);
+    if digest.as_slice() != *pcr {
+        return Err(VtpmError::InitDataMismatch(init_data_pcr));
+    }
+    Ok(())
+}
+
+/// The SHA-256 PCR value which binds `init_data_hash` with the default
+/// `InitDataExtend::Zeroed`, `sha256(0x00 * 32 || init_data_hash)`, for
+/// guest tooling to precompute the value expected in PCR8
+pub fn expected_init_data_pcr(init_data_hash: &[u8; 32]) -> [u8; 32] {
+    openssl::sha::sha256(&[[0u8; 32], *init_data_hash].concat())
+}
+
+#[cfg(all(test, feature = "zeroize"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_zeroizing() {
+        // the init_data buffers are scrubbed on drop
+        let mut input: zeroize::Zeroizing<Vec<u8>> = transient(vec![0u8; 64]);
+        input[32..].copy_from_slice(&[1; 32]);
+        let digest: zeroize::Zeroizing<Vec<u8>> = transient(PcrBank::Sha256.digest(&input));
+        assert_eq!(digest.len(), 32);
+    }
+}
@@ -0,0 +1,266 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Minimal decoding of the TPMS_ATTEST structure signed in a vTPM quote.
+//!
+//! Only the fields required to cross-check the PCR selection and the PCR
+//! digest are interpreted (TPM 2.0 Library, Part 2, 10.12.8 and 10.12.1).
+
+use super::crypto::{Crypto, CryptoBackend};
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ReadBytesExt};
+use std::io::{Cursor, Read};
+
+const TPM_GENERATED_VALUE: u32 = 0xff54_4347;
+const TPM_ST_ATTEST_QUOTE: u16 = 0x8018;
+const TPM_ALG_SHA256: u16 = 0x000b;
+const TPM_ALG_SHA384: u16 = 0x000c;
+/// clock (u64) || resetCount (u32) || restartCount (u32) || safe (u8)
+const CLOCK_INFO_SIZE: usize = 17;
+
+/// Hash algorithm of a TPM PCR bank
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PcrBank {
+    Sha256,
+    Sha384,
+}
+
+impl PcrBank {
+    fn from_alg_id(alg: u16) -> Result<Self> {
+        match alg {
+            TPM_ALG_SHA256 => Ok(Self::Sha256),
+            TPM_ALG_SHA384 => Ok(Self::Sha384),
+            _ => bail!("Unsupported PCR bank algorithm 0x{alg:04x}"),
+        }
+    }
+
+    /// Infer the hash algorithm from the length of a digest
+    pub fn from_digest_len(len: usize) -> Result<Self> {
+        match len {
+            32 => Ok(Self::Sha256),
+            48 => Ok(Self::Sha384),
+            _ => bail!("Unexpected digest length {len}"),
+        }
+    }
+
+    pub fn digest_len(&self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha384 => 48,
+        }
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Crypto::sha256(data).to_vec(),
+            Self::Sha384 => Crypto::sha384(data).to_vec(),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "sha256",
+            Self::Sha384 => "sha384",
+        }
+    }
+}
+
+/// A single TPMS_PCR_SELECTION: a bank and the PCR indices selected in it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PcrSelection {
+    pub bank: PcrBank,
+    pub indices: Vec<usize>,
+}
+
+/// The TPMS_QUOTE_INFO part of a quote
+#[derive(Clone, Debug)]
+pub struct QuoteInfo {
+    pub selections: Vec<PcrSelection>,
+    pub pcr_digest: Vec<u8>,
+}
+
+/// The PCR values read from one bank, in the order of the quote's selection
+#[derive(Clone, Debug)]
+pub struct PcrBankValues<'a> {
+    pub bank: PcrBank,
+    pub values: Vec<&'a [u8]>,
+}
+
+/// Check the PCR values of every bank selected by the quote and build the
+/// input of the quote's PCR composite digest, a single digest over the
+/// banks in the order of the selection. Errors name the bank which failed.
+pub fn pcr_composite(info: &QuoteInfo, banks: &[PcrBankValues]) -> Result<Vec<u8>> {
+    if info.selections.len() != banks.len() {
+        bail!(
+            "Quote selects {} PCR banks, but {} PCR banks are provided",
+            info.selections.len(),
+            banks.len()
+        );
+    }
+
+    let mut composite = Vec::new();
+    for selection in &info.selections {
+        composite.extend(bank_composite(selection, banks)?);
+    }
+    Ok(composite)
+}
+
+fn bank_composite(selection: &PcrSelection, banks: &[PcrBankValues]) -> Result<Vec<u8>> {
+    let name = selection.bank.name();
+    let bank = banks
+        .iter()
+        .find(|bank| bank.bank == selection.bank)
+        .with_context(|| format!("No PCR values provided for the {name} bank"))?;
+
+    if bank.values.len() != selection.indices.len() {
+        bail!(
+            "{name} bank: quote selects {} PCRs, but {} PCR values are provided",
+            selection.indices.len(),
+            bank.values.len()
+        );
+    }
+
+    let mut composite = Vec::with_capacity(bank.values.len() * selection.bank.digest_len());
+    for (index, value) in selection.indices.iter().zip(&bank.values) {
+        if value.len() != selection.bank.digest_len() {
+            bail!("{name} bank: PCR{index} is {} bytes long", value.len());
+        }
+        composite.extend_from_slice(value);
+    }
+    Ok(composite)
+}
+
+fn read_sized(cursor: &mut Cursor<&[u8]>) -> Result<Vec<u8>> {
+    let size = cursor.read_u16::<BigEndian>()?;
+    let mut buf = vec![0u8; size as usize];
+    cursor.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+fn skip(cursor: &mut Cursor<&[u8]>, len: usize) -> Result<()> {
+    let mut buf = vec![0u8; len];
+    cursor.read_exact(&mut buf)?;
+    Ok(())
+}
+
+/// Parse the TPMS_QUOTE_INFO from the attestation message of a quote
+pub fn parse_quote_info(message: &[u8]) -> Result<QuoteInfo> {
+    let mut cursor = Cursor::new(message);
+
+    let magic = cursor.read_u32::<BigEndian>().context("Truncated quote")?;
+    if magic != TPM_GENERATED_VALUE {
+        bail!("Quote is not TPM generated");
+    }
+    let attest_type = cursor.read_u16::<BigEndian>().context("Truncated quote")?;
+    if attest_type != TPM_ST_ATTEST_QUOTE {
+        bail!("Attestation structure is not a quote");
+    }
+
+    // qualifiedSigner, extraData, clockInfo, firmwareVersion
+    read_sized(&mut cursor).context("Truncated quote")?;
+    read_sized(&mut cursor).context("Truncated quote")?;
+    skip(&mut cursor, CLOCK_INFO_SIZE + 8).context("Truncated quote")?;
+
+    let count = cursor.read_u32::<BigEndian>().context("Truncated quote")?;
+    let mut selections = Vec::new();
+    for _ in 0..count {
+        let bank = PcrBank::from_alg_id(cursor.read_u16::<BigEndian>()?)?;
+        let size = cursor.read_u8()?;
+        let mut select = vec![0u8; size as usize];
+        cursor.read_exact(&mut select).context("Truncated quote")?;
+        let indices = select
+            .iter()
+            .enumerate()
+            .flat_map(|(byte, bits)| {
+                (0..8)
+                    .filter(move |bit| bits & (1u8 << bit) != 0)
+                    .map(move |bit| byte * 8 + bit)
+            })
+            .collect();
+        selections.push(PcrSelection { bank, indices });
+    }
+
+    let pcr_digest = read_sized(&mut cursor).context("Truncated quote")?;
+
+    Ok(QuoteInfo {
+        selections,
+        pcr_digest,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8; 122] = include_bytes!("../../test_data/az-snp-vtpm/tpm-quote.msg");
+
+    #[test]
+    fn test_parse_quote_info() {
+        let info = parse_quote_info(MESSAGE).unwrap();
+        assert_eq!(
+            info.selections,
+            vec![PcrSelection {
+                bank: PcrBank::Sha256,
+                indices: vec![0, 1, 2, 3, 4, 5, 6, 7, 14],
+            }]
+        );
+        assert_eq!(info.pcr_digest.len(), 32);
+    }
+
+    #[test]
+    fn test_pcr_composite() {
+        let sha256_pcrs = [[1u8; 32], [2u8; 32]];
+        let sha384_pcrs = [[3u8; 48], [4u8; 48]];
+        let info = QuoteInfo {
+            selections: vec![
+                PcrSelection {
+                    bank: PcrBank::Sha256,
+                    indices: vec![0, 1],
+                },
+                PcrSelection {
+                    bank: PcrBank::Sha384,
+                    indices: vec![0, 1],
+                },
+            ],
+            pcr_digest: vec![],
+        };
+        let sha256 = PcrBankValues {
+            bank: PcrBank::Sha256,
+            values: sha256_pcrs.iter().map(|pcr| pcr.as_slice()).collect(),
+        };
+        let sha384 = PcrBankValues {
+            bank: PcrBank::Sha384,
+            values: sha384_pcrs.iter().map(|pcr| pcr.as_slice()).collect(),
+        };
+
+        let composite = pcr_composite(&info, &[sha384.clone(), sha256.clone()]).unwrap();
+        assert_eq!(composite.len(), 2 * 32 + 2 * 48);
+        assert_eq!(composite[..32], [1u8; 32]);
+        assert_eq!(composite[64..112], [3u8; 48]);
+
+        let mut wrong_sha384 = sha384.clone();
+        wrong_sha384.values[1] = &sha256_pcrs[1];
+        assert_eq!(
+            pcr_composite(&info, &[sha256.clone(), wrong_sha384])
+                .unwrap_err()
+                .to_string(),
+            "sha384 bank: PCR1 is 32 bytes long"
+        );
+
+        pcr_composite(&info, &[sha256]).unwrap_err();
+    }
+
+    #[test]
+    fn test_parse_quote_info_failure() {
+        parse_quote_info(&MESSAGE[..64]).unwrap_err();
+
+        let mut wrong_message = *MESSAGE;
+        wrong_message[0] = 0;
+        assert_eq!(
+            parse_quote_info(&wrong_message).unwrap_err().to_string(),
+            "Quote is not TPM generated"
+        );
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright (c) Microsoft Corporation.
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+
+//! Checks of the vTPM quote and the HCL report shared by the Azure vTPM
+//! verifiers, which only differ in the hardware report bound to the HCL
+//! variable data.
+
+use super::crypto::{Crypto, CryptoBackend};
+use super::tpm::{parse_quote_info, pcr_composite, PcrBank, PcrBankValues};
+use super::{claim_map, VtpmError};
+use crate::TeeEvidenceParsedClaim;
+use anyhow::{anyhow, Context, Result};
+use az_cvm_vtpm::hcl::HclReport;
+use az_cvm_vtpm::vtpm::Quote;
+use log::debug;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// The SHA-256 PCRs of the quote keyed by their index in the quote's PCR
+/// selection, rather than by their position in the quote
+pub(crate) fn sha256_pcrs_by_index(quote: &Quote) -> Result<BTreeMap<usize, &[u8; 32]>, VtpmError> {
+    pcrs_by_index(quote, PcrBank::Sha256, quote.pcrs_sha256().collect())
+}
+
+/// The SHA-384 PCRs of the quote, `pcrs_sha384`, keyed by their index in
+/// the quote's PCR selection
+pub(crate) fn sha384_pcrs_by_index<'a>(
+    quote: &Quote,
+    pcrs_sha384: &'a [Vec<u8>],
+) -> Result<BTreeMap<usize, &'a [u8]>, VtpmError> {
+    pcrs_by_index(
+        quote,
+        PcrBank::Sha384,
+        pcrs_sha384.iter().map(Vec::as_slice).collect(),
+    )
+}
+
+/// Key the values of the PCRs of `bank` by the indices the quote selects
+/// in that bank, which must be as many as the values
+fn pcrs_by_index<T>(
+    quote: &Quote,
+    bank: PcrBank,
+    pcrs: Vec<T>,
+) -> Result<BTreeMap<usize, T>, VtpmError> {
+    let info = parse_quote_info(&quote.message())?;
+    let indices = info
+        .selections
+        .iter()
+        .find(|selection| selection.bank == bank)
+        .map(|selection| selection.indices.as_slice())
+        .unwrap_or_default();
+    if pcrs.len() != indices.len() {
+        return Err(VtpmError::PcrSelectionMismatch {
+            bank: bank.name(),
+            selected: indices.len(),
+            found: pcrs.len(),
+        });
+    }
+    Ok(indices.iter().copied().zip(pcrs).collect())
+}
+
+pub(crate) fn extend_claim(
+    claim: &mut TeeEvidenceParsedClaim,
+    quote: &Quote,
+    init_data_pcr: usize,
+) -> Result<()> {
+    let pcrs = sha256_pcrs_by_index(quote)?;
+    let map = claim_map(claim)?;
+    let mut tpm_values = serde_json::Map::new();
+    for (i, pcr) in &pcrs {
+        tpm_values.insert(format!("pcr{:02}", i), Value::String(hex::encode(pcr)));
+    }
+    map.insert("tpm".to_string(), Value::Object(tpm_values));
+    let init_data = pcrs
+        .get(&init_data_pcr)
+        .with_context(|| format!("Quote has no init_data PCR{init_data_pcr}"))?;
+    map.insert("init_data".into(), Value::String(hex::encode(init_data)));
+    map.insert(
+        "report_data".into(),
+        Value::String(hex::encode(quote.nonce()?)),
+    );
+    Ok(())
+}
+
+/// Verify that the quote nonce is exactly `report_data`: a shorter or
+/// longer nonce is rejected, and neither side is padded
+pub(crate) fn verify_nonce(quote: &Quote, report_data: &[u8]) -> Result<(), VtpmError> {
+    let nonce = quote.nonce()?;
+    if nonce.len() != report_data.len() {
+        return Err(VtpmError::NonceLength {
+            expected: report_data.len(),
+            actual: nonce.len(),
+        });
+    }
+    if nonce != report_data {
+        return Err(VtpmError::NonceMismatch);
+    }
+    debug!("TPM report_data verification completed successfully");
+    Ok(())
+}
+
+/// Verify that the quote nonce has each of `segments` at its offset,
+/// leaving the other bytes unconstrained
+pub(crate) fn verify_nonce_segments(
+    quote: &Quote,
+    segments: &[(usize, Vec<u8>)],
+) -> Result<(), VtpmError> {
+    if segments.is_empty() {
+        return Err(anyhow!("No report_data segment to check").into());
+    }
+    let nonce = quote.nonce()?;
+    for (offset, expected) in segments {
+        let actual = offset
+            .checked_add(expected.len())
+            .and_then(|end| nonce.get(*offset..end));
+        if actual != Some(expected.as_slice()) {
+            return Err(VtpmError::NonceSegmentMismatch { offset: *offset });
+        }
+    }
+    debug!("TPM report_data segments verification completed successfully");
+    Ok(())
+}
+
+pub(crate) fn ak_pub_der(hcl_report: &HclReport) -> Result<Vec<u8>> {
+    let ak_pub = hcl_report.ak_pub().context("Failed to get AKpub")?;
+    let der = ak_pub.key.try_to_der()?;
+    Ok(der)
+}
+
+pub(crate) fn verify_signature(quote: &Quote, hcl_report: &HclReport) -> Result<()> {
+    verify_quote_signature(quote, &ak_pub_der(hcl_report)?)
+}
+
+/// The signature of `quote`, the first field of its bincode encoding
+pub(crate) fn quote_signature(quote: &Quote) -> Result<Vec<u8>> {
+    Ok(bincode::deserialize(&bincode::serialize(quote)?)?)
+}
+
+pub(crate) fn verify_quote_signature(quote: &Quote, ak_pub_der: &[u8]) -> Result<()> {
+    Crypto::verify_rsa_sha256(ak_pub_der, &quote.message(), &quote_signature(quote)?)
+        .context("vTPM quote is not signed by AKpub")?;
+    debug!("Signature verification completed successfully");
+    Ok(())
+}
+
+/// Verify the PCR digest of the quote. A quote selecting a SHA-384 bank
+/// needs the values of its SHA-384 PCRs, `pcrs_sha384`, which the library
+/// can't check.
+pub(crate) fn verify_pcrs(quote: &Quote, pcrs_sha384: Option<&[Vec<u8>]>) -> Result<(), VtpmError> {
+    verify_pcr_count(quote)?;
+    if pcrs_sha384.is_none() {
+        quote
+            .verify_pcrs()
+            .context("Digest of PCRs does not match digest in Quote")?;
+    }
+    verify_pcr_digest(quote, pcrs_sha384)?;
+    debug!("PCR verification completed successfully");
+    Ok(())
+}
+
+/// Verify that the quote carries a SHA-256 PCR for each index it selects,
+/// so that its PCRs are looked up by index rather than by position
+pub(crate) fn verify_pcr_count(quote: &Quote) -> Result<(), VtpmError> {
+    sha256_pcrs_by_index(quote)?;
+    Ok(())
+}
+
+/// Recompute the PCR composite digest from the reported PCR values and
+/// compare it to the pcrDigest in the signed quote body. The PCR values
+/// must belong to the banks named in the quote's PCR selection.
+pub(crate) fn verify_pcr_digest(
+    quote: &Quote,
+    pcrs_sha384: Option<&[Vec<u8>]>,
+) -> Result<(), VtpmError> {
+    let info = parse_quote_info(&quote.message())?;
+    let selects = |bank| info.selections.iter().any(|s| s.bank == bank);
+    let mut banks = vec![];
+    if selects(PcrBank::Sha256) {
+        banks.push(PcrBankValues {
+            bank: PcrBank::Sha256,
+            values: quote.pcrs_sha256().map(|pcr| pcr.as_slice()).collect(),
+        });
+    }
+    if let Some(pcrs_sha384) = pcrs_sha384 {
+        if !selects(PcrBank::Sha384) {
+            return Err(
+                anyhow!("SHA-384 PCRs are provided, but the quote doesn't select them").into(),
+            );
+        }
+        banks.push(PcrBankValues {
+            bank: PcrBank::Sha384,
+            values: pcrs_sha384.iter().map(Vec::as_slice).collect(),
+        });
+    }
+    let composite = pcr_composite(&info, &banks)?;
+
+    let digest_alg = PcrBank::from_digest_len(info.pcr_digest.len())?;
+    if digest_alg.digest(&composite) != info.pcr_digest {
+        return Err(VtpmError::PcrDigestMismatch);
+    }
+    Ok(())
+}
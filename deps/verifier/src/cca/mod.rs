@@ -82,6 +82,10 @@ fn unwrap_evidence(wrapped_evidence: &[u8]) -> Result<String> {
 
 #[async_trait]
 impl Verifier for CCA {
+    fn evidence_kind(&self) -> &'static str {
+        "cca"
+    }
+
     async fn evaluate(
         &self,
         evidence: &[u8],
@@ -94,7 +98,7 @@ impl Verifier for CCA {
         let config = Config::try_from(Path::new(&config_file))
             .map_err(|e| anyhow!("parsing {config_file}: {e}"))?;
 
-        let ReportData::Value(expected_report_data) = expected_report_data else {
+        let Some(expected_report_data) = expected_report_data.value()? else {
             bail!("CCA verifier must provide report data field!");
         };
 
@@ -64,6 +64,10 @@ pub struct CsvVerifier {}
 
 #[async_trait]
 impl Verifier for CsvVerifier {
+    fn evidence_kind(&self) -> &'static str {
+        "csv"
+    }
+
     async fn evaluate(
         &self,
         evidence: &[u8],
@@ -76,7 +80,7 @@ impl Verifier for CsvVerifier {
 
         let report_raw = restore_attestation_report(tee_evidence.attestation_report)?;
 
-        if let ReportData::Value(expected_report_data) = expected_report_data {
+        if let Some(expected_report_data) = expected_report_data.value()? {
             debug!("Check the binding of REPORT_DATA.");
             let expected_report_data =
                 regularize_data(expected_report_data, 64, "REPORT_DATA", "CSV");
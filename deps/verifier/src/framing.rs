@@ -0,0 +1,144 @@
+//! Self-describing binary framing of TEE evidence for non-HTTP transports.
+//!
+//! A frame has the following layout, all integers are big-endian:
+//!
+//! | offset | size | field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 4    | magic, ASCII `TEEV`                    |
+//! | 4      | 1    | frame version, currently `1`           |
+//! | 5      | 1    | TEE type, see [`tee_to_id`]            |
+//! | 6      | 4    | payload length in bytes                |
+//! | 10     | len  | payload, the evidence for the TEE type |
+//!
+//! The payload is handed verbatim to the verifier of the given TEE type,
+//! so its format is the one documented by each verifier.
+
+use anyhow::*;
+use kbs_types::Tee;
+
+use crate::{to_verifier, InitDataHash, ReportData, TeeEvidenceParsedClaim};
+
+pub const FRAME_MAGIC: &[u8; 4] = b"TEEV";
+pub const FRAME_VERSION: u8 = 1;
+const HEADER_LEN: usize = 10;
+
+/// Stable on-wire identifier of a TEE type
+pub fn tee_to_id(tee: &Tee) -> u8 {
+    match tee {
+        Tee::Sample => 0,
+        Tee::Sev => 1,
+        Tee::Snp => 2,
+        Tee::AzSnpVtpm => 3,
+        Tee::Tdx => 4,
+        Tee::AzTdxVtpm => 5,
+        Tee::Sgx => 6,
+        Tee::Csv => 7,
+        Tee::Cca => 8,
+        Tee::Se => 9,
+    }
+}
+
+fn tee_from_id(id: u8) -> Result<Tee> {
+    let tee = match id {
+        0 => Tee::Sample,
+        1 => Tee::Sev,
+        2 => Tee::Snp,
+        3 => Tee::AzSnpVtpm,
+        4 => Tee::Tdx,
+        5 => Tee::AzTdxVtpm,
+        6 => Tee::Sgx,
+        7 => Tee::Csv,
+        8 => Tee::Cca,
+        9 => Tee::Se,
+        _ => bail!("Unknown TEE type {id} in evidence frame"),
+    };
+    Ok(tee)
+}
+
+/// Wrap `evidence` of the given TEE type into a frame.
+pub fn frame_evidence(tee: &Tee, evidence: &[u8]) -> Result<Vec<u8>> {
+    let len = u32::try_from(evidence.len()).context("Evidence too large to be framed")?;
+    let mut frame = Vec::with_capacity(HEADER_LEN + evidence.len());
+    frame.extend_from_slice(FRAME_MAGIC);
+    frame.push(FRAME_VERSION);
+    frame.push(tee_to_id(tee));
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(evidence);
+    Ok(frame)
+}
+
+/// Decode a frame into its TEE type and evidence payload.
+pub fn parse_framed_evidence(frame: &[u8]) -> Result<(Tee, &[u8])> {
+    if frame.len() < HEADER_LEN {
+        bail!("Evidence frame is shorter than its header");
+    }
+    if &frame[..4] != FRAME_MAGIC {
+        bail!("Bad evidence frame magic");
+    }
+    if frame[4] != FRAME_VERSION {
+        bail!("Unsupported evidence frame version {}", frame[4]);
+    }
+    let tee = tee_from_id(frame[5])?;
+    let len = u32::from_be_bytes(frame[6..HEADER_LEN].try_into()?) as usize;
+    let payload = &frame[HEADER_LEN..];
+    if payload.len() != len {
+        bail!(
+            "Evidence frame length mismatch. Header: {len}, actual: {}",
+            payload.len()
+        );
+    }
+    Ok((tee, payload))
+}
+
+/// Decode a frame and dispatch its payload to the verifier of its TEE type.
+pub async fn evaluate_framed_evidence(
+    frame: &[u8],
+    expected_report_data: &ReportData<'_>,
+    expected_init_data_hash: &InitDataHash<'_>,
+) -> Result<TeeEvidenceParsedClaim> {
+    let (tee, evidence) = parse_framed_evidence(frame)?;
+    let verifier = to_verifier(&tee)?;
+    verifier
+        .evaluate(evidence, expected_report_data, expected_init_data_hash)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_framed_evidence() {
+        let frame = frame_evidence(&Tee::AzSnpVtpm, b"evidence").unwrap();
+        let (tee, evidence) = parse_framed_evidence(&frame).unwrap();
+        assert_eq!(tee_to_id(&tee), tee_to_id(&Tee::AzSnpVtpm));
+        assert_eq!(evidence, b"evidence");
+    }
+
+    #[test]
+    fn test_parse_framed_evidence_failure() {
+        let frame = frame_evidence(&Tee::Sample, b"evidence").unwrap();
+
+        parse_framed_evidence(&frame[..HEADER_LEN - 1]).unwrap_err();
+
+        let mut wrong_frame = frame.clone();
+        wrong_frame[0] = b'X';
+        assert_eq!(
+            parse_framed_evidence(&wrong_frame).unwrap_err().to_string(),
+            "Bad evidence frame magic"
+        );
+
+        let mut wrong_frame = frame.clone();
+        wrong_frame[4] = 2;
+        assert_eq!(
+            parse_framed_evidence(&wrong_frame).unwrap_err().to_string(),
+            "Unsupported evidence frame version 2"
+        );
+
+        let mut wrong_frame = frame.clone();
+        wrong_frame[5] = 0xff;
+        parse_framed_evidence(&wrong_frame).unwrap_err();
+
+        parse_framed_evidence(&frame[..frame.len() - 1]).unwrap_err();
+    }
+}
@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
 
 use anyhow::*;
 use async_trait::async_trait;
@@ -9,12 +10,17 @@ pub mod sample;
 
 pub mod eventlog;
 
+pub mod framing;
+
 #[cfg(feature = "az-snp-vtpm-verifier")]
 pub mod az_snp_vtpm;
 
 #[cfg(feature = "az-tdx-vtpm-verifier")]
 pub mod az_tdx_vtpm;
 
+#[cfg(any(feature = "az-snp-vtpm-verifier", feature = "az-tdx-vtpm-verifier"))]
+pub(crate) mod az_vtpm;
+
 #[cfg(feature = "snp-verifier")]
 pub mod snp;
 
@@ -128,16 +134,92 @@ pub type TeeEvidenceParsedClaim = serde_json::Value;
 
 pub enum ReportData<'a> {
     Value(&'a [u8]),
+    /// Values expected at their offset in the report_data, leaving the
+    /// other bytes unconstrained
+    Segments(Vec<(usize, Vec<u8>)>),
     NotProvided,
 }
 
+impl ReportData<'_> {
+    /// The expected report_data, for the verifiers which only compare it as
+    /// a whole
+    pub fn value(&self) -> Result<Option<&[u8]>> {
+        match self {
+            Self::Value(value) => Ok(Some(value)),
+            Self::Segments(_) => bail!("This verifier doesn't support report_data segments"),
+            Self::NotProvided => Ok(None),
+        }
+    }
+}
+
 pub enum InitDataHash<'a> {
     Value(&'a [u8]),
     NotProvided,
 }
 
+/// Reference values supplied by the caller, e.g. a policy engine, for the
+/// verifier to enforce. An empty list leaves its measurement unconstrained.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExpectedMeasurements {
+    /// Acceptable launch measurements, e.g. MEASUREMENT for SNP
+    pub measurement: Vec<Vec<u8>>,
+    /// Acceptable values of the PCRs, by index
+    pub pcrs: BTreeMap<usize, Vec<Vec<u8>>>,
+    /// Acceptable host_data
+    pub host_data: Vec<Vec<u8>>,
+}
+
+impl ExpectedMeasurements {
+    /// Whether no measurement is constrained
+    pub fn is_empty(&self) -> bool {
+        self.measurement.is_empty()
+            && self.pcrs.values().all(Vec::is_empty)
+            && self.host_data.is_empty()
+    }
+}
+
+/// Class of a failed verification, e.g. for mapping it to a status code
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VerifyFailure {
+    /// The evidence is malformed
+    Deserialization,
+    /// A signature of the evidence or of its endorsements is invalid
+    Signature,
+    /// The evidence isn't bound to the expected report_data
+    ReportData,
+    /// The measurement registers don't match their digest or reference
+    Pcr,
+    /// The evidence isn't bound to the expected init_data hash
+    InitData,
+    /// The evidence has been issued at an unexpected privilege level
+    Vmpl,
+}
+
 #[async_trait]
 pub trait Verifier {
+    /// Name of the evidence format the verifier handles, e.g. `az-snp-vtpm`.
+    ///
+    /// Verifiers which don't name their evidence format are `unknown`.
+    fn evidence_kind(&self) -> &'static str {
+        "unknown"
+    }
+
+    /// Whether `evidence` has the shape of the evidence of this verifier,
+    /// without verifying it. A dispatcher may probe the registered
+    /// verifiers with it when the kind of the evidence is unknown.
+    ///
+    /// Verifiers which can't cheaply tell their evidence apart return false.
+    fn can_handle(&self, _evidence: &[u8]) -> bool {
+        false
+    }
+
+    /// The class of `error`, returned by `evaluate`, if the verifier can
+    /// tell it. Errors of other classes, e.g. of an appraisal policy, are
+    /// `None`.
+    fn classify_error(&self, _error: &Error) -> Option<VerifyFailure> {
+        None
+    }
+
     /// Verify the hardware signature.
     ///
     ///
@@ -175,6 +257,28 @@ pub trait Verifier {
         expected_init_data_hash: &InitDataHash,
     ) -> Result<TeeEvidenceParsedClaim>;
 
+    /// Same as `evaluate`, and enforce the `expected` reference values.
+    ///
+    /// The default implementation enforces none: it rejects any `expected`
+    /// which constrains a measurement, so that the verifiers which don't
+    /// implement it fail closed.
+    async fn evaluate_with_expected(
+        &self,
+        evidence: &[u8],
+        expected_report_data: &ReportData,
+        expected_init_data_hash: &InitDataHash,
+        expected: &ExpectedMeasurements,
+    ) -> Result<TeeEvidenceParsedClaim> {
+        if !expected.is_empty() {
+            bail!(
+                "The {} verifier doesn't enforce reference values",
+                self.evidence_kind()
+            );
+        }
+        self.evaluate(evidence, expected_report_data, expected_init_data_hash)
+            .await
+    }
+
     /// Generate the supplemental challenge
     ///
     /// Some TEE like IBM SE need a `challenge` generated on verifier side
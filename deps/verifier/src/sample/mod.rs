@@ -22,6 +22,10 @@ pub struct Sample {}
 
 #[async_trait]
 impl Verifier for Sample {
+    fn evidence_kind(&self) -> &'static str {
+        "sample"
+    }
+
     async fn evaluate(
         &self,
         evidence: &[u8],
@@ -49,12 +53,12 @@ async fn verify_tee_evidence(
     // Verify the TEE Hardware signature. (Null for sample TEE)
 
     // Emulate the report data.
-    if let ReportData::Value(expected_report_data) = expected_report_data {
+    if let Some(expected_report_data) = expected_report_data.value()? {
         debug!("Check the binding of REPORT_DATA.");
         let ev_report_data = base64::engine::general_purpose::STANDARD
             .decode(&evidence.report_data)
             .context("base64 decode report data for sample evidence")?;
-        if *expected_report_data != ev_report_data {
+        if expected_report_data != ev_report_data {
             bail!("REPORT_DATA is different from that in Sample Quote");
         }
     }
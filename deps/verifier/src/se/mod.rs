@@ -20,6 +20,10 @@ pub struct SeVerifier;
 
 #[async_trait]
 impl Verifier for SeVerifier {
+    fn evidence_kind(&self) -> &'static str {
+        "se"
+    }
+
     async fn evaluate(
         &self,
         evidence: &[u8],
@@ -32,7 +36,7 @@ impl Verifier for SeVerifier {
         if let InitDataHash::Value(_) = expected_init_data_hash {
             warn!("IBM SE verifier does not support verify init data hash, will ignore the input `init_data_hash`.");
         }
-        if let ReportData::Value(_) = expected_report_data {
+        if !matches!(expected_report_data, ReportData::NotProvided) {
             warn!("IBM SE verifier does not support verify report data hash, will ignore the input `report_data`.");
         }
         se_verifier.evaluate(evidence)
@@ -32,6 +32,10 @@ pub struct SgxVerifier {}
 
 #[async_trait]
 impl Verifier for SgxVerifier {
+    fn evidence_kind(&self) -> &'static str {
+        "sgx"
+    }
+
     async fn evaluate(
         &self,
         evidence: &[u8],
@@ -72,7 +76,7 @@ async fn verify_evidence(
         .context("Evidence's identity verification error.")?;
 
     let quote = parse_sgx_quote(&quote_bin)?;
-    if let ReportData::Value(expected_report_data) = expected_report_data {
+    if let Some(expected_report_data) = expected_report_data.value()? {
         debug!("Check the binding of REPORT_DATA.");
         let expected_report_data = regularize_data(expected_report_data, 64, "REPORT_DATA", "SGX");
         if expected_report_data != quote.report_body.report_data {
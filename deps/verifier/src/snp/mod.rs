@@ -39,11 +39,12 @@ impl SnpEvidence {
     }
 }
 
+const PRODUCT_NAME_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .2);
 const HW_ID_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .4);
-const UCODE_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .8);
-const SNP_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .3);
-const TEE_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .2);
-const LOADER_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .1);
+pub(crate) const UCODE_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .8);
+pub(crate) const SNP_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .3);
+pub(crate) const TEE_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .2);
+pub(crate) const LOADER_SPL_OID: Oid<'static> = oid!(1.3.6 .1 .4 .1 .3704 .1 .3 .1);
 
 // KDS URL parameters
 const KDS_CERT_SITE: &str = "https://kdsintf.amd.com";
@@ -64,17 +65,8 @@ pub struct Snp {
 pub(crate) fn load_milan_cert_chain() -> &'static Result<VendorCertificates> {
     static MILAN_CERT_CHAIN: OnceLock<Result<VendorCertificates>> = OnceLock::new();
     MILAN_CERT_CHAIN.get_or_init(|| {
-        let certs = X509::stack_from_pem(include_bytes!("milan_ask_ark_asvk.pem"))?;
-        if certs.len() != 3 {
-            bail!("Malformed Milan ASK/ARK/ASVK");
-        }
-
-        let vendor_certs = VendorCertificates {
-            ask: certs[0].clone(),
-            ark: certs[1].clone(),
-            asvk: certs[2].clone(),
-        };
-        Ok(vendor_certs)
+        VendorCertificates::from_pem(include_bytes!("milan_ask_ark_asvk.pem"))
+            .context("Malformed Milan ASK/ARK/ASVK")
     })
 }
 
@@ -90,15 +82,55 @@ impl Snp {
     }
 }
 
+/// The AMD cert chain of a SEV-SNP CPU generation
 #[derive(Clone, Debug)]
-pub(crate) struct VendorCertificates {
-    ask: X509,
-    ark: X509,
-    asvk: X509,
+pub struct VendorCertificates {
+    pub(crate) ask: X509,
+    pub(crate) ark: X509,
+    pub(crate) asvk: X509,
+}
+
+impl VendorCertificates {
+    /// A chain of the `ask` and `asvk` issued by `ark`. The ARK must be
+    /// self-signed and the ASK signed by the ARK.
+    pub fn new(ask: X509, ark: X509, asvk: X509) -> Result<Self> {
+        verify_signature(&ark, &ark, "ARK")?;
+        verify_signature(&ask, &ark, "ASK")?;
+        Ok(Self { ask, ark, asvk })
+    }
+
+    /// Parse a PEM bundle of exactly three certificates, ASK, ARK and ASVK
+    pub fn from_pem(pem: &[u8]) -> Result<Self> {
+        let certs = X509::stack_from_pem(pem)?;
+        let [ask, ark, asvk] = <[X509; 3]>::try_from(certs).map_err(|certs| {
+            anyhow!(
+                "Expected ASK, ARK and ASVK, got {} certificates",
+                certs.len()
+            )
+        })?;
+        Self::new(ask, ark, asvk)
+    }
+
+    /// SHA-256 over the DER of the ARK, ASK and ASVK, identifying the chain
+    pub(crate) fn fingerprint(&self) -> Result<[u8; 32]> {
+        let mut hasher = openssl::sha::Sha256::new();
+        for cert in [&self.ark, &self.ask, &self.asvk] {
+            hasher.update(&cert.to_der()?);
+        }
+        Ok(hasher.finish())
+    }
 }
 
 #[async_trait]
 impl Verifier for Snp {
+    fn evidence_kind(&self) -> &'static str {
+        "snp"
+    }
+
+    fn can_handle(&self, evidence: &[u8]) -> bool {
+        serde_json::from_slice::<SnpEvidence>(evidence).is_ok()
+    }
+
     /// Evaluates the provided evidence against the expected report data and initialize data hash.
     /// Validates the report signature, version, VMPL, and other fields.
     /// Returns parsed claims if the verification is successful.
@@ -120,18 +152,13 @@ impl Verifier for Snp {
 
         verify_report_signature(&report, &cert_chain, &self.vendor_certs)?;
 
-        // See Trustee Issue#589 https://github.com/confidential-containers/trustee/issues/589
-        if report.version < REPORT_VERSION_MIN || report.version > REPORT_VERSION_MAX {
-            return Err(anyhow!(
-                "Unexpected attestation report version. Check SNP Firmware ABI specification"
-            ));
-        }
+        verify_report_version(&report)?;
 
         if report.vmpl != 0 {
             return Err(anyhow!("VMPL Check Failed"));
         }
 
-        if let ReportData::Value(expected_report_data) = expected_report_data {
+        if let Some(expected_report_data) = expected_report_data.value()? {
             debug!("Check the binding of REPORT_DATA.");
             let expected_report_data =
                 regularize_data(expected_report_data, 64, "REPORT_DATA", "SNP");
@@ -188,7 +215,7 @@ fn get_oid_octets<const N: usize>(
 }
 
 /// Retrieves an integer value for a given OID from a certificate's extensions.
-fn get_oid_int(cert: &x509_parser::certificate::TbsCertificate, oid: Oid) -> Result<u8> {
+pub(crate) fn get_oid_int(cert: &x509_parser::certificate::TbsCertificate, oid: Oid) -> Result<u8> {
     let val = cert
         .get_extension_unique(&oid)?
         .ok_or_else(|| anyhow!("Oid not found"))?
@@ -198,6 +225,51 @@ fn get_oid_int(cert: &x509_parser::certificate::TbsCertificate, oid: Oid) -> Res
     val_int.as_u8().context("Unexpected data size")
 }
 
+/// Verifies that the extensions of a VCEK/VLEK allow it to sign reports.
+/// AMD issues them without basicConstraints, keyUsage and EKU, but with the
+/// AMD product name extension. Certificates carrying any of the former must
+/// not be a CA, must allow digitalSignature and must not be restricted to
+/// other purposes.
+fn verify_endorsement_key_extensions(
+    cert: &x509_parser::certificate::TbsCertificate,
+) -> Result<()> {
+    if let Some(basic_constraints) = cert.basic_constraints()? {
+        if basic_constraints.value.ca {
+            bail!("Endorsement key is a CA certificate");
+        }
+    }
+
+    if let Some(key_usage) = cert.key_usage()? {
+        let key_usage = key_usage.value;
+        if !key_usage.digital_signature() || key_usage.key_cert_sign() || key_usage.crl_sign() {
+            bail!("Endorsement key usage doesn't allow signing reports");
+        }
+    }
+
+    if let Some(extended_key_usage) = cert.extended_key_usage()? {
+        if !extended_key_usage.value.any {
+            bail!("Endorsement key is restricted to other extended key usages");
+        }
+    }
+
+    cert.get_extension_unique(&PRODUCT_NAME_OID)?
+        .context("Endorsement key has no AMD product name")?;
+    Ok(())
+}
+
+/// Verifies that the attestation report version is supported, naming the
+/// reported and the supported versions otherwise.
+pub(crate) fn verify_report_version(report: &AttestationReport) -> Result<()> {
+    // See Trustee Issue#589 https://github.com/confidential-containers/trustee/issues/589
+    if !(REPORT_VERSION_MIN..=REPORT_VERSION_MAX).contains(&report.version) {
+        bail!(
+            "Unexpected attestation report version {}, expected {REPORT_VERSION_MIN} to {REPORT_VERSION_MAX}. Check SNP Firmware ABI specification",
+            report.version
+        );
+    }
+    Ok(())
+}
+
 /// Verifies the signature of the attestation report using the provided certificate chain and vendor certificates.
 pub(crate) fn verify_report_signature(
     report: &AttestationReport,
@@ -210,16 +282,25 @@ pub(crate) fn verify_report_signature(
     // verify VCEK or VLEK cert chain
     // the key can be either VCEK or VLEK
     let endorsement_key = verify_cert_chain(cert_chain, ask, ark, asvk)?;
+    verify_report_signed_by(report, &endorsement_key)
+}
 
+/// Verifies that the attestation report is signed by the VCEK or VLEK
+/// `endorsement_key` and matches its extensions, without verifying its chain.
+pub(crate) fn verify_report_signed_by(
+    report: &AttestationReport,
+    endorsement_key: &X509,
+) -> Result<()> {
     // OpenSSL bindings do not expose custom extensions
     // Parse the key using x509_parser
     let endorsement_key_der = &endorsement_key.to_der()?;
     let parsed_endorsement_key = X509Certificate::from_der(endorsement_key_der)?
         .1
         .tbs_certificate;
+    verify_endorsement_key_extensions(&parsed_endorsement_key)?;
 
     let common_name =
-        get_common_name(&endorsement_key).context("No common name found in certificate")?;
+        get_common_name(endorsement_key).context("No common name found in certificate")?;
 
     // if the common name is "VCEK", then the key is a VCEK
     // so lets check the chip id
@@ -231,20 +312,36 @@ pub(crate) fn verify_report_signature(
 
     // tcb version
     // these integer extensions are 3 bytes with the last byte as the data
-    if get_oid_int(&parsed_endorsement_key, UCODE_SPL_OID)? != report.reported_tcb.microcode {
-        return Err(anyhow!("Microcode version mismatch"));
+    let vcek_spl = get_oid_int(&parsed_endorsement_key, UCODE_SPL_OID)?;
+    if vcek_spl != report.reported_tcb.microcode {
+        bail!(
+            "Microcode version mismatch: endorsement key has {vcek_spl}, report has {}",
+            report.reported_tcb.microcode
+        );
     }
 
-    if get_oid_int(&parsed_endorsement_key, SNP_SPL_OID)? != report.reported_tcb.snp {
-        return Err(anyhow!("SNP version mismatch"));
+    let vcek_spl = get_oid_int(&parsed_endorsement_key, SNP_SPL_OID)?;
+    if vcek_spl != report.reported_tcb.snp {
+        bail!(
+            "SNP version mismatch: endorsement key has {vcek_spl}, report has {}",
+            report.reported_tcb.snp
+        );
     }
 
-    if get_oid_int(&parsed_endorsement_key, TEE_SPL_OID)? != report.reported_tcb.tee {
-        return Err(anyhow!("TEE version mismatch"));
+    let vcek_spl = get_oid_int(&parsed_endorsement_key, TEE_SPL_OID)?;
+    if vcek_spl != report.reported_tcb.tee {
+        bail!(
+            "TEE version mismatch: endorsement key has {vcek_spl}, report has {}",
+            report.reported_tcb.tee
+        );
     }
 
-    if get_oid_int(&parsed_endorsement_key, LOADER_SPL_OID)? != report.reported_tcb.bootloader {
-        return Err(anyhow!("Boot loader version mismatch"));
+    let vcek_spl = get_oid_int(&parsed_endorsement_key, LOADER_SPL_OID)?;
+    if vcek_spl != report.reported_tcb.bootloader {
+        bail!(
+            "Boot loader version mismatch: endorsement key has {vcek_spl}, report has {}",
+            report.reported_tcb.bootloader
+        );
     }
 
     // verify report signature
@@ -320,6 +417,8 @@ pub(crate) fn parse_tee_evidence(report: &AttestationReport) -> TeeEvidenceParse
         "policy_single_socket": format!("{}", report.policy.single_socket_required()),
 
         // versioning info
+        "report_version": format!("{}", report.version),
+        "signature_algo": format!("{}", report.sig_algo),
         "reported_tcb_bootloader": format!("{}", report.reported_tcb.bootloader),
         "reported_tcb_tee": format!("{}", report.reported_tcb.tee),
         "reported_tcb_snp": format!("{}", report.reported_tcb.snp),
@@ -330,14 +429,30 @@ pub(crate) fn parse_tee_evidence(report: &AttestationReport) -> TeeEvidenceParse
         "platform_smt_enabled": format!("{}", report.plat_info.smt_enabled()),
 
         // measurements
-        "measurement": format!("{}", STANDARD.encode(report.measurement)),
+        // the MEASUREMENT field, the launch digest of the guest, hex encoded
+        "measurement": hex::encode(report.measurement),
         "report_data": format!("{}", STANDARD.encode(report.report_data)),
         "init_data": format!("{}", STANDARD.encode(report.host_data)),
+
+        // launch binding, hex encoded
+        "host_data": hex::encode(report.host_data),
+        "family_id": hex::encode(report.family_id),
+        "image_id": hex::encode(report.image_id),
     });
 
     claims_map as TeeEvidenceParsedClaim
 }
 
+/// The cert table entry type of a versioned endorsement key: a VLEK has
+/// the `SEV-VLEK` subject CN, any other key is taken as a VCEK
+pub(crate) fn endorsement_key_type(cert: &X509) -> CertType {
+    if get_common_name(cert).is_ok_and(|cn| cn == "SEV-VLEK") {
+        CertType::VLEK
+    } else {
+        CertType::VCEK
+    }
+}
+
 /// Extracts the common name (CN) from the subject name of a certificate.
 fn get_common_name(cert: &x509::X509) -> Result<String> {
     let mut entries = cert.subject_name().entries_by_nid(Nid::COMMONNAME);
@@ -471,6 +586,69 @@ mod tests {
         check_oid_ints(&parsed_vcek);
     }
 
+    fn self_signed_cert(extension: openssl::x509::X509Extension) -> Vec<u8> {
+        use openssl::asn1::Asn1Time;
+        use openssl::ec::{EcGroup, EcKey};
+        use openssl::hash::MessageDigest;
+        use openssl::nid::Nid;
+        use openssl::x509::X509Builder;
+
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut builder = X509Builder::new().unwrap();
+        builder.set_version(2).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.append_extension(extension).unwrap();
+        builder.sign(&key, MessageDigest::sha384()).unwrap();
+        builder.build().to_der().unwrap()
+    }
+
+    #[test]
+    fn check_endorsement_key_extensions() {
+        for der in [&VCEK[..], &VLEK[..]] {
+            let cert = X509Certificate::from_der(der).unwrap().1.tbs_certificate;
+            verify_endorsement_key_extensions(&cert).unwrap();
+        }
+    }
+
+    #[test]
+    fn check_endorsement_key_extensions_failure() {
+        use openssl::x509::extension::{BasicConstraints, KeyUsage};
+
+        let der = self_signed_cert(KeyUsage::new().key_encipherment().build().unwrap());
+        let cert = X509Certificate::from_der(&der).unwrap().1.tbs_certificate;
+        assert_eq!(
+            verify_endorsement_key_extensions(&cert)
+                .unwrap_err()
+                .to_string(),
+            "Endorsement key usage doesn't allow signing reports"
+        );
+
+        let der = self_signed_cert(BasicConstraints::new().ca().build().unwrap());
+        let cert = X509Certificate::from_der(&der).unwrap().1.tbs_certificate;
+        assert_eq!(
+            verify_endorsement_key_extensions(&cert)
+                .unwrap_err()
+                .to_string(),
+            "Endorsement key is a CA certificate"
+        );
+
+        let der = self_signed_cert(KeyUsage::new().digital_signature().build().unwrap());
+        let cert = X509Certificate::from_der(&der).unwrap().1.tbs_certificate;
+        assert_eq!(
+            verify_endorsement_key_extensions(&cert)
+                .unwrap_err()
+                .to_string(),
+            "Endorsement key has no AMD product name"
+        );
+    }
+
     #[test]
     fn check_vcek_signature_verification() {
         let cert_table = vec![CertTableEntry::new(CertType::VCEK, VCEK.to_vec())];
@@ -538,6 +716,14 @@ mod tests {
         verify_report_signature(&attestation_report, &cert_chain, vendor_certs).unwrap();
     }
 
+    #[test]
+    fn check_endorsement_key_type() {
+        let vcek = X509::from_der(VCEK).unwrap();
+        assert_eq!(endorsement_key_type(&vcek), CertType::VCEK);
+        let vlek = X509::from_der(VLEK).unwrap();
+        assert_eq!(endorsement_key_type(&vlek), CertType::VLEK);
+    }
+
     #[test]
     fn check_report_signature_failure() {
         let mut bytes = VCEK_REPORT.clone();
@@ -551,6 +737,90 @@ mod tests {
         verify_report_signature(&attestation_report, &cert_chain, vendor_certs).unwrap_err();
     }
 
+    fn snp_evidence(report: &[u8], cert: CertTableEntry) -> Vec<u8> {
+        let attestation_report = bincode::deserialize::<AttestationReport>(report).unwrap();
+        serde_json::to_vec(&SnpEvidence::new(attestation_report, Some(vec![cert]))).unwrap()
+    }
+
+    #[tokio::test]
+    async fn check_evaluate_bare_report() {
+        let evidence = snp_evidence(
+            VCEK_REPORT,
+            CertTableEntry::new(CertType::VCEK, VCEK.to_vec()),
+        );
+        let report_data = &VCEK_REPORT[0x50..0x70];
+        let verifier = Snp::new().unwrap();
+        assert!(verifier.can_handle(&evidence));
+        assert!(!verifier.can_handle(VCEK_REPORT));
+
+        let claim = verifier
+            .evaluate(
+                &evidence,
+                &ReportData::Value(report_data),
+                &InitDataHash::NotProvided,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            claim["report_data"],
+            STANDARD.encode(&VCEK_REPORT[0x50..0x90])
+        );
+        assert_eq!(
+            claim["measurement"],
+            "a1f3930413247bb38cfc171579ea3c12d5fe4901f0c792f6\
+             3fd75d98f1ef827c23500644e0e692e6be917f9050d3d38c"
+        );
+
+        verifier
+            .evaluate(
+                &evidence,
+                &ReportData::Value(b"another nonce"),
+                &InitDataHash::NotProvided,
+            )
+            .await
+            .unwrap_err();
+
+        // the VLEK doesn't endorse the VCEK report
+        let evidence = snp_evidence(
+            VCEK_REPORT,
+            CertTableEntry::new(CertType::VLEK, VLEK.to_vec()),
+        );
+        verifier
+            .evaluate(
+                &evidence,
+                &ReportData::Value(report_data),
+                &InitDataHash::NotProvided,
+            )
+            .await
+            .unwrap_err();
+    }
+
+    #[test]
+    fn check_launch_binding_claims() {
+        let report = bincode::deserialize::<AttestationReport>(VCEK_REPORT.as_slice()).unwrap();
+        let claim = parse_tee_evidence(&report);
+        assert_eq!(claim["host_data"], hex::encode(&VCEK_REPORT[0xc0..0xe0]));
+        assert_eq!(claim["family_id"], "01000000000000000000000000000000");
+        assert_eq!(claim["image_id"], "02000000000000000000000000000000");
+    }
+
+    #[test]
+    fn check_report_version() {
+        let mut report = bincode::deserialize::<AttestationReport>(VCEK_REPORT.as_slice()).unwrap();
+        let claim = parse_tee_evidence(&report);
+        assert_eq!(claim["report_version"], "2");
+        // ECDSA P-384 with SHA-384
+        assert_eq!(claim["signature_algo"], "1");
+        verify_report_version(&report).unwrap();
+
+        report.version = 5;
+        assert_eq!(
+            verify_report_version(&report).unwrap_err().to_string(),
+            "Unexpected attestation report version 5, expected 2 to 3. \
+             Check SNP Firmware ABI specification"
+        );
+    }
+
     #[test]
     fn check_vlek_report_signature_failure() {
         let mut bytes = VLEK_REPORT.clone();
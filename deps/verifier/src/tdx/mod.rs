@@ -33,6 +33,10 @@ pub struct Tdx {}
 
 #[async_trait]
 impl Verifier for Tdx {
+    fn evidence_kind(&self) -> &'static str {
+        "tdx"
+    }
+
     async fn evaluate(
         &self,
         evidence: &[u8],
@@ -68,7 +72,7 @@ async fn verify_evidence(
 
     debug!("{quote}");
 
-    if let ReportData::Value(expected_report_data) = expected_report_data {
+    if let Some(expected_report_data) = expected_report_data.value()? {
         debug!("Check the binding of REPORT_DATA.");
         let expected_report_data = regularize_data(expected_report_data, 64, "REPORT_DATA", "TDX");
         if expected_report_data != quote.report_data() {